@@ -1,69 +1,188 @@
-use tauri::Emitter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
 
 use crate::compression::{
+    AnalysisOptions,
+    AnalysisSummary,
     CompressionConfig,
+    CompressionError,
     CompressResult,
+    DecodeLimits,
+    ExecutionMode,
     ImageInfo,
+    LargerOutputPolicy,
     ProgressUpdate,
+    RawConfig,
+    SvgConfig,
+    ReportFormat,
+    ResizeFilter,
+    ThumbnailCache,
+    DEFAULT_MAX_CACHE_BYTES,
     analyze_images as analyze_images_internal,
     compress_images as compress_images_internal,
+    get_unique_filename,
+    write_report,
 };
 
-/// Analyze images without compressing them (with progress events)
+/// Cancellation flag shared between the running analysis and `cancel_analysis`
+#[derive(Default)]
+pub struct AnalysisState {
+    cancel: Arc<AtomicBool>,
+}
+
+/// Most recent finished compression job, kept for report export
+#[derive(Default)]
+pub struct JobState {
+    last_job: Mutex<Option<FinishedJob>>,
+}
+
+struct FinishedJob {
+    output_folder: String,
+    result: CompressResult,
+}
+
+/// Analyze images without compressing them.
+///
+/// Results are streamed to the frontend as `analysis:batch` events while the analysis runs;
+/// the command itself only returns the totals.
 #[tauri::command]
 pub async fn analyze_images(
     app: tauri::AppHandle,
+    state: tauri::State<'_, AnalysisState>,
     paths: Vec<String>,
     quality: Option<f32>,
     size_ratio: Option<f32>,
     generate_thumbnails: Option<bool>,
-) -> Result<Vec<ImageInfo>, String> {
+    deep_validation: Option<bool>,
+    accept_extensionless: Option<bool>,
+) -> Result<AnalysisSummary, CompressionError> {
     // Use default values if not provided
     let quality = quality.unwrap_or(85.0);
     let size_ratio = size_ratio.unwrap_or(0.8);
     let generate_thumbnails = generate_thumbnails.unwrap_or(true);
+    let deep_validation = deep_validation.unwrap_or(false);
+    let accept_extensionless = accept_extensionless.unwrap_or(false);
 
     // Validate parameters
     if quality < 0.0 || quality > 100.0 {
-        return Err(format!("Quality must be between 0 and 100, got {}", quality));
+        return Err(CompressionError::out_of_range("quality", quality, 0.0, 100.0));
     }
 
     if size_ratio < 0.0 || size_ratio > 1.0 {
-        return Err(format!("Size ratio must be between 0 and 1, got {}", size_ratio));
+        return Err(CompressionError::out_of_range("size_ratio", size_ratio, 0.0, 1.0));
     }
 
     if paths.is_empty() {
-        return Err("No paths provided for analysis".to_string());
+        return Err(CompressionError::NoPaths);
     }
 
+    let options = AnalysisOptions {
+        quality,
+        size_ratio,
+        generate_thumbnails,
+        deep_validation,
+        accept_extensionless,
+        limits: DecodeLimits::default(),
+        raw: RawConfig::default(),
+    };
+
     // Clone app handle for use in closure
     let app_handle = app.clone();
 
+    // A new analysis starts out uncancelled
+    let cancel = state.cancel.clone();
+    cancel.store(false, Ordering::Relaxed);
+
+    // Reuse thumbnails of unchanged files from previous runs
+    let thumbnail_cache = thumbnail_cache(&app);
+
     // Run analysis in a blocking task with progress reporting
-    let result = tokio::task::spawn_blocking(move || {
-        analyze_images_internal(&paths, quality, size_ratio, generate_thumbnails, |current, total| {
-            // Emit progress event for every image
-            let percent = (current as f32 / total as f32 * 100.0) as u32;
-            let progress = AnalysisProgress {
-                current,
-                total,
-                percent,
-            };
-            let _ = app_handle.emit("analysis:progress", &progress);
-        })
+    let summary = tokio::task::spawn_blocking(move || {
+        let batch_handle = app_handle.clone();
+
+        let summary = analyze_images_internal(
+            &paths,
+            &options,
+            thumbnail_cache.as_ref(),
+            &cancel,
+            |current, total| {
+                // Emit progress event for every image
+                let percent = (current as f32 / total as f32 * 100.0) as u32;
+                let progress = AnalysisProgress {
+                    current,
+                    total,
+                    percent,
+                };
+                let _ = app_handle.emit("analysis:progress", &progress);
+            },
+            |images| {
+                // Hand finished images to the frontend right away
+                if let Err(e) = batch_handle.emit("analysis:batch", &AnalysisBatch { images }) {
+                    log::error!("Failed to emit analysis batch: {}", e);
+                }
+            },
+        );
+
+        // Keep the cache within its size budget
+        if let Some(cache) = &thumbnail_cache {
+            if let Err(e) = cache.evict() {
+                log::warn!("Thumbnail cache eviction failed: {}", e);
+            }
+        }
+
+        summary
     })
     .await
-    .map_err(|e| format!("Analysis task failed: {}", e))?;
+    .map_err(|e| CompressionError::internal(format!("Analysis task failed: {}", e)))?;
+
+    // Emit completion event
+    let _ = app.emit("analysis:complete", &summary);
 
-    if result.is_empty() {
-        Err("No valid images found in the provided paths".to_string())
+    if summary.cancelled {
+        log::info!("Analysis cancelled after {} of {} images", summary.analyzed, summary.total);
+    } else if summary.failed > 0 {
+        log::warn!("{} of {} images could not be analyzed", summary.failed, summary.total);
+    }
+
+    // Unreadable files are reported in the summary, so only fail if there was nothing to analyze
+    if summary.total == 0 {
+        Err(CompressionError::NoImagesFound)
     } else {
-        // Emit completion event
-        let _ = app.emit("analysis:complete", result.len());
-        Ok(result)
+        Ok(summary)
     }
 }
 
+/// Cancel the running analysis; images analyzed so far are kept
+#[tauri::command]
+pub fn cancel_analysis(state: tauri::State<'_, AnalysisState>) {
+    log::info!("Analysis cancellation requested");
+    state.cancel.store(true, Ordering::Relaxed);
+}
+
+/// Clear the persistent thumbnail cache, returning the number of bytes freed
+#[tauri::command]
+pub async fn clear_thumbnail_cache(app: tauri::AppHandle) -> Result<u64, CompressionError> {
+    let cache = thumbnail_cache(&app)
+        .ok_or_else(|| CompressionError::internal("Could not find the app cache directory"))?;
+
+    let freed = tokio::task::spawn_blocking(move || cache.clear())
+        .await
+        .map_err(|e| CompressionError::internal(format!("Cache clearing task failed: {}", e)))??;
+
+    log::info!("Cleared thumbnail cache ({} bytes)", freed);
+    Ok(freed)
+}
+
+/// Thumbnail cache in the app cache directory (None if the directory cannot be resolved)
+fn thumbnail_cache(app: &tauri::AppHandle) -> Option<ThumbnailCache> {
+    app.path()
+        .app_cache_dir()
+        .ok()
+        .map(|dir| ThumbnailCache::new(dir.join("thumbnails"), DEFAULT_MAX_CACHE_BYTES))
+}
+
 /// Analysis progress event payload
 #[derive(serde::Serialize, Clone)]
 pub struct AnalysisProgress {
@@ -72,14 +191,22 @@ pub struct AnalysisProgress {
     pub percent: u32,
 }
 
+/// Analysis batch event payload
+#[derive(serde::Serialize, Clone)]
+pub struct AnalysisBatch {
+    pub images: Vec<ImageInfo>,
+}
+
 /// Main compression function that emits progress events
 #[tauri::command]
 pub async fn compress_images(
     app: tauri::AppHandle,
+    state: tauri::State<'_, JobState>,
     config: CompressionConfig,
-) -> Result<CompressResult, String> {
+) -> Result<CompressResult, CompressionError> {
     // Validate configuration
     config.validate()?;
+    let output_folder = config.output_folder.clone();
 
     // Clone app handle for use in closure
     let app_handle = app.clone();
@@ -94,13 +221,19 @@ pub async fn compress_images(
         })
     })
     .await
-    .map_err(|e| format!("Compression task failed: {}", e))?;
+    .map_err(|e| CompressionError::internal(format!("Compression task failed: {}", e)))?;
 
     // Emit completion event
     match &result {
         Ok(compress_result) => {
+            // Remember the job so a report can be exported later
+            *state.last_job.lock().unwrap_or_else(|e| e.into_inner()) = Some(FinishedJob {
+                output_folder,
+                result: compress_result.clone(),
+            });
+
             app.emit("compression:complete", compress_result)
-                .map_err(|e| format!("Failed to emit completion event: {}", e))?;
+                .map_err(|e| CompressionError::internal(format!("Failed to emit completion event: {}", e)))?;
 
             log::info!(
                 "Compression completed: {}/{} successful, {} bytes saved in {}ms",
@@ -118,29 +251,78 @@ pub async fn compress_images(
     result
 }
 
+/// Write a report of the last finished job and return its path.
+///
+/// Without a `path` the report is written into the job's output folder as
+/// `compression-report-<timestamp>.<ext>`.
+#[tauri::command]
+pub async fn export_report(
+    state: tauri::State<'_, JobState>,
+    format: ReportFormat,
+    path: Option<String>,
+) -> Result<String, CompressionError> {
+    let (output_folder, result) = state.last_job
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|job| (job.output_folder.clone(), job.result.clone()))
+        .ok_or(CompressionError::NoFinishedJob)?;
+
+    let report_path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let filename = format!(
+                "compression-report-{}.{}",
+                chrono::Local::now().format("%Y%m%d-%H%M%S"),
+                format.extension()
+            );
+            get_unique_filename(Path::new(&output_folder).join(filename))
+        }
+    };
+
+    let written = report_path.clone();
+    tokio::task::spawn_blocking(move || write_report(&result, format, &written))
+        .await
+        .map_err(|e| CompressionError::internal(format!("Report task failed: {}", e)))??;
+
+    log::info!("Wrote {:?} report to {}", format, report_path.display());
+    Ok(report_path.display().to_string())
+}
+
 /// Estimate compression savings for a set of images
 #[tauri::command]
 pub async fn estimate_savings(
-    app: tauri::AppHandle,
     paths: Vec<String>,
     quality: f32,
     size_ratio: f32,
-) -> Result<SavingsEstimate, String> {
-    // Analyze images to get current sizes and estimated sizes (without thumbnails for speed)
-    let images = analyze_images(app, paths, Some(quality), Some(size_ratio), Some(false)).await?;
-
-    if images.is_empty() {
-        return Ok(SavingsEstimate {
-            total_original: 0,
-            total_estimated: 0,
-            estimated_savings: 0,
-            savings_percentage: 0.0,
-            file_count: 0,
-        });
-    }
+) -> Result<SavingsEstimate, CompressionError> {
+    // Analyze images to get current sizes and estimated sizes (headers only, no thumbnails).
+    // Runs without events so the frontend's image list is left alone.
+    let options = AnalysisOptions {
+        quality,
+        size_ratio,
+        generate_thumbnails: false,
+        deep_validation: false,
+        accept_extensionless: false,
+        limits: DecodeLimits::default(),
+        raw: RawConfig::default(),
+    };
+
+    let summary = tokio::task::spawn_blocking(move || {
+        analyze_images_internal(
+            &paths,
+            &options,
+            None,
+            &AtomicBool::new(false),
+            |_, _| {},
+            |_| {},
+        )
+    })
+    .await
+    .map_err(|e| CompressionError::internal(format!("Estimation task failed: {}", e)))?;
 
-    let total_original: u64 = images.iter().map(|img| img.original_size).sum();
-    let total_estimated: u64 = images.iter().map(|img| img.estimated_size).sum();
+    let total_original = summary.total_original_size;
+    let total_estimated = summary.total_estimated_size;
     let estimated_savings = total_original.saturating_sub(total_estimated);
 
     let savings_percentage = if total_original > 0 {
@@ -154,7 +336,7 @@ pub async fn estimate_savings(
         total_estimated,
         estimated_savings,
         savings_percentage,
-        file_count: images.len(),
+        file_count: summary.analyzed,
     })
 }
 
@@ -187,6 +369,23 @@ pub fn get_default_config() -> CompressionConfig {
         size_ratio: 0.8,
         thread_count: num_cpus::get().max(1),
         preserve_structure: false,
+        output_template: None,
+        transforms: Vec::new(),
+        crop: None,
+        resize_filter: ResizeFilter::default(),
+        linear_light: false,
+        sharpen: None,
+        watermark: None,
+        responsive: None,
+        accept_extensionless: false,
+        jpeg: None,
+        lossless_jpeg: None,
+        larger_output: LargerOutputPolicy::default(),
+        min_savings_percent: 0.0,
+        limits: DecodeLimits::default(),
+        execution: ExecutionMode::default(),
+        raw: RawConfig::default(),
+        svg: SvgConfig::default(),
     }
 }
 
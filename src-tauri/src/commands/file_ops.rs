@@ -4,7 +4,9 @@ use tauri::Manager;
 use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_shell::ShellExt;
 
-use crate::compression::{PathValidation, validate_paths as validate_paths_internal};
+use crate::compression::{
+    enabled_formats, supported_extensions, PathValidation, ReportFormat, validate_paths as validate_paths_internal,
+};
 
 /// Open a folder selection dialog
 #[tauri::command]
@@ -29,14 +31,15 @@ pub async fn select_folder(app: tauri::AppHandle) -> Result<Option<String>, Stri
 pub async fn select_files(app: tauri::AppHandle) -> Result<Vec<String>, String> {
     let (tx, rx) = std::sync::mpsc::channel();
 
-    app.dialog()
+    let mut dialog = app.dialog()
         .file()
-        .add_filter("Image Files", &["jpg", "jpeg", "png", "bmp", "gif", "webp", "tiff", "tif", "ico"])
-        .add_filter("JPEG Images", &["jpg", "jpeg"])
-        .add_filter("PNG Images", &["png"])
-        .add_filter("BMP Images", &["bmp"])
-        .add_filter("GIF Images", &["gif"])
-        .add_filter("WebP Images", &["webp"])
+        .add_filter("Image Files", &supported_extensions());
+
+    for format in enabled_formats() {
+        dialog = dialog.add_filter(format.label, format.extensions);
+    }
+
+    dialog
         .add_filter("All Files", &["*"])
         .pick_files(move |file_paths| {
             let _ = tx.send(file_paths);
@@ -119,10 +122,41 @@ pub fn get_default_output_folder() -> Result<String, String> {
     Ok(output_dir.to_string_lossy().to_string())
 }
 
-/// Validate if paths exist and are valid images or contain images
+/// Validate if paths exist and are valid images or contain images.
+/// Only headers are read unless `deep` is set.
+#[tauri::command]
+pub fn validate_paths(paths: Vec<String>, deep: Option<bool>) -> Vec<PathValidation> {
+    validate_paths_internal(&paths, deep.unwrap_or(false))
+}
+
+/// Open a save dialog for a batch report, starting in `directory` if given
 #[tauri::command]
-pub fn validate_paths(paths: Vec<String>) -> Vec<PathValidation> {
-    validate_paths_internal(&paths)
+pub async fn select_report_path(
+    app: tauri::AppHandle,
+    format: ReportFormat,
+    directory: Option<String>,
+) -> Result<Option<String>, String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let extension = format.extension();
+    let mut dialog = app.dialog()
+        .file()
+        .add_filter(format!("{} Report", extension.to_uppercase()), &[extension])
+        .set_file_name(format!("compression-report.{}", extension));
+
+    if let Some(directory) = directory {
+        dialog = dialog.set_directory(directory);
+    }
+
+    dialog.save_file(move |file_path| {
+        let _ = tx.send(file_path);
+    });
+
+    // Wait for the dialog result
+    let result = rx.recv()
+        .map_err(|e| format!("Failed to receive dialog result: {}", e))?;
+
+    Ok(result.map(|path| path.to_string()))
 }
 
 /// Get information about the app's directories
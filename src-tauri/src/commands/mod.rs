@@ -8,16 +8,22 @@ pub use file_ops::{
     open_in_explorer,
     get_default_output_folder,
     validate_paths,
+    select_report_path,
     get_app_directories,
     ensure_directory_exists,
     check_path_exists,
 };
 
 pub use compress::{
+    AnalysisState,
+    JobState,
     analyze_images,
+    cancel_analysis,
     compress_images,
+    export_report,
     estimate_savings,
     cancel_compression,
+    clear_thumbnail_cache,
     get_default_config,
     get_system_info,
 };
\ No newline at end of file
@@ -1,24 +1,33 @@
 use std::path::Path;
 use std::fs;
 use std::io;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use image::{DynamicImage, ImageFormat};
 use image::imageops::FilterType;
 use walkdir::WalkDir;
 use base64::{Engine as _, engine::general_purpose};
 use rayon::prelude::*;
 
-use super::types::{ImageInfo, PathValidation};
+use super::thumbnail_cache::{ThumbnailCache, ThumbnailKey};
+use super::header::{bit_depth, color_type_name, extension_format, is_extension_mismatch, open_image, read_header, sniff_format, SourceFormat};
+use super::formats::{is_supported_extension, is_supported_format};
+use super::error::{catch_panic, CompressionError};
+use super::limits::{admit, MemoryBudget};
+use super::types::{AnalysisOptions, AnalysisSummary, DecodeLimits, ImageError, ImageInfo, PathValidation, RawConfig, SvgConfig};
 
-/// Supported image extensions
-const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "bmp", "gif", "webp", "tiff", "tif", "ico"];
+/// Maximum number of analyzed images handed to the batch callback at once
+const ANALYSIS_BATCH_SIZE: usize = 50;
+
+/// Flush a partial batch after this long, so slow images still show up promptly
+const ANALYSIS_BATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Default thumbnail size in pixels
 const THUMBNAIL_SIZE: u32 = 64;
 
-/// Generate a thumbnail from an image and return it as a base64-encoded data URI
-fn generate_thumbnail(img: &DynamicImage) -> Result<String, String> {
+/// Generate a thumbnail from an image and return it as PNG bytes
+fn generate_thumbnail(img: &DynamicImage) -> Result<Vec<u8>, CompressionError> {
     // Resize to thumbnail size (maintaining aspect ratio)
     // Using Triangle filter for better performance while maintaining decent quality
     let thumbnail = img.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle);
@@ -26,98 +35,168 @@ fn generate_thumbnail(img: &DynamicImage) -> Result<String, String> {
     // Convert to PNG bytes
     let mut png_bytes = Vec::new();
     thumbnail.write_to(&mut io::Cursor::new(&mut png_bytes), ImageFormat::Png)
-        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+        .map_err(|e| CompressionError::EncodeFailed {
+            format: "PNG".to_string(),
+            cause: e.to_string(),
+        })?;
 
-    // Encode as base64
-    let base64_string = general_purpose::STANDARD.encode(&png_bytes);
+    Ok(png_bytes)
+}
 
-    Ok(format!("data:image/png;base64,{}", base64_string))
+/// Encode PNG bytes as a base64 data URI
+fn thumbnail_data_uri(png_bytes: &[u8]) -> String {
+    format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png_bytes))
 }
 
 /// Check if a file has a valid image extension
 pub fn has_valid_extension(path: &Path) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
-        .map(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .map(is_supported_extension)
         .unwrap_or(false)
 }
 
+/// Check if a file without an extension starts with the signature of a supported format
+fn has_supported_signature(path: &Path) -> bool {
+    path.extension().is_none()
+        && matches!(sniff_format(path), Ok(Some(format)) if is_supported_format(format))
+}
+
+/// Check if a file should be treated as an image: a supported extension, or (when
+/// `accept_extensionless` is set) no extension but a recognised file signature
+pub fn is_image_candidate(path: &Path, accept_extensionless: bool) -> bool {
+    has_valid_extension(path) || (accept_extensionless && has_supported_signature(path))
+}
+
 /// Validate if a file is a valid image
-pub fn is_valid_image(path: &Path) -> Result<(), String> {
+///
+/// By default only the header is read; `deep` decodes the whole image to catch
+/// truncated or corrupted pixel data.
+pub fn is_valid_image(path: &Path, deep: bool) -> Result<(), CompressionError> {
     // Check if file exists
     if !path.exists() {
-        return Err(format!("File does not exist: {}", path.display()));
+        return Err(CompressionError::FileNotFound { path: path.display().to_string() });
     }
 
     // Check if it's a file (not a directory)
     if !path.is_file() {
-        return Err(format!("Path is not a file: {}", path.display()));
+        return Err(CompressionError::NotAFile { path: path.display().to_string() });
     }
 
-    // Check extension
-    if !has_valid_extension(path) {
-        return Err(format!(
-            "Unsupported file extension. Supported formats: {}",
-            SUPPORTED_EXTENSIONS.join(", ")
-        ));
+    // Check extension (files without one are judged by their contents)
+    if !has_valid_extension(path) && !has_supported_signature(path) {
+        return Err(CompressionError::UnsupportedFormat { path: path.display().to_string() });
     }
 
-    // Try to open as image to verify it's actually valid
-    match image::open(path) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(format!("Invalid or corrupted image file: {}", e)),
-    }
+    // Try to read the image to verify it's actually valid
+    let result = if deep {
+        open_image(path, &DecodeLimits::default(), &RawConfig::default(), &SvgConfig::default()).map(|_| ())
+    } else {
+        read_header(path, &RawConfig::default()).map(|_| ())
+    };
+
+    result.map_err(|e| CompressionError::decode(path, &e))
 }
 
 /// Analyze a single image file and extract metadata (optimized - opens image only once)
-pub fn analyze_image(path: &Path, quality: f32, size_ratio: f32, generate_thumbnails: bool) -> Result<ImageInfo, String> {
+///
+/// Only the header is read unless thumbnails or deep validation are requested, and with a
+/// thumbnail cache unchanged files are answered from the cache without decoding.
+/// Failures are typed so the UI can explain why a file is missing.
+pub fn analyze_image(
+    path: &Path,
+    options: &AnalysisOptions,
+    thumbnail_cache: Option<&ThumbnailCache>,
+    budget: &MemoryBudget,
+) -> Result<ImageInfo, CompressionError> {
     // Check if file exists and is valid
     if !path.exists() {
-        return Err(format!("File does not exist: {}", path.display()));
+        return Err(CompressionError::FileNotFound { path: path.display().to_string() });
     }
 
     if !path.is_file() {
-        return Err(format!("Path is not a file: {}", path.display()));
+        return Err(CompressionError::NotAFile { path: path.display().to_string() });
     }
 
-    // Check extension before trying to open
-    if !has_valid_extension(path) {
-        return Err(format!(
-            "Unsupported file extension. Supported formats: {}",
-            SUPPORTED_EXTENSIONS.join(", ")
-        ));
+    // Check extension before trying to open (extensionless files are checked by the header read)
+    let extensionless_allowed = options.accept_extensionless && path.extension().is_none();
+    if !has_valid_extension(path) && !extensionless_allowed {
+        return Err(CompressionError::UnsupportedFormat { path: path.display().to_string() });
     }
 
     // Get file metadata
     let metadata = fs::metadata(path)
-        .map_err(|e| format!("Failed to read file metadata: {}", e))?;
+        .map_err(|e| CompressionError::read(path, &e))?;
 
     let original_size = metadata.len();
 
-    // Open the image ONCE - use for validation, dimensions, and thumbnail
-    let img = image::open(path)
-        .map_err(|e| format!("Invalid or corrupted image file: {}", e))?;
+    // Extract filename
+    let filename = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
 
-    let (width, height) = (img.width(), img.height());
+    // Cache hit: the file is unchanged since it was last decoded successfully
+    let cache_key = thumbnail_cache
+        .filter(|_| options.generate_thumbnails)
+        .map(|_| ThumbnailKey::new(path, &metadata, THUMBNAIL_SIZE));
+
+    if let (Some(cache), Some(key)) = (thumbnail_cache, &cache_key) {
+        // The cache only stores dimensions, the color type comes from the (cheap) header
+        if let (Some(cached), Ok(header)) = (cache.get(key), read_header(path, &options.raw)) {
+            let format = header.format.name();
+            let estimated_size = estimate_compressed_size(original_size, &format, options.quality, options.size_ratio);
+
+            return Ok(ImageInfo {
+                path: path.display().to_string(),
+                filename,
+                original_size,
+                estimated_size,
+                format,
+                width: cached.width,
+                height: cached.height,
+                extension_mismatch: is_extension_mismatch(path, header.format),
+                color_type: header.color_type_name(),
+                bit_depth: header.bit_depth(),
+                thumbnail: Some(thumbnail_data_uri(&cached.png)),
+            });
+        }
+    }
 
-    // Detect format
-    let format = detect_format(path, &img);
+    let invalid = |e: image::ImageError| CompressionError::decode(path, &e);
 
-    // Estimate compressed size
-    let estimated_size = estimate_compressed_size(original_size, &format, quality, size_ratio);
+    // Decode the whole image ONCE only when the pixels are needed, otherwise read the header
+    let (content_format, width, height, color_type, thumbnail) = if options.generate_thumbnails || options.deep_validation {
+        // Reject oversized images before decoding and wait for memory to decode this one
+        let header = read_header(path, &options.raw).map_err(invalid)?;
+        let _permit = admit(path, (header.width, header.height), &options.limits, budget)?;
 
-    // Generate thumbnail only if requested (thumbnails are expensive)
-    let thumbnail = if generate_thumbnails {
-        generate_thumbnail(&img).ok()
+        let img = open_image(path, &options.limits, &options.raw, &SvgConfig::default()).map_err(invalid)?;
+        let (width, height) = (img.width(), img.height());
+
+        // Generate thumbnail only if requested (thumbnails are expensive)
+        let thumbnail = if options.generate_thumbnails {
+            generate_thumbnail(&img).ok().map(|png| {
+                if let (Some(cache), Some(key)) = (thumbnail_cache, &cache_key) {
+                    cache.put(key, width, height, &png);
+                }
+                thumbnail_data_uri(&png)
+            })
+        } else {
+            None
+        };
+
+        (detect_format(path), width, height, img.color(), thumbnail)
     } else {
-        None
+        let header = read_header(path, &options.raw).map_err(invalid)?;
+        (Some(header.format), header.width, header.height, header.color_type, None)
     };
 
-    // Extract filename
-    let filename = path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+    let format = content_format.map(SourceFormat::name).unwrap_or_else(|| "UNKNOWN".to_string());
+    let extension_mismatch = content_format.is_some_and(|f| is_extension_mismatch(path, f));
+
+    // Estimate compressed size
+    let estimated_size = estimate_compressed_size(original_size, &format, options.quality, options.size_ratio);
 
     Ok(ImageInfo {
         path: path.display().to_string(),
@@ -127,42 +206,35 @@ pub fn analyze_image(path: &Path, quality: f32, size_ratio: f32, generate_thumbn
         format,
         width,
         height,
+        extension_mismatch,
+        color_type: color_type_name(color_type),
+        bit_depth: bit_depth(color_type),
         thumbnail,
     })
 }
 
-/// Detect image format from path and image data
-fn detect_format(path: &Path, _img: &DynamicImage) -> String {
-    // Try to detect from extension first
-    if let Some(format) = path.extension()
-        .and_then(|ext| ext.to_str())
-        .and_then(|ext| match ext.to_lowercase().as_str() {
-            "jpg" | "jpeg" => Some("JPEG"),
-            "png" => Some("PNG"),
-            "bmp" => Some("BMP"),
-            "gif" => Some("GIF"),
-            "webp" => Some("WEBP"),
-            "tiff" | "tif" => Some("TIFF"),
-            "ico" => Some("ICO"),
-            _ => None,
-        }) {
-        return format.to_string();
-    }
-
-    // Fallback to unknown if we can't determine from extension
-    "UNKNOWN".to_string()
+/// Detect image format from the file signature, falling back to the extension
+fn detect_format(path: &Path) -> Option<SourceFormat> {
+    sniff_format(path)
+        .ok()
+        .flatten()
+        .or_else(|| extension_format(path))
 }
 
-
 /// Estimate compressed size based on quality and size ratio
 pub fn estimate_compressed_size(original_size: u64, format: &str, quality: f32, size_ratio: f32) -> u64 {
     // Base compression factor depends on the source format
     let base_factor = match format {
-        "BMP" | "TIFF" => 0.15,  // Uncompressed formats compress well
+        "BMP" | "TIFF" | "TGA" | "PNM" | "PSD" => 0.15,  // Uncompressed formats compress well
         "PNG" => 0.4,             // Lossless format to lossy
         "GIF" => 0.5,             // Already compressed but inefficient
         "JPEG" => 0.8,            // Already compressed, less savings
         "WEBP" => 0.85,           // Already well compressed
+        "HEIF" => 1.0,            // HEVC is more efficient than the JPEG output
+        "RAW" => 0.15,            // Sensor data, far larger than any JPEG
+        "SVG" => 1.0,             // Output depends on the render size, not the source
+        "HDR" | "EXR" => 0.1,     // 32-bit float samples down to 8 bits
+        "QOI" | "DDS" => 0.3,     // Lightly compressed
         _ => 0.5,                 // Default estimate
     };
 
@@ -176,17 +248,25 @@ pub fn estimate_compressed_size(original_size: u64, format: &str, quality: f32,
     estimated.max(1024).min(original_size)
 }
 
-/// Analyze multiple image paths with parallel processing and progress reporting
-pub fn analyze_images<F>(
+/// Analyze multiple image paths in parallel, handing results to `batch_callback` in
+/// chunks as soon as they are ready instead of collecting them all first.
+///
+/// Setting `cancel` stops the run: images not yet started are skipped and whatever was
+/// already analyzed is still flushed.
+pub fn analyze_images<F, B>(
     paths: &[String],
-    quality: f32,
-    size_ratio: f32,
-    generate_thumbnails: bool,
-    progress_callback: F
-) -> Vec<ImageInfo>
+    options: &AnalysisOptions,
+    thumbnail_cache: Option<&ThumbnailCache>,
+    cancel: &AtomicBool,
+    progress_callback: F,
+    batch_callback: B,
+) -> AnalysisSummary
 where
     F: Fn(usize, usize) + Send + Sync,
+    B: Fn(Vec<ImageInfo>) + Send + Sync,
 {
+    let start_time = Instant::now();
+
     // First, collect all image paths to process
     let mut image_paths = Vec::new();
 
@@ -200,55 +280,120 @@ where
                 .filter_map(|e| e.ok())
                 .filter(|e| e.file_type().is_file())
             {
-                if has_valid_extension(entry.path()) {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                if is_image_candidate(entry.path(), options.accept_extensionless) {
                     image_paths.push(entry.path().to_path_buf());
                 }
             }
         } else {
             // Single file
-            if has_valid_extension(path) {
+            if is_image_candidate(path, options.accept_extensionless) {
                 image_paths.push(path.to_path_buf());
             }
         }
     }
 
     let total = image_paths.len();
-    if total == 0 {
-        return Vec::new();
-    }
+    let mut summary = AnalysisSummary {
+        total,
+        ..AnalysisSummary::default()
+    };
 
-    // Use atomic counter for thread-safe progress tracking
-    let processed = Arc::new(AtomicUsize::new(0));
+    if total > 0 {
+        // Use atomic counter for thread-safe progress tracking
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        // Results waiting to be flushed, and when the last batch went out
+        let pending = Mutex::new((Vec::with_capacity(ANALYSIS_BATCH_SIZE), Instant::now()));
+
+        // Take the pending results if a batch is due (or unconditionally when forced)
+        let take_batch = |force: bool| -> Option<Vec<ImageInfo>> {
+            let mut pending = pending.lock().unwrap_or_else(|e| e.into_inner());
+            let (images, last_flush) = &mut *pending;
+            let due = images.len() >= ANALYSIS_BATCH_SIZE
+                || last_flush.elapsed() >= ANALYSIS_BATCH_INTERVAL;
+            if images.is_empty() || !(force || due) {
+                return None;
+            }
+            *last_flush = Instant::now();
+            Some(std::mem::take(images))
+        };
+
+        // Files that could not be analyzed, reported in the summary
+        let errors = Mutex::new(Vec::new());
+
+        // Memory shared by the images decoded at the same time
+        let budget = MemoryBudget::new(&options.limits);
+
+        // Process images in parallel using Rayon
+        let (analyzed, original_size, estimated_size) = image_paths
+            .par_iter()
+            .map(|path| {
+                if cancel.load(Ordering::Relaxed) {
+                    return (0, 0, 0);
+                }
 
-    // Process images in parallel using Rayon
-    let results: Vec<ImageInfo> = image_paths
-        .par_iter()
-        .filter_map(|path| {
-            // Analyze the image
-            let result = analyze_image(path, quality, size_ratio, generate_thumbnails).ok();
+                // Analyze the image
+                let sizes = match catch_panic(path, || analyze_image(path, options, thumbnail_cache, &budget)) {
+                    Ok(info) => {
+                        let sizes = (1, info.original_size, info.estimated_size);
+                        pending.lock().unwrap_or_else(|e| e.into_inner()).0.push(info);
+                        sizes
+                    }
+                    Err(error) => {
+                        log::warn!("Failed to analyze {}: {}", path.display(), error);
+                        let error = ImageError::new(path.display().to_string(), error);
+                        errors.lock().unwrap_or_else(|e| e.into_inner()).push(error);
+                        (0, 0, 0)
+                    }
+                };
+
+                // Send outside the lock so other workers are not blocked by the callback
+                if let Some(batch) = take_batch(false) {
+                    batch_callback(batch);
+                }
 
-            // Update progress counter
-            let current = processed.fetch_add(1, Ordering::Relaxed) + 1;
+                // Update progress counter
+                let current = processed.fetch_add(1, Ordering::Relaxed) + 1;
 
-            // Report progress (note: this will be called from multiple threads)
-            progress_callback(current, total);
+                // Report progress (note: this will be called from multiple threads)
+                progress_callback(current, total);
 
-            result
-        })
-        .collect();
+                sizes
+            })
+            .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+        // Flush whatever is left over
+        if let Some(batch) = take_batch(true) {
+            batch_callback(batch);
+        }
+
+        let mut errors = errors.into_inner().unwrap_or_else(|e| e.into_inner());
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+
+        summary.analyzed = analyzed;
+        summary.failed = errors.len();
+        summary.errors = errors;
+        summary.total_original_size = original_size;
+        summary.total_estimated_size = estimated_size;
+    }
 
-    results
+    summary.cancelled = cancel.load(Ordering::Relaxed);
+    summary.duration_ms = start_time.elapsed().as_millis();
+    summary
 }
 
-/// Validate multiple paths
-pub fn validate_paths(paths: &[String]) -> Vec<PathValidation> {
+/// Validate multiple paths (`deep` fully decodes single files, see `is_valid_image`)
+pub fn validate_paths(paths: &[String], deep: bool) -> Vec<PathValidation> {
     paths.iter().map(|path_str| {
         let path = Path::new(path_str);
 
         if !path.exists() {
             PathValidation::invalid(
                 path_str.clone(),
-                "Path does not exist".to_string()
+                CompressionError::FileNotFound { path: path_str.clone() }
             )
         } else if path.is_dir() {
             // Check if directory contains any images
@@ -262,12 +407,12 @@ pub fn validate_paths(paths: &[String]) -> Vec<PathValidation> {
             } else {
                 PathValidation::invalid(
                     path_str.clone(),
-                    "Directory contains no valid image files".to_string()
+                    CompressionError::NoImagesFound
                 )
             }
         } else {
             // Single file validation
-            match is_valid_image(path) {
+            match is_valid_image(path, deep) {
                 Ok(()) => PathValidation::valid(path_str.clone()),
                 Err(e) => PathValidation::invalid(path_str.clone(), e),
             }
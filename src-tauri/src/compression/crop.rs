@@ -0,0 +1,278 @@
+use image::{DynamicImage, GrayImage};
+use image::imageops::FilterType;
+
+use super::types::{CropAnchor, CropConfig};
+
+/// Longest side of the preview used to score crop windows in the smart modes
+const ANALYSIS_SIZE: u32 = 256;
+
+/// Number of candidate windows evaluated in entropy mode
+const ENTROPY_STEPS: u32 = 32;
+
+/// Aspect ratio components are exact up to this many decimals (100 = two), e.g. 1.91:1
+const ASPECT_SCALE: f32 = 100.0;
+
+/// Windows spanning fewer units of the reduced ratio keep the rounded size, as snapping
+/// them to whole units could give up more than 2% of the image
+const MIN_SNAP_UNITS: u32 = 50;
+
+/// Smallest whole-pixel size with exactly the target aspect ratio, e.g. 4×5 for "8:10"
+fn aspect_unit(crop: &CropConfig) -> Option<(u32, u32)> {
+    let whole = |value: f32| {
+        let scaled = value * ASPECT_SCALE;
+        ((scaled - scaled.round()).abs() < 1e-3 && scaled >= 1.0).then(|| scaled.round() as u32)
+    };
+    let (width, height) = (whole(crop.aspect_width)?, whole(crop.aspect_height)?);
+
+    let divisor = gcd(width, height);
+    Some((width / divisor, height / divisor))
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Size of the largest window with the target aspect ratio that fits the image. It is a
+/// whole number of aspect units when the image is large enough, so rounding each side
+/// doesn't leave it a pixel off the ratio.
+pub fn crop_dimensions(width: u32, height: u32, crop: &CropConfig) -> (u32, u32) {
+    if let Some((unit_w, unit_h)) = aspect_unit(crop) {
+        let units = (width / unit_w).min(height / unit_h);
+        if units >= MIN_SNAP_UNITS {
+            return (units * unit_w, units * unit_h);
+        }
+    }
+
+    let target = crop.aspect_width as f64 / crop.aspect_height as f64;
+    let current = width as f64 / height.max(1) as f64;
+
+    if current > target {
+        // Too wide: keep the full height
+        (((height as f64 * target).round() as u32).clamp(1, width), height)
+    } else {
+        // Too tall: keep the full width
+        (width, ((width as f64 / target).round() as u32).clamp(1, height))
+    }
+}
+
+/// Scale a window from `crop_dimensions` by whole aspect units, so its ratio stays exact.
+/// `None` when the window wasn't snapped to units or would shrink below them.
+pub fn scaled_crop_dimensions(width: u32, height: u32, crop: &CropConfig, size_ratio: f32) -> Option<(u32, u32)> {
+    let (unit_w, unit_h) = aspect_unit(crop)?;
+    let units = width / unit_w;
+    if units < MIN_SNAP_UNITS || (units * unit_w, units * unit_h) != (width, height) {
+        return None;
+    }
+
+    let scaled = ((units as f32 * size_ratio) as u32).max(1);
+    Some((scaled * unit_w, scaled * unit_h))
+}
+
+/// Crop an image to the configured aspect ratio around the chosen anchor
+pub fn crop_to_aspect(img: DynamicImage, crop: &CropConfig) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let (crop_w, crop_h) = crop_dimensions(width, height, crop);
+
+    if crop_w == width && crop_h == height {
+        return img;
+    }
+
+    let (x, y) = match crop.anchor {
+        CropAnchor::Center => ((width - crop_w) / 2, (height - crop_h) / 2),
+        CropAnchor::Top => ((width - crop_w) / 2, 0),
+        CropAnchor::Bottom => ((width - crop_w) / 2, height - crop_h),
+        CropAnchor::Left => (0, (height - crop_h) / 2),
+        CropAnchor::Right => (width - crop_w, (height - crop_h) / 2),
+        CropAnchor::Entropy | CropAnchor::Attention => {
+            smart_offset(&img, crop_w, crop_h, crop.anchor)
+        }
+    };
+
+    img.crop_imm(x, y, crop_w, crop_h)
+}
+
+/// Find the window offset that keeps the most interesting region.
+///
+/// Only one axis is ever cropped, so this slides the window along that axis on a
+/// downscaled grayscale copy and maps the best position back to full resolution.
+fn smart_offset(img: &DynamicImage, crop_w: u32, crop_h: u32, anchor: CropAnchor) -> (u32, u32) {
+    let (width, height) = (img.width(), img.height());
+    let horizontal = crop_w < width;
+
+    let preview = img.resize(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Triangle).to_luma8();
+    let (preview_len, full_len, window_full) = if horizontal {
+        (preview.width(), width, crop_w)
+    } else {
+        (preview.height(), height, crop_h)
+    };
+
+    let window = ((window_full as f64 / full_len as f64 * preview_len as f64).round() as u32)
+        .clamp(1, preview_len);
+    let free = preview_len - window;
+
+    let best = if free == 0 {
+        0
+    } else if anchor == CropAnchor::Entropy {
+        best_entropy_offset(&preview, window, free, horizontal)
+    } else {
+        best_detail_offset(&preview, window, horizontal)
+    };
+
+    // Map the preview offset back and keep the window inside the image
+    let offset = ((best as f64 / preview_len as f64) * full_len as f64).round() as u32;
+    let offset = offset.min(full_len - window_full);
+
+    if horizontal {
+        (offset, (height - crop_h) / 2)
+    } else {
+        ((width - crop_w) / 2, offset)
+    }
+}
+
+/// Offset of the window with the highest Shannon entropy of its luminance histogram
+fn best_entropy_offset(preview: &GrayImage, window: u32, free: u32, horizontal: bool) -> u32 {
+    let steps = ENTROPY_STEPS.min(free);
+    let mut best = (0u32, f64::MIN);
+
+    for step in 0..=steps {
+        let offset = (free as u64 * step as u64 / steps as u64) as u32;
+        let mut histogram = [0u32; 256];
+        let mut count = 0u32;
+
+        for (x, y, pixel) in preview.enumerate_pixels() {
+            let position = if horizontal { x } else { y };
+            if position >= offset && position < offset + window {
+                histogram[pixel[0] as usize] += 1;
+                count += 1;
+            }
+        }
+
+        let entropy: f64 = histogram
+            .iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| {
+                let p = n as f64 / count as f64;
+                -p * p.log2()
+            })
+            .sum();
+
+        // Ties (e.g. flat backgrounds) prefer the window closest to the center
+        let closer = offset.abs_diff(free / 2) < best.0.abs_diff(free / 2);
+        if entropy > best.1 + f64::EPSILON || ((entropy - best.1).abs() <= f64::EPSILON && closer) {
+            best = (offset, entropy);
+        }
+    }
+
+    best.0
+}
+
+/// Offset of the window with the most edge detail (sum of gradient magnitudes)
+fn best_detail_offset(preview: &GrayImage, window: u32, horizontal: bool) -> u32 {
+    let (w, h) = preview.dimensions();
+    let len = if horizontal { w } else { h };
+    let mut energy = vec![0u64; len as usize];
+
+    for y in 0..h {
+        for x in 0..w {
+            let center = preview.get_pixel(x, y)[0] as i32;
+            let right = preview.get_pixel((x + 1).min(w - 1), y)[0] as i32;
+            let below = preview.get_pixel(x, (y + 1).min(h - 1))[0] as i32;
+            let gradient = ((right - center).abs() + (below - center).abs()) as u64;
+            energy[if horizontal { x } else { y } as usize] += gradient;
+        }
+    }
+
+    // Sliding window sum over the per-column (or per-row) energy
+    let window = window as usize;
+    let center = (energy.len() - window) / 2;
+    let mut sum: u64 = energy[..window].iter().sum();
+    let mut best = (0usize, sum);
+
+    for start in 1..=(energy.len() - window) {
+        sum = sum + energy[start + window - 1] - energy[start - 1];
+        // Ties (e.g. flat backgrounds) prefer the window closest to the center
+        if sum > best.1 || (sum == best.1 && start.abs_diff(center) < best.0.abs_diff(center)) {
+            best = (start, sum);
+        }
+    }
+
+    best.0 as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    fn crop(aspect_width: f32, aspect_height: f32, anchor: CropAnchor) -> CropConfig {
+        CropConfig { aspect_width, aspect_height, anchor }
+    }
+
+    /// Each pixel holds its own x (wide images) or y (tall images), so a crop shows its offset
+    fn ramp(width: u32, height: u32) -> DynamicImage {
+        let wide = width > height;
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| Luma([if wide { x } else { y } as u8])))
+    }
+
+    #[test]
+    fn crops_to_the_exact_ratio() {
+        assert_eq!(crop_dimensions(4032, 3024, &crop(4.0, 5.0, CropAnchor::Center)), (2416, 3020));
+        assert_eq!(crop_dimensions(1920, 1440, &crop(16.0, 9.0, CropAnchor::Center)), (1920, 1080));
+        // Reduced like 4:5, and decimal ratios are exact too
+        assert_eq!(crop_dimensions(4032, 3024, &crop(8.0, 10.0, CropAnchor::Center)), (2416, 3020));
+        assert_eq!(crop_dimensions(10000, 6000, &crop(1.91, 1.0, CropAnchor::Center)), (9932, 5200));
+        // Too small to snap to whole units: rounded instead
+        assert_eq!(crop_dimensions(1080, 1350, &crop(1.91, 1.0, CropAnchor::Center)), (1080, 565));
+        assert_eq!(crop_dimensions(3, 1, &crop(1.0, 1.0, CropAnchor::Center)), (1, 1));
+    }
+
+    #[test]
+    fn scales_snapped_windows_by_whole_units() {
+        let config = crop(4.0, 5.0, CropAnchor::Center);
+        assert_eq!(scaled_crop_dimensions(2416, 3020, &config, 0.5), Some((1208, 1510)));
+        assert_eq!(scaled_crop_dimensions(2416, 3020, &config, 0.333), Some((804, 1005)));
+        // Not snapped, or not the target ratio at all
+        assert_eq!(scaled_crop_dimensions(1080, 565, &crop(1.91, 1.0, CropAnchor::Center), 0.5), None);
+        assert_eq!(scaled_crop_dimensions(2416, 3000, &config, 0.5), None);
+    }
+
+    #[test]
+    fn positions_the_window_by_the_anchor() {
+        let offset = |img: DynamicImage, anchor| {
+            let cropped = crop_to_aspect(img, &crop(1.0, 1.0, anchor)).to_luma8();
+            assert_eq!(cropped.dimensions(), (10, 10));
+            cropped.get_pixel(0, 0)[0]
+        };
+
+        assert_eq!(offset(ramp(30, 10), CropAnchor::Left), 0);
+        assert_eq!(offset(ramp(30, 10), CropAnchor::Center), 10);
+        assert_eq!(offset(ramp(30, 10), CropAnchor::Right), 20);
+        assert_eq!(offset(ramp(10, 30), CropAnchor::Top), 0);
+        assert_eq!(offset(ramp(10, 30), CropAnchor::Center), 10);
+        assert_eq!(offset(ramp(10, 30), CropAnchor::Bottom), 20);
+        // Anchors along the other axis center the window
+        assert_eq!(offset(ramp(30, 10), CropAnchor::Top), 10);
+        assert_eq!(offset(ramp(10, 30), CropAnchor::Right), 10);
+    }
+
+    #[test]
+    fn smart_anchors_find_the_detailed_region() {
+        // Flat gray with a checkerboard in the right quarter
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(400, 100, |x, y| {
+            Luma([if x >= 300 && (x / 4 + y / 4) % 2 == 0 { 255 } else { 128 }])
+        }));
+
+        for anchor in [CropAnchor::Entropy, CropAnchor::Attention] {
+            let cropped = crop_to_aspect(img.clone(), &crop(1.0, 1.0, anchor)).to_luma8();
+            assert_eq!(cropped.dimensions(), (100, 100));
+            assert!(cropped.pixels().filter(|p| p[0] == 255).count() > 4000, "{:?}", anchor);
+        }
+    }
+
+    #[test]
+    fn leaves_images_with_the_ratio_alone() {
+        let cropped = crop_to_aspect(ramp(40, 50), &crop(4.0, 5.0, CropAnchor::Entropy));
+        assert_eq!(cropped.to_luma8(), ramp(40, 50).to_luma8());
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+
+use super::error::CompressionError;
+use super::jpeg::encode_jpeg;
+use super::types::{JpegEncoderConfig, OutputFormat};
+
+/// Encode an image in the given format into memory.
+/// JPEG uses the tuned encoder when `jpeg` is set, the image crate's baseline encoder otherwise.
+pub fn encode_bytes(
+    img: &DynamicImage,
+    format: OutputFormat,
+    quality: f32,
+    jpeg: Option<&JpegEncoderConfig>,
+) -> Result<Vec<u8>, CompressionError> {
+    let encode_failed = |name: &str, e: image::ImageError| CompressionError::EncodeFailed {
+        format: name.to_string(),
+        cause: e.to_string(),
+    };
+
+    let quality = quality.round().clamp(1.0, 100.0);
+
+    let bytes = match format {
+        OutputFormat::Jpeg => {
+            // JPEG has no alpha channel
+            if let Some(config) = jpeg {
+                return encode_jpeg(&flatten_to_rgb(img), quality, config);
+            }
+
+            let mut buffer = Vec::new();
+            JpegEncoder::new_with_quality(&mut buffer, quality as u8)
+                .encode_image(&flatten_to_rgb(img))
+                .map_err(|e| encode_failed("JPEG", e))?;
+            buffer
+        }
+        OutputFormat::Png => {
+            let mut buffer = Cursor::new(Vec::new());
+            strip_opaque_alpha(img)
+                .write_to(&mut buffer, ImageFormat::Png)
+                .map_err(|e| encode_failed("PNG", e))?;
+            buffer.into_inner()
+        }
+        OutputFormat::Webp => {
+            let img = strip_opaque_alpha(img);
+            let encoded = match &img {
+                DynamicImage::ImageRgba8(rgba) => {
+                    webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height()).encode(quality)
+                }
+                other => {
+                    let rgb = other.to_rgb8();
+                    webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height()).encode(quality)
+                }
+            };
+            encoded.to_vec()
+        }
+    };
+
+    Ok(bytes)
+}
+
+/// Write encoded bytes to `output_path` and return the size in bytes
+pub fn write_output(output_path: &Path, bytes: &[u8]) -> Result<u64, CompressionError> {
+    fs::write(output_path, bytes)
+        .map_err(|e| CompressionError::write(output_path, &e))?;
+
+    Ok(bytes.len() as u64)
+}
+
+/// Convert to RGB for JPEG, compositing any transparency onto white
+pub fn flatten_to_rgb(img: &DynamicImage) -> RgbImage {
+    if !img.color().has_alpha() {
+        return img.to_rgb8();
+    }
+
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let p = rgba.get_pixel(x, y);
+        let alpha = p[3] as u16;
+        let over_white = |c: u8| ((c as u16 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        image::Rgb([over_white(p[0]), over_white(p[1]), over_white(p[2])])
+    })
+}
+
+/// Normalize to 8-bit RGB(A), dropping the alpha channel when every pixel is opaque
+fn strip_opaque_alpha(img: &DynamicImage) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return DynamicImage::ImageRgb8(img.to_rgb8());
+    }
+
+    let rgba = img.to_rgba8();
+    if rgba.pixels().all(|p| p[3] == 255) {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+    } else {
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
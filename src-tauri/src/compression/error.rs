@@ -0,0 +1,321 @@
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use super::types::FailureReason;
+
+/// Error raised by the compression engine.
+///
+/// Serialized with a stable snake_case `code` next to the variant's parameters, e.g.
+/// `{ "code": "file_not_found", "path": "/photos/a.jpg" }`, so the frontend can pick a
+/// translated message. `Display` gives the English text used in logs and as a fallback.
+/// `cause` parameters carry the underlying library message and are not localized.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum CompressionError {
+    // Reading input files
+    FileNotFound { path: String },
+    NotAFile { path: String },
+    PermissionDenied { path: String },
+    UnsupportedFormat { path: String },
+    InvalidFileName { path: String },
+    DecodeFailed { path: String, cause: String },
+    ImageTooLarge { path: String, cause: String },
+    ReadFailed { path: String, cause: String },
+
+    // Writing output files
+    DiskFull { path: String },
+    WriteFailed { path: String, cause: String },
+    EncodeFailed { format: String, cause: String },
+
+    // Batch setup
+    NoPaths,
+    NoImagesFound,
+    MissingSetting { setting: String },
+    OutOfRange { setting: String, value: f64, min: f64, max: f64 },
+    InvalidSetting { setting: String, value: String, expected: String },
+    InvalidTemplate { template: String, cause: String },
+    NoFinishedJob,
+
+    // Bugs and environment problems the user cannot fix
+    Crashed { path: String, cause: String },  // a panic or worker crash while processing one image
+    TimedOut { path: String, seconds: u64 },  // a worker process took too long on one image
+    Internal { cause: String },
+}
+
+impl CompressionError {
+    /// Error for a failed read of an input file
+    pub fn read(path: &Path, error: &io::Error) -> Self {
+        let path = path.display().to_string();
+        match error.kind() {
+            io::ErrorKind::NotFound => CompressionError::FileNotFound { path },
+            io::ErrorKind::PermissionDenied => CompressionError::PermissionDenied { path },
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => CompressionError::DecodeFailed {
+                path,
+                cause: error.to_string(),
+            },
+            _ => CompressionError::ReadFailed {
+                path,
+                cause: error.to_string(),
+            },
+        }
+    }
+
+    /// Error for a failed write of an output file (or directory)
+    pub fn write(path: &Path, error: &io::Error) -> Self {
+        let path = path.display().to_string();
+        match error.kind() {
+            _ if is_disk_full(error) => CompressionError::DiskFull { path },
+            io::ErrorKind::PermissionDenied => CompressionError::PermissionDenied { path },
+            _ => CompressionError::WriteFailed {
+                path,
+                cause: error.to_string(),
+            },
+        }
+    }
+
+    /// Error for a failed decode of an input image
+    pub fn decode(path: &Path, error: &image::ImageError) -> Self {
+        let cause = error.to_string();
+        match error {
+            image::ImageError::IoError(e) => Self::read(path, e),
+            image::ImageError::Unsupported(_) => CompressionError::UnsupportedFormat {
+                path: path.display().to_string(),
+            },
+            image::ImageError::Limits(_) => CompressionError::ImageTooLarge {
+                path: path.display().to_string(),
+                cause,
+            },
+            _ => CompressionError::DecodeFailed {
+                path: path.display().to_string(),
+                cause,
+            },
+        }
+    }
+
+    /// Error for a failed encode into `format`, written to `path`
+    pub fn encode(path: &Path, format: &str, error: &image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(e) => Self::write(path, e),
+            _ => CompressionError::EncodeFailed {
+                format: format.to_string(),
+                cause: error.to_string(),
+            },
+        }
+    }
+
+    pub fn out_of_range(setting: &str, value: impl Into<f64>, min: impl Into<f64>, max: impl Into<f64>) -> Self {
+        CompressionError::OutOfRange {
+            setting: setting.to_string(),
+            value: value.into(),
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    pub fn invalid_setting(setting: &str, value: impl ToString, expected: &str) -> Self {
+        CompressionError::InvalidSetting {
+            setting: setting.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    pub fn missing_setting(setting: &str) -> Self {
+        CompressionError::MissingSetting {
+            setting: setting.to_string(),
+        }
+    }
+
+    pub fn invalid_template(template: &str, cause: impl ToString) -> Self {
+        CompressionError::InvalidTemplate {
+            template: template.to_string(),
+            cause: cause.to_string(),
+        }
+    }
+
+    pub fn internal(cause: impl ToString) -> Self {
+        CompressionError::Internal {
+            cause: cause.to_string(),
+        }
+    }
+
+    /// Broad category used to group per-file failures
+    pub fn reason(&self) -> FailureReason {
+        match self {
+            CompressionError::FileNotFound { .. } => FailureReason::NotFound,
+            CompressionError::PermissionDenied { .. } => FailureReason::PermissionDenied,
+            CompressionError::UnsupportedFormat { .. } => FailureReason::UnsupportedFormat,
+            CompressionError::DecodeFailed { .. } => FailureReason::Corrupt,
+            CompressionError::ImageTooLarge { .. } => FailureReason::TooLarge,
+            CompressionError::ReadFailed { .. }
+            | CompressionError::DiskFull { .. }
+            | CompressionError::WriteFailed { .. } => FailureReason::Io,
+            CompressionError::Crashed { .. } => FailureReason::Crashed,
+            CompressionError::TimedOut { .. } => FailureReason::TimedOut,
+            _ => FailureReason::Other,
+        }
+    }
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionError::FileNotFound { path } => write!(f, "File does not exist: {}", path),
+            CompressionError::NotAFile { path } => write!(f, "Path is not a file: {}", path),
+            CompressionError::PermissionDenied { path } => write!(f, "Permission denied: {}", path),
+            CompressionError::UnsupportedFormat { path } => write!(f, "Unsupported image format: {}", path),
+            CompressionError::InvalidFileName { path } => write!(f, "Invalid file name: {}", path),
+            CompressionError::DecodeFailed { cause, .. } => write!(f, "Invalid or corrupted image file: {}", cause),
+            CompressionError::ImageTooLarge { cause, .. } => write!(f, "Image is too large to decode: {}", cause),
+            CompressionError::ReadFailed { path, cause } => write!(f, "Failed to read {}: {}", path, cause),
+            CompressionError::DiskFull { path } => write!(f, "Disk full while writing {}", path),
+            CompressionError::WriteFailed { path, cause } => write!(f, "Failed to write {}: {}", path, cause),
+            CompressionError::EncodeFailed { format, cause } => write!(f, "{} encoding failed: {}", format, cause),
+            CompressionError::NoPaths => write!(f, "No paths provided"),
+            CompressionError::NoImagesFound => write!(f, "No valid image files found"),
+            CompressionError::MissingSetting { setting } => write!(f, "Missing setting: {}", setting),
+            CompressionError::OutOfRange { setting, value, min, max } => {
+                write!(f, "{} must be between {} and {}, got {}", setting, min, max, value)
+            }
+            CompressionError::InvalidSetting { setting, value, expected } => {
+                write!(f, "{} must be {}, got {}", setting, expected, value)
+            }
+            CompressionError::InvalidTemplate { template, cause } => {
+                write!(f, "Invalid filename template '{}': {}", template, cause)
+            }
+            CompressionError::NoFinishedJob => write!(f, "No finished compression job to report on"),
+            CompressionError::Crashed { path, cause } => write!(f, "Processing crashed on {}: {}", path, cause),
+            CompressionError::TimedOut { path, seconds } => {
+                write!(f, "Processing {} took longer than {} seconds", path, seconds)
+            }
+            CompressionError::Internal { cause } => write!(f, "Internal error: {}", cause),
+        }
+    }
+}
+
+impl std::error::Error for CompressionError {}
+
+/// Run the work for one image, turning a panic into that image's error so it can't
+/// take down the rest of the batch
+pub fn catch_panic<T>(
+    path: &Path,
+    work: impl FnOnce() -> Result<T, CompressionError>,
+) -> Result<T, CompressionError> {
+    panic::catch_unwind(AssertUnwindSafe(work)).unwrap_or_else(|payload| {
+        Err(CompressionError::Crashed {
+            path: path.display().to_string(),
+            cause: panic_message(payload.as_ref()).unwrap_or_else(|| "unknown panic".to_string()),
+        })
+    })
+}
+
+/// Message of a panic payload raised with `panic!` or `resume_unwind`
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+}
+
+/// Whether a write failed because the disk or the user's quota is full. Checked by OS
+/// error code, as the matching `io::ErrorKind`s need a newer Rust than the crate's MSRV.
+fn is_disk_full(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    const DISK_FULL_CODES: &[i32] = &[libc::ENOSPC, libc::EDQUOT];
+    // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL and ERROR_DISK_QUOTA_EXCEEDED
+    #[cfg(windows)]
+    const DISK_FULL_CODES: &[i32] = &[39, 112, 1295];
+    #[cfg(not(any(unix, windows)))]
+    const DISK_FULL_CODES: &[i32] = &[];
+
+    error.raw_os_error().is_some_and(|code| DISK_FULL_CODES.contains(&code))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serializes_a_code_next_to_the_parameters() {
+        let error = CompressionError::FileNotFound { path: "/photos/a.jpg".to_string() };
+        assert_eq!(serde_json::to_value(&error).unwrap(), json!({ "code": "file_not_found", "path": "/photos/a.jpg" }));
+        assert_eq!(serde_json::to_value(CompressionError::NoPaths).unwrap(), json!({ "code": "no_paths" }));
+        assert_eq!(
+            serde_json::to_value(CompressionError::out_of_range("quality", 120, 0, 100)).unwrap(),
+            json!({ "code": "out_of_range", "setting": "quality", "value": 120.0, "min": 0.0, "max": 100.0 })
+        );
+
+        let round_trip: CompressionError = serde_json::from_value(json!({ "code": "timed_out", "path": "a.png", "seconds": 30 })).unwrap();
+        assert_eq!(round_trip, CompressionError::TimedOut { path: "a.png".to_string(), seconds: 30 });
+    }
+
+    #[test]
+    fn maps_io_errors_by_kind() {
+        let path = Path::new("a.jpg");
+        let error = |kind| io::Error::new(kind, "boom");
+
+        assert_eq!(CompressionError::read(path, &error(io::ErrorKind::NotFound)).reason(), FailureReason::NotFound);
+        assert_eq!(CompressionError::read(path, &error(io::ErrorKind::UnexpectedEof)).reason(), FailureReason::Corrupt);
+        assert_eq!(CompressionError::read(path, &error(io::ErrorKind::Other)).reason(), FailureReason::Io);
+        assert_eq!(
+            CompressionError::write(path, &error(io::ErrorKind::PermissionDenied)),
+            CompressionError::PermissionDenied { path: "a.jpg".to_string() }
+        );
+        assert!(matches!(CompressionError::write(path, &error(io::ErrorKind::Other)), CompressionError::WriteFailed { .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detects_full_disks_by_os_error_code() {
+        let path = Path::new("out/a.jpg");
+        for code in [libc::ENOSPC, libc::EDQUOT] {
+            let error = io::Error::from_raw_os_error(code);
+            assert_eq!(CompressionError::write(path, &error), CompressionError::DiskFull { path: "out/a.jpg".to_string() });
+        }
+        assert!(!is_disk_full(&io::Error::from_raw_os_error(libc::EACCES)));
+        assert!(!is_disk_full(&io::Error::other("no space")));
+    }
+
+    #[test]
+    fn maps_image_errors_to_decode_failures() {
+        let path = Path::new("a.jpg");
+        let unsupported = image::ImageError::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+            image::error::ImageFormatHint::Unknown,
+            image::error::UnsupportedErrorKind::GenericFeature("test".to_string()),
+        ));
+        let limits = image::ImageError::Limits(image::error::LimitError::from_kind(image::error::LimitErrorKind::DimensionError));
+
+        assert_eq!(CompressionError::decode(path, &unsupported).reason(), FailureReason::UnsupportedFormat);
+        assert_eq!(CompressionError::decode(path, &limits).reason(), FailureReason::TooLarge);
+        assert_eq!(
+            CompressionError::decode(path, &image::ImageError::IoError(io::Error::new(io::ErrorKind::NotFound, "gone"))).reason(),
+            FailureReason::NotFound
+        );
+        assert!(matches!(CompressionError::encode(path, "WebP", &limits), CompressionError::EncodeFailed { .. }));
+    }
+
+    #[test]
+    fn turns_a_panic_into_the_images_error() {
+        let path = Path::new("a.jpg");
+        assert_eq!(catch_panic(path, || Ok(1)), Ok(1));
+        assert_eq!(catch_panic::<()>(path, || Err(CompressionError::NoPaths)), Err(CompressionError::NoPaths));
+
+        let crashed = |cause: &str| Err(CompressionError::Crashed { path: "a.jpg".to_string(), cause: cause.to_string() });
+        assert_eq!(catch_panic::<()>(path, || panic!("index out of bounds")), crashed("index out of bounds"));
+        assert_eq!(catch_panic::<()>(path, || panic!("row {} of {}", 3, 2)), crashed("row 3 of 2"));
+        assert_eq!(catch_panic::<()>(path, || panic::resume_unwind(Box::new(42))), crashed("unknown panic"));
+    }
+
+    #[test]
+    fn reads_panic_messages() {
+        assert_eq!(panic_message(&"static"), Some("static".to_string()));
+        assert_eq!(panic_message(&"owned".to_string()), Some("owned".to_string()));
+        assert_eq!(panic_message(&42), None);
+    }
+}
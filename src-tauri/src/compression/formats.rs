@@ -0,0 +1,97 @@
+use image::ImageFormat;
+
+use super::header::SourceFormat;
+use super::heif;
+use super::psd;
+use super::raw;
+use super::svg;
+
+/// An input format: what the file dialog calls it and which extensions belong to it
+pub struct InputFormat {
+    pub label: &'static str,  // file dialog filter name
+    pub format: SourceFormat,
+    pub extensions: &'static [&'static str],
+    pub enabled: bool,        // false when its decoder isn't compiled in
+}
+
+const fn image_format(label: &'static str, format: ImageFormat, extensions: &'static [&'static str]) -> InputFormat {
+    InputFormat {
+        label,
+        format: SourceFormat::Image(format),
+        extensions,
+        enabled: true,
+    }
+}
+
+/// Every input format, in file dialog order. Extension checks, dialog filters and the
+/// extension-to-format mapping are all derived from this list.
+pub const INPUT_FORMATS: &[InputFormat] = &[
+    image_format("JPEG Images", ImageFormat::Jpeg, &["jpg", "jpeg"]),
+    image_format("PNG Images", ImageFormat::Png, &["png"]),
+    image_format("BMP Images", ImageFormat::Bmp, &["bmp"]),
+    image_format("GIF Images", ImageFormat::Gif, &["gif"]),
+    image_format("WebP Images", ImageFormat::WebP, &["webp"]),
+    image_format("TIFF Images", ImageFormat::Tiff, &["tiff", "tif"]),
+    image_format("Icons", ImageFormat::Ico, &["ico"]),
+    image_format("QOI Images", ImageFormat::Qoi, &["qoi"]),
+    image_format("TGA Images", ImageFormat::Tga, &["tga"]),
+    image_format("PNM Images", ImageFormat::Pnm, &["pbm", "pgm", "ppm", "pam", "pnm"]),
+    image_format("DDS Textures", ImageFormat::Dds, &["dds"]),
+    image_format("Radiance HDR Images", ImageFormat::Hdr, &["hdr"]),
+    image_format("OpenEXR Images", ImageFormat::OpenExr, &["exr"]),
+    InputFormat {
+        label: "Photoshop Documents",
+        format: SourceFormat::Psd,
+        extensions: psd::EXTENSIONS,
+        enabled: true,
+    },
+    InputFormat {
+        label: "HEIC Images",
+        format: SourceFormat::Heif,
+        extensions: heif::EXTENSIONS,
+        enabled: heif::ENABLED,
+    },
+    InputFormat {
+        label: "SVG Images",
+        format: SourceFormat::Svg,
+        extensions: svg::EXTENSIONS,
+        enabled: true,
+    },
+    InputFormat {
+        label: "Camera RAW",
+        format: SourceFormat::Raw,
+        extensions: raw::EXTENSIONS,
+        enabled: true,
+    },
+];
+
+/// Formats this build can read
+pub fn enabled_formats() -> impl Iterator<Item = &'static InputFormat> {
+    INPUT_FORMATS.iter().filter(|format| format.enabled)
+}
+
+/// Extensions of every format this build can read, e.g. for file dialog filters
+pub fn supported_extensions() -> Vec<&'static str> {
+    enabled_formats()
+        .flat_map(|format| format.extensions.iter().copied())
+        .collect()
+}
+
+/// Whether this build reads files with the given extension (case-insensitive)
+pub fn is_supported_extension(extension: &str) -> bool {
+    let extension = extension.to_lowercase();
+    enabled_formats().any(|format| format.extensions.contains(&extension.as_str()))
+}
+
+/// Whether this build reads the given format
+pub fn is_supported_format(format: SourceFormat) -> bool {
+    enabled_formats().any(|entry| entry.format == format)
+}
+
+/// Format named by an extension (lower case), whether or not this build reads it
+pub fn format_for_extension(extension: &str) -> Option<SourceFormat> {
+    INPUT_FORMATS
+        .iter()
+        .find(|format| format.extensions.contains(&extension))
+        .map(|format| format.format)
+}
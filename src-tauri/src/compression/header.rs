@@ -0,0 +1,351 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use image::codecs::bmp::BmpDecoder;
+use image::codecs::dds::DdsDecoder;
+use image::codecs::gif::GifDecoder;
+use image::codecs::hdr::{HdrAdapter, HdrDecoder};
+use image::codecs::ico::IcoDecoder;
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::openexr::OpenExrDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::pnm::PnmDecoder;
+use image::codecs::qoi::QoiDecoder;
+use image::codecs::tga::TgaDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind};
+use image::io::Reader as ImageReader;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult, Rgb, Rgb32FImage};
+
+use super::formats::format_for_extension;
+use super::heif;
+use super::psd;
+use super::raw;
+use super::svg;
+use super::tonemap::tone_map;
+use super::transform::{apply_orientation, swaps_dimensions};
+use super::limits::decoder_limits;
+use super::types::{DecodeLimits, RawConfig, SvgConfig};
+
+/// Bytes read from the start of a file to recognise its signature
+const SIGNATURE_LEN: usize = 32;
+
+/// Format of a source file: one the image crate decodes, or one with its own codec
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceFormat {
+    Image(ImageFormat),
+    Heif,  // decoded with libheif (`heif` feature)
+    Raw,   // camera RAW, from its embedded preview or developed with rawloader
+    Svg,   // rendered with resvg
+    Psd,   // flattened composite only
+}
+
+impl SourceFormat {
+    /// Upper-case display name, e.g. "JPEG", "HEIF" or "RAW"
+    pub fn name(self) -> String {
+        match self {
+            SourceFormat::Image(format) => format_name(format),
+            SourceFormat::Heif => "HEIF".to_string(),
+            SourceFormat::Raw => "RAW".to_string(),
+            SourceFormat::Svg => "SVG".to_string(),
+            SourceFormat::Psd => "PSD".to_string(),
+        }
+    }
+}
+
+/// Image properties read from the file header, without decoding any pixels
+#[derive(Clone, Copy, Debug)]
+pub struct ImageHeader {
+    pub format: SourceFormat,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: ColorType,  // what the image decodes to
+}
+
+impl ImageHeader {
+    fn from_decoder<'a>(format: ImageFormat, decoder: impl ImageDecoder<'a>) -> Self {
+        let (width, height) = decoder.dimensions();
+        Self {
+            format: SourceFormat::Image(format),
+            width,
+            height,
+            color_type: decoder.color_type(),
+        }
+    }
+
+    /// Channel layout, e.g. "RGB", "RGBA", "Gray" or "GrayAlpha"
+    pub fn color_type_name(&self) -> String {
+        color_type_name(self.color_type)
+    }
+
+    /// Bits per channel
+    pub fn bit_depth(&self) -> u8 {
+        bit_depth(self.color_type)
+    }
+}
+
+/// Channel layout of a decoded color type, e.g. "RGB" or "GrayAlpha"
+pub fn color_type_name(color_type: ColorType) -> String {
+    let name = match (color_type.has_color(), color_type.has_alpha()) {
+        (true, true) => "RGBA",
+        (true, false) => "RGB",
+        (false, true) => "GrayAlpha",
+        (false, false) => "Gray",
+    };
+    name.to_string()
+}
+
+/// Bits per channel of a decoded color type
+pub fn bit_depth(color_type: ColorType) -> u8 {
+    (color_type.bits_per_pixel() / color_type.channel_count().max(1) as u16) as u8
+}
+
+/// Read format, dimensions and color type from the file header.
+///
+/// The format is detected from the file contents (falling back to the extension),
+/// so only a few hundred bytes are read for most files.
+pub fn read_header(path: &Path, raw_config: &RawConfig) -> ImageResult<ImageHeader> {
+    match sniff_format(path)? {
+        Some(SourceFormat::Heif) => return heif::read_header(path),
+        Some(SourceFormat::Raw) => return raw::read_header(path, raw_config),
+        Some(SourceFormat::Svg) => return svg::read_header(path),
+        Some(SourceFormat::Psd) => return psd::read_header(path),
+        _ => {}
+    }
+
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format().ok_or_else(|| {
+        ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            ImageFormatHint::PathExtension(path.to_path_buf()),
+            UnsupportedErrorKind::Format(ImageFormatHint::PathExtension(path.to_path_buf())),
+        ))
+    })?;
+    let mut file = reader.into_inner();
+
+    let header = match format {
+        ImageFormat::Png => ImageHeader::from_decoder(format, PngDecoder::new(file)?),
+        ImageFormat::Jpeg => ImageHeader::from_decoder(format, JpegDecoder::new(file)?),
+        ImageFormat::Gif => ImageHeader::from_decoder(format, GifDecoder::new(file)?),
+        ImageFormat::Bmp => ImageHeader::from_decoder(format, BmpDecoder::new(file)?),
+        ImageFormat::Ico => ImageHeader::from_decoder(format, IcoDecoder::new(file)?),
+        ImageFormat::Tiff => ImageHeader::from_decoder(format, TiffDecoder::new(file)?),
+        ImageFormat::Qoi => ImageHeader::from_decoder(format, QoiDecoder::new(file)?),
+        ImageFormat::Tga => ImageHeader::from_decoder(format, TgaDecoder::new(file)?),
+        ImageFormat::Pnm => ImageHeader::from_decoder(format, PnmDecoder::new(file)?),
+        ImageFormat::Dds => ImageHeader::from_decoder(format, DdsDecoder::new(file)?),
+        ImageFormat::Hdr => ImageHeader::from_decoder(format, HdrAdapter::new(file)?),
+        ImageFormat::OpenExr => ImageHeader::from_decoder(format, OpenExrDecoder::new(file)?),
+        // image's WebP decoder reads the whole file up front, so parse the chunk headers here
+        ImageFormat::WebP => read_webp_header(&mut file)?,
+        _ => {
+            // No cheap header reader for this format, decode it fully
+            let img = ImageReader::with_format(file, format).decode()?;
+            ImageHeader {
+                format: SourceFormat::Image(format),
+                width: img.width(),
+                height: img.height(),
+                color_type: img.color(),
+            }
+        }
+    };
+
+    // Dimensions of the upright image, as `open_image` returns it
+    if swaps_dimensions(exif_orientation(path, format)) {
+        return Ok(ImageHeader { width: header.height, height: header.width, ..header });
+    }
+
+    Ok(header)
+}
+
+/// Format of a file judged by its signature (magic bytes), `None` if unrecognised.
+/// RAW formats that are plain TIFF files and XML-prolog SVGs are told apart by their extension.
+pub fn sniff_format(path: &Path) -> std::io::Result<Option<SourceFormat>> {
+    let mut buf = Vec::with_capacity(SIGNATURE_LEN);
+    File::open(path)?.take(SIGNATURE_LEN as u64).read_to_end(&mut buf)?;
+
+    if heif::is_heif_signature(&buf) {
+        return Ok(Some(SourceFormat::Heif));
+    }
+    if raw::is_raw(path, &buf) {
+        return Ok(Some(SourceFormat::Raw));
+    }
+    if svg::is_svg(path, &buf) {
+        return Ok(Some(SourceFormat::Svg));
+    }
+    if psd::is_psd_signature(&buf) {
+        return Ok(Some(SourceFormat::Psd));
+    }
+    Ok(image::guess_format(&buf).ok().map(SourceFormat::Image))
+}
+
+/// Format named by the file extension, if any
+pub fn extension_format(path: &Path) -> Option<SourceFormat> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    format_for_extension(&extension).or_else(|| ImageFormat::from_extension(extension).map(SourceFormat::Image))
+}
+
+/// Whether the extension is missing or names a different format than the file contents
+pub fn is_extension_mismatch(path: &Path, content_format: SourceFormat) -> bool {
+    extension_format(path) != Some(content_format)
+}
+
+/// EXIF orientation (1-8, 1 = upright) of an image the image crate decodes. Other sources
+/// are decoded upright, so 1 for those.
+pub fn read_orientation(path: &Path) -> u16 {
+    match sniff_format(path) {
+        Ok(Some(SourceFormat::Image(format))) => exif_orientation(path, format),
+        _ => 1,
+    }
+}
+
+/// EXIF orientation of a file in one of the formats that carry EXIF, 1 if it has none
+fn exif_orientation(path: &Path, format: ImageFormat) -> u16 {
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Tiff | ImageFormat::WebP) {
+        return 1;
+    }
+
+    let Ok(file) = File::open(path) else { return 1 };
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+        .and_then(|exif| exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?.value.get_uint(0))
+        .and_then(|orientation| u16::try_from(orientation).ok())
+        .filter(|orientation| (1..=8).contains(orientation))
+        .unwrap_or(1)
+}
+
+/// Metadata carried from a source image into JPEG outputs
+#[derive(Clone, Debug, Default)]
+pub struct SourceMetadata {
+    pub exif: Option<Vec<u8>>,  // TIFF structure, without the "Exif\0\0" prefix
+    pub icc: Option<Vec<u8>>,
+}
+
+impl SourceMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc.is_none()
+    }
+}
+
+/// Metadata to carry into JPEG outputs (currently only read from HEIF sources)
+pub fn read_metadata(path: &Path) -> SourceMetadata {
+    match sniff_format(path) {
+        Ok(Some(SourceFormat::Heif)) => heif::read_metadata(path).unwrap_or_default(),
+        _ => SourceMetadata::default(),
+    }
+}
+
+/// Decode an image, detecting the format from its contents rather than its extension.
+/// The decoder may not allocate more than the configured limit. Floating-point images
+/// (HDR, OpenEXR) are tone-mapped to 8 bits, and images are turned upright according
+/// to their EXIF orientation.
+pub fn open_image(
+    path: &Path,
+    limits: &DecodeLimits,
+    raw_config: &RawConfig,
+    svg_config: &SvgConfig,
+) -> ImageResult<DynamicImage> {
+    match sniff_format(path)? {
+        Some(SourceFormat::Heif) => return heif::decode(path, limits),
+        Some(SourceFormat::Raw) => return raw::decode(path, limits, raw_config),
+        Some(SourceFormat::Svg) => return svg::decode(path, limits, svg_config),
+        Some(SourceFormat::Psd) => return psd::decode(path, limits),
+        Some(SourceFormat::Image(ImageFormat::Hdr)) => return decode_radiance(path, limits).map(tone_map),
+        _ => {}
+    }
+
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(decoder_limits(limits));
+    let orientation = reader.format().map_or(1, |format| exif_orientation(path, format));
+    let img = reader.decode().map(tone_map)?;
+    Ok(apply_orientation(img, orientation))
+}
+
+/// Decode a Radiance HDR file to floating point. image's own decoder for it clamps
+/// to 8 bits, which would leave nothing to tone-map.
+fn decode_radiance(path: &Path, limits: &DecodeLimits) -> ImageResult<DynamicImage> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let metadata = decoder.metadata();
+
+    let bytes = metadata.width as u64 * metadata.height as u64 * std::mem::size_of::<Rgb<f32>>() as u64;
+    if bytes > limits.max_alloc_mb.saturating_mul(1024 * 1024) {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+    }
+
+    let data = decoder.read_image_hdr()?.into_iter().flat_map(|pixel| pixel.0).collect();
+    Rgb32FImage::from_raw(metadata.width, metadata.height, data)
+        .map(DynamicImage::ImageRgb32F)
+        .ok_or_else(|| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Exact(ImageFormat::Hdr),
+                "Pixel data does not match the image size",
+            ))
+        })
+}
+
+/// Upper-case display name of a format, e.g. "JPEG" or "WEBP"
+pub fn format_name(format: ImageFormat) -> String {
+    match format {
+        ImageFormat::Jpeg => "JPEG".to_string(),
+        ImageFormat::Tiff => "TIFF".to_string(),
+        ImageFormat::Pnm => "PNM".to_string(),
+        ImageFormat::OpenExr => "EXR".to_string(),
+        format => format
+            .extensions_str()
+            .first()
+            .map(|ext| ext.to_uppercase())
+            .unwrap_or_else(|| "UNKNOWN".to_string()),
+    }
+}
+
+/// Parse the RIFF header of a WebP file (simple lossy, lossless or extended)
+fn read_webp_header(file: &mut BufReader<File>) -> ImageResult<ImageHeader> {
+    let invalid = |message: &str| {
+        ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::WebP), message.to_string()))
+    };
+
+    let mut buf = [0u8; 30];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut buf)
+        .map_err(|_| invalid("File too short for a WebP header"))?;
+
+    if &buf[0..4] != b"RIFF" || &buf[8..12] != b"WEBP" {
+        return Err(invalid("Missing RIFF/WEBP signature"));
+    }
+
+    let u24 = |b: &[u8]| u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16;
+
+    let (width, height, has_alpha) = match &buf[12..16] {
+        // Extended: flags byte, 3 reserved bytes, then 24-bit canvas size minus one
+        b"VP8X" => (u24(&buf[24..27]) + 1, u24(&buf[27..30]) + 1, buf[20] & 0x10 != 0),
+        // Lossy: 3-byte frame tag, start code, then 14-bit width and height
+        b"VP8 " => {
+            if buf[23..26] != [0x9d, 0x01, 0x2a] {
+                return Err(invalid("Missing VP8 start code"));
+            }
+            let width = u32::from(u16::from_le_bytes([buf[26], buf[27]]) & 0x3fff);
+            let height = u32::from(u16::from_le_bytes([buf[28], buf[29]]) & 0x3fff);
+            (width, height, false)
+        }
+        // Lossless: signature byte, then 14-bit width and height minus one and an alpha hint
+        b"VP8L" => {
+            if buf[20] != 0x2f {
+                return Err(invalid("Missing VP8L signature"));
+            }
+            let bits = u32::from_le_bytes([buf[21], buf[22], buf[23], buf[24]]);
+            ((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1, (bits >> 28) & 1 != 0)
+        }
+        _ => return Err(invalid("Unknown WebP chunk")),
+    };
+
+    if width == 0 || height == 0 {
+        return Err(invalid("WebP image has no pixels"));
+    }
+
+    Ok(ImageHeader {
+        format: SourceFormat::Image(ImageFormat::WebP),
+        width,
+        height,
+        color_type: if has_alpha { ColorType::Rgba8 } else { ColorType::Rgb8 },
+    })
+}
@@ -0,0 +1,254 @@
+use std::path::Path;
+use image::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{DynamicImage, ImageError, ImageResult};
+
+use super::header::{ImageHeader, SourceMetadata};
+use super::types::DecodeLimits;
+
+#[cfg(feature = "heif")]
+use std::fs::File;
+#[cfg(feature = "heif")]
+use std::io::BufReader;
+#[cfg(feature = "heif")]
+use image::error::{DecodingError, LimitError, LimitErrorKind};
+#[cfg(feature = "heif")]
+use image::{ColorType, ImageBuffer, Rgb, Rgba};
+#[cfg(feature = "heif")]
+use libheif_rs::{ColorSpace, HeifContext, HeifError, ImageHandle, LibHeif, RgbChroma, StreamReader};
+#[cfg(feature = "heif")]
+use super::header::SourceFormat;
+
+/// File extensions of HEIF images (HEIC is HEIF with HEVC-coded pixels)
+pub const EXTENSIONS: &[&str] = &["heic", "heif", "hif"];
+
+/// Whether this build decodes HEIF images (the `heif` cargo feature)
+pub const ENABLED: bool = cfg!(feature = "heif");
+
+/// `ftyp` brands of HEIF images with HEVC-coded pixels
+const HEVC_BRANDS: &[&[u8]] = &[b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs"];
+
+/// Whether the start of a file is the `ftyp` box of a HEIF image.
+/// The generic `mif1`/`msf1` brands are shared with AVIF, so for those the compatible
+/// brands decide.
+pub fn is_heif_signature(buf: &[u8]) -> bool {
+    if buf.len() < 12 || &buf[4..8] != b"ftyp" {
+        return false;
+    }
+
+    let is_hevc_brand = |brand: &[u8]| HEVC_BRANDS.contains(&brand);
+    let major = &buf[8..12];
+    if is_hevc_brand(major) {
+        return true;
+    }
+    if major != b"mif1" && major != b"msf1" {
+        return false;
+    }
+
+    // Compatible brands follow the minor version, up to the end of the box
+    let box_size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    buf.get(16..box_size.min(buf.len()))
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .any(is_hevc_brand)
+}
+
+/// Read dimensions (after rotation) and color type without decoding any pixels
+#[cfg(feature = "heif")]
+pub fn read_header(path: &Path) -> ImageResult<ImageHeader> {
+    let context = open_context(path)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+
+    let color_type = match (handle.has_alpha_channel(), is_high_depth(&handle)) {
+        (false, false) => ColorType::Rgb8,
+        (true, false) => ColorType::Rgba8,
+        (false, true) => ColorType::Rgb16,
+        (true, true) => ColorType::Rgba16,
+    };
+
+    Ok(ImageHeader {
+        format: SourceFormat::Heif,
+        width: handle.width(),
+        height: handle.height(),
+        color_type,
+    })
+}
+
+/// Decode the primary image to RGB(A), 16 bits per channel for HDR sources.
+/// libheif applies the rotation and mirroring stored in the container while decoding.
+#[cfg(feature = "heif")]
+pub fn decode(path: &Path, limits: &DecodeLimits) -> ImageResult<DynamicImage> {
+    let context = open_context(path)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+
+    let has_alpha = handle.has_alpha_channel();
+    let high_depth = is_high_depth(&handle);
+    let channels = if has_alpha { 4 } else { 3 };
+    let sample_size = if high_depth { 2 } else { 1 };
+
+    // libheif allocates outside the image crate, so enforce its limit up front
+    let bytes = handle.width() as u64 * handle.height() as u64 * channels * sample_size;
+    if bytes > limits.max_alloc_mb.saturating_mul(1024 * 1024) {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+    }
+
+    let chroma = match (has_alpha, high_depth) {
+        (false, false) => RgbChroma::Rgb,
+        (true, false) => RgbChroma::Rgba,
+        (false, true) => RgbChroma::HdrRgbLe,
+        (true, true) => RgbChroma::HdrRgbaLe,
+    };
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), None)
+        .map_err(heif_error)?;
+
+    let planes = image.planes();
+    let plane = planes.interleaved.ok_or_else(|| decoding_error("Decoded image has no interleaved plane"))?;
+    let (width, height) = (plane.width, plane.height);
+
+    // Rows are padded to the stride
+    let row_len = width as usize * channels as usize * sample_size as usize;
+    let mut data = Vec::with_capacity(row_len * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        data.extend_from_slice(row.get(..row_len).ok_or_else(|| decoding_error("Truncated pixel row"))?);
+    }
+
+    let img = if high_depth {
+        // Stretch 10 or 12 significant bits to the full 16-bit range
+        let bits = u32::from(handle.luma_bits_per_pixel()).clamp(9, 16);
+        let samples: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|b| {
+                let value = u16::from_le_bytes([b[0], b[1]]);
+                (value << (16 - bits)) | (value >> (2 * bits - 16))
+            })
+            .collect();
+
+        if has_alpha {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+        } else {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgb16)
+        }
+    } else if has_alpha {
+        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+    } else {
+        ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+    };
+
+    img.ok_or_else(|| decoding_error("Pixel data does not match the image size"))
+}
+
+/// EXIF and ICC profile of the primary image
+#[cfg(feature = "heif")]
+pub fn read_metadata(path: &Path) -> ImageResult<SourceMetadata> {
+    let context = open_context(path)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+
+    let mut ids = [0; 1];
+    let exif = if handle.metadata_block_ids(&mut ids, b"Exif") > 0 {
+        handle.metadata(ids[0])
+            .ok()
+            .and_then(|block| tiff_from_exif_block(&block))
+            .map(|mut tiff| {
+                // The pixels are already upright, viewers must not rotate them again
+                reset_orientation(&mut tiff);
+                tiff
+            })
+    } else {
+        None
+    };
+
+    Ok(SourceMetadata {
+        exif,
+        icc: handle.color_profile_raw().map(|profile| profile.data),
+    })
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn read_header(path: &Path) -> ImageResult<ImageHeader> {
+    Err(unsupported(path))
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn decode(path: &Path, _limits: &DecodeLimits) -> ImageResult<DynamicImage> {
+    Err(unsupported(path))
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn read_metadata(path: &Path) -> ImageResult<SourceMetadata> {
+    Err(unsupported(path))
+}
+
+/// Error for HEIF files in a build without the `heif` feature
+#[cfg_attr(feature = "heif", allow(dead_code))]
+fn unsupported(path: &Path) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::PathExtension(path.to_path_buf()),
+        UnsupportedErrorKind::Format(ImageFormatHint::Name("HEIF".to_string())),
+    ))
+}
+
+#[cfg(feature = "heif")]
+fn open_context(path: &Path) -> ImageResult<HeifContext<'static>> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    HeifContext::read_from_reader(Box::new(StreamReader::new(BufReader::new(file), size))).map_err(heif_error)
+}
+
+#[cfg(feature = "heif")]
+fn is_high_depth(handle: &ImageHandle) -> bool {
+    handle.luma_bits_per_pixel() > 8
+}
+
+#[cfg(feature = "heif")]
+fn heif_error(error: HeifError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("HEIF".to_string()), error))
+}
+
+#[cfg(feature = "heif")]
+fn decoding_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("HEIF".to_string()), message.to_string()))
+}
+
+/// The TIFF structure of a HEIF `Exif` item, which starts with the offset to the TIFF header
+#[cfg(feature = "heif")]
+fn tiff_from_exif_block(block: &[u8]) -> Option<Vec<u8>> {
+    let offset = u32::from_be_bytes(block.get(0..4)?.try_into().ok()?) as usize;
+    block.get(4usize.checked_add(offset)?..).map(|tiff| tiff.to_vec())
+}
+
+/// Set the orientation tag in IFD0 to 1 (upright), if present
+#[cfg(feature = "heif")]
+fn reset_orientation(tiff: &mut [u8]) {
+    const ORIENTATION: u16 = 0x0112;
+
+    let big_endian = match tiff.get(0..2) {
+        Some(b"MM") => true,
+        Some(b"II") => false,
+        _ => return,
+    };
+    let read_u16 = |tiff: &[u8], at: usize| {
+        tiff.get(at..at + 2).map(|b| {
+            if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) }
+        })
+    };
+    let read_u32 = |tiff: &[u8], at: usize| {
+        tiff.get(at..at + 4).map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+        })
+    };
+
+    let Some(ifd) = read_u32(tiff, 4).map(|offset| offset as usize) else { return };
+    let Some(count) = read_u16(tiff, ifd) else { return };
+
+    // 12-byte entries: tag, type, count, then the value (a SHORT sits in the first two bytes)
+    for entry in (0..count as usize).map(|i| ifd + 2 + i * 12) {
+        if read_u16(tiff, entry) == Some(ORIENTATION) {
+            let upright = if big_endian { 1u16.to_be_bytes() } else { 1u16.to_le_bytes() };
+            if let Some(value) = tiff.get_mut(entry + 8..entry + 10) {
+                value.copy_from_slice(&upright);
+            }
+            return;
+        }
+    }
+}
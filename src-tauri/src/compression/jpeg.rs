@@ -0,0 +1,271 @@
+use std::panic::{self, AssertUnwindSafe};
+use image::RgbImage;
+use jpeg_encoder::{ColorType, QuantizationTableType, SamplingFactor};
+use mozjpeg::qtable::{self, QTable};
+
+use super::error::{panic_message, CompressionError};
+use super::header::SourceMetadata;
+use super::types::{ChromaSubsampling, JpegBackend, JpegEncoderConfig, QuantTablePreset, QuantTables};
+
+/// Largest ICC profile chunk in one APP2 segment
+const ICC_CHUNK_LEN: usize = 65519;
+
+/// EXIF tag of the orientation
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Encode an RGB image to JPEG with the configured backend and options
+pub fn encode_jpeg(rgb: &RgbImage, quality: f32, config: &JpegEncoderConfig) -> Result<Vec<u8>, CompressionError> {
+    match config.backend {
+        JpegBackend::Mozjpeg => encode_mozjpeg(rgb, quality, config),
+        JpegBackend::Rust => encode_rust(rgb, quality, config),
+    }
+}
+
+fn encode_failed(cause: impl ToString) -> CompressionError {
+    CompressionError::EncodeFailed {
+        format: "JPEG".to_string(),
+        cause: cause.to_string(),
+    }
+}
+
+fn encode_mozjpeg(rgb: &RgbImage, quality: f32, config: &JpegEncoderConfig) -> Result<Vec<u8>, CompressionError> {
+    // libjpeg reports errors by unwinding, so catch them per image
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> std::io::Result<Vec<u8>> {
+        let mut comp = mozjpeg::Compress::new(mozjpeg::ColorSpace::JCS_RGB);
+
+        // The default profile enables trellis quantization; the fastest one is plain libjpeg
+        if !config.trellis {
+            comp.set_fastest_defaults();
+        }
+
+        comp.set_size(rgb.width() as usize, rgb.height() as usize);
+        comp.set_quality(quality);
+
+        if let Some(QuantTables::Preset { preset }) = &config.quant_tables {
+            let (luma, chroma) = mozjpeg_tables(*preset);
+            comp.set_luma_qtable(&luma.scaled(quality, quality));
+            comp.set_chroma_qtable(&chroma.scaled(quality, quality));
+        }
+
+        if config.progressive {
+            comp.set_progressive_mode();
+        } else {
+            comp.set_optimize_scans(false);
+        }
+        comp.set_optimize_coding(config.optimize_huffman || config.progressive);
+
+        let pixel_size = match config.subsampling {
+            ChromaSubsampling::Full => (1, 1),
+            ChromaSubsampling::Horizontal => (2, 1),
+            ChromaSubsampling::Both => (2, 2),
+        };
+        comp.set_chroma_sampling_pixel_sizes(pixel_size, pixel_size);
+
+        let mut started = comp.start_compress(Vec::new())?;
+        started.write_scanlines(rgb.as_raw())?;
+        started.finish()
+    }));
+
+    match result {
+        Ok(encoded) => encoded.map_err(encode_failed),
+        Err(payload) => {
+            let cause = panic_message(payload.as_ref()).unwrap_or_else(|| "mozjpeg error".to_string());
+            Err(encode_failed(cause))
+        }
+    }
+}
+
+/// Luma and chroma tables of a preset, unscaled
+fn mozjpeg_tables(preset: QuantTablePreset) -> (&'static QTable, &'static QTable) {
+    match preset {
+        QuantTablePreset::AnnexK => (&qtable::AnnexK_Luma, &qtable::AnnexK_Chroma),
+        QuantTablePreset::Flat => (&qtable::Flat, &qtable::Flat),
+        QuantTablePreset::MsSsim => (&qtable::MSSSIM_Luma, &qtable::MSSSIM_Chroma),
+        QuantTablePreset::PsnrHvs => (&qtable::PSNRHVS_Luma, &qtable::PSNRHVS_Chroma),
+        QuantTablePreset::KleinSilversteinCarney => (&qtable::KleinSilversteinCarney, &qtable::KleinSilversteinCarney),
+    }
+}
+
+fn encode_rust(rgb: &RgbImage, quality: f32, config: &JpegEncoderConfig) -> Result<Vec<u8>, CompressionError> {
+    let (width, height) = (rgb.width(), rgb.height());
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(encode_failed(format!("{}×{} exceeds the JPEG size limit of 65535 pixels", width, height)));
+    }
+
+    let mut buffer = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut buffer, quality as u8);
+
+    encoder.set_progressive(config.progressive);
+    encoder.set_optimized_huffman_tables(config.optimize_huffman || config.progressive);
+    encoder.set_sampling_factor(match config.subsampling {
+        ChromaSubsampling::Full => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Horizontal => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Both => SamplingFactor::R_4_2_0,
+    });
+
+    match &config.quant_tables {
+        Some(QuantTables::Preset { preset }) => {
+            let table = match preset {
+                QuantTablePreset::AnnexK => QuantizationTableType::Default,
+                QuantTablePreset::Flat => QuantizationTableType::Flat,
+                QuantTablePreset::MsSsim => QuantizationTableType::CustomMsSsim,
+                QuantTablePreset::PsnrHvs => QuantizationTableType::CustomPsnrHvs,
+                QuantTablePreset::KleinSilversteinCarney => QuantizationTableType::KleinSilversteinCarney,
+            };
+            encoder.set_quantization_tables(table.clone(), table);
+        }
+        Some(QuantTables::Custom { luma, chroma }) => {
+            encoder.set_quantization_tables(custom_table(luma)?, custom_table(chroma)?);
+        }
+        None => {}
+    }
+
+    encoder
+        .encode(rgb.as_raw(), width as u16, height as u16, ColorType::Rgb)
+        .map_err(encode_failed)?;
+
+    Ok(buffer)
+}
+
+fn custom_table(values: &[u16]) -> Result<QuantizationTableType, CompressionError> {
+    let table: [u16; 64] = values
+        .try_into()
+        .map_err(|_| CompressionError::invalid_setting("jpeg.quant_tables", values.len(), "64 values"))?;
+    Ok(QuantizationTableType::Custom(Box::new(table)))
+}
+
+/// Insert EXIF (APP1) and ICC profile (APP2) segments into an encoded JPEG, after
+/// its JFIF header if it has one. Outputs are encoded upright, so the EXIF orientation
+/// is set to 1.
+pub fn insert_metadata(jpeg: Vec<u8>, metadata: &SourceMetadata) -> Vec<u8> {
+    if metadata.is_empty() || !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return jpeg;
+    }
+
+    let mut insert_at = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+        if let Some(len) = jpeg.get(4..6) {
+            insert_at = (4 + u16::from_be_bytes([len[0], len[1]]) as usize).min(jpeg.len());
+        }
+    }
+
+    let mut out = Vec::with_capacity(jpeg.len() + 1024);
+    out.extend_from_slice(&jpeg[..insert_at]);
+
+    // Too large for one segment: dropped rather than split, as EXIF can't be continued
+    if let Some(exif) = metadata.exif.as_deref().filter(|exif| exif.len() <= 0xFFFF - 8) {
+        let mut exif = exif.to_vec();
+        reset_orientation(&mut exif);
+        write_segment(&mut out, 0xE1, &[b"Exif\0\0", &exif]);
+    }
+
+    // Profiles larger than one segment are split into numbered chunks
+    if let Some(icc) = &metadata.icc {
+        let count = icc.len().div_ceil(ICC_CHUNK_LEN);
+        if (1..=255).contains(&count) {
+            for (index, chunk) in icc.chunks(ICC_CHUNK_LEN).enumerate() {
+                write_segment(&mut out, 0xE2, &[b"ICC_PROFILE\0", &[index as u8 + 1, count as u8], chunk]);
+            }
+        }
+    }
+
+    out.extend_from_slice(&jpeg[insert_at..]);
+    out
+}
+
+/// Set the orientation in the first IFD of an EXIF TIFF structure to 1 (upright), in place.
+/// Structures that can't be parsed are left as they are.
+pub fn reset_orientation(tiff: &mut [u8]) {
+    let big_endian = match tiff.get(..2) {
+        Some(b"MM") => true,
+        Some(b"II") => false,
+        _ => return,
+    };
+    let u16_at = |tiff: &[u8], at: usize| {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let Some(ifd) = tiff.get(4..8).and_then(|bytes| bytes.try_into().ok()) else { return };
+    let ifd = if big_endian { u32::from_be_bytes(ifd) } else { u32::from_le_bytes(ifd) } as usize;
+
+    let count = u16_at(tiff, ifd).unwrap_or(0) as usize;
+    for entry in (0..count).map(|index| ifd + 2 + index * 12) {
+        // A SHORT value is stored in the first bytes of the entry's value field
+        if u16_at(tiff, entry) == Some(ORIENTATION_TAG) && u16_at(tiff, entry + 2) == Some(3) {
+            let upright = if big_endian { 1u16.to_be_bytes() } else { 1u16.to_le_bytes() };
+            if let Some(value) = tiff.get_mut(entry + 8..entry + 10) {
+                value.copy_from_slice(&upright);
+            }
+        }
+    }
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, parts: &[&[u8]]) {
+    let len = 2 + parts.iter().map(|part| part.len()).sum::<usize>();
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TIFF structure with the orientation as the second of two entries in its first IFD
+    fn tiff(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+        let mut tiff = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        tiff.extend_from_slice(&u32_bytes(8));
+        tiff.extend_from_slice(&u16_bytes(2));
+        for (tag, value) in [(0x0100, 640), (ORIENTATION_TAG, orientation)] {
+            tiff.extend_from_slice(&u16_bytes(tag));
+            tiff.extend_from_slice(&u16_bytes(3));
+            tiff.extend_from_slice(&u32_bytes(1));
+            tiff.extend_from_slice(&u16_bytes(value));
+            tiff.extend_from_slice(&[0, 0]);
+        }
+        tiff.extend_from_slice(&u32_bytes(0));
+        tiff
+    }
+
+    #[test]
+    fn resets_the_orientation_only() {
+        for big_endian in [false, true] {
+            let mut exif = tiff(big_endian, 6);
+            reset_orientation(&mut exif);
+            assert_eq!(exif, tiff(big_endian, 1));
+        }
+    }
+
+    #[test]
+    fn leaves_unparseable_exif_alone() {
+        let mut truncated = tiff(false, 6);
+        truncated.truncate(20);
+        let expected = truncated.clone();
+        reset_orientation(&mut truncated);
+        assert_eq!(truncated, expected);
+
+        let mut garbage = b"XX\0*\x08\0\0\0".to_vec();
+        reset_orientation(&mut garbage);
+        assert_eq!(garbage, b"XX\0*\x08\0\0\0");
+    }
+
+    #[test]
+    fn inserts_upright_exif_after_the_jfif_header() {
+        let jpeg = [&[0xFF, 0xD8, 0xFF, 0xE0, 0, 4, b'J', b'F'][..], &[0xFF, 0xD9]].concat();
+        let metadata = SourceMetadata { exif: Some(tiff(false, 8)), icc: None };
+
+        let out = insert_metadata(jpeg, &metadata);
+
+        let exif = tiff(false, 1);
+        assert_eq!(&out[..8], &[0xFF, 0xD8, 0xFF, 0xE0, 0, 4, b'J', b'F']);
+        assert_eq!(&out[8..12], &[0xFF, 0xE1, 0, (2 + 6 + exif.len()) as u8]);
+        assert_eq!(&out[12..18], b"Exif\0\0");
+        assert_eq!(&out[18..18 + exif.len()], &exif[..]);
+        assert_eq!(&out[18 + exif.len()..], &[0xFF, 0xD9]);
+    }
+}
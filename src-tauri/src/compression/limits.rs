@@ -0,0 +1,177 @@
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use image::io::Limits;
+
+use super::error::CompressionError;
+use super::types::DecodeLimits;
+
+const MB: u64 = 1024 * 1024;
+
+/// Memory an image is expected to need while it is processed: 8-bit RGBA pixels
+/// plus one working copy for resizing or editing
+pub fn estimated_memory(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64 * 4 * 2
+}
+
+/// Reject an image whose dimensions exceed the pixel limit, before anything is decoded
+pub fn check_dimensions(path: &Path, width: u32, height: u32, limits: &DecodeLimits) -> Result<(), CompressionError> {
+    let pixels = width as u64 * height as u64;
+    if pixels <= limits.max_pixels {
+        return Ok(());
+    }
+
+    Err(CompressionError::ImageTooLarge {
+        path: path.display().to_string(),
+        cause: format!(
+            "{}×{} pixels exceeds the limit of {:.1} megapixels",
+            width,
+            height,
+            limits.max_pixels as f64 / 1_000_000.0
+        ),
+    })
+}
+
+/// Decoder limits for the image crate
+pub fn decoder_limits(limits: &DecodeLimits) -> Limits {
+    let mut decoder_limits = Limits::default();
+    decoder_limits.max_alloc = Some(limits.max_alloc_mb.saturating_mul(MB));
+    decoder_limits
+}
+
+/// Memory shared by all images processed at the same time.
+///
+/// Each worker reserves the estimated memory of its image before decoding and waits
+/// while the reservation doesn't fit. An image larger than the whole budget still runs,
+/// but only once nothing else is in flight.
+pub struct MemoryBudget {
+    capacity: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(limits: &DecodeLimits) -> Self {
+        Self {
+            capacity: limits.memory_budget_mb.saturating_mul(MB),
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until `bytes` fit into the budget and reserve them until the permit is dropped
+    pub fn acquire(&self, bytes: u64) -> MemoryPermit<'_> {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        while *used > 0 && used.saturating_add(bytes) > self.capacity {
+            used = self.released.wait(used).unwrap_or_else(|e| e.into_inner());
+        }
+        *used += bytes;
+
+        MemoryPermit { budget: self, bytes }
+    }
+}
+
+/// Reserved share of a `MemoryBudget`, released on drop
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        let mut used = self.budget.used.lock().unwrap_or_else(|e| e.into_inner());
+        *used -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+/// Check an image against the pixel limit and reserve its memory for processing
+pub fn admit<'a>(
+    path: &Path,
+    (width, height): (u32, u32),
+    limits: &DecodeLimits,
+    budget: &'a MemoryBudget,
+) -> Result<MemoryPermit<'a>, CompressionError> {
+    check_dimensions(path, width, height, limits)?;
+    Ok(budget.acquire(estimated_memory(width, height)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limits(max_pixels: u64, memory_budget_mb: u64) -> DecodeLimits {
+        DecodeLimits { max_pixels, memory_budget_mb, ..DecodeLimits::default() }
+    }
+
+    /// Whether `acquire(bytes)` waits for `held` to be released
+    fn waits_for(budget: &MemoryBudget, held: MemoryPermit<'_>, bytes: u64) -> bool {
+        let admitted = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let _permit = budget.acquire(bytes);
+                admitted.store(true, Ordering::SeqCst);
+            });
+            thread::sleep(Duration::from_millis(50));
+            let waited = !admitted.load(Ordering::SeqCst);
+            drop(held);
+            waited
+        })
+    }
+
+    #[test]
+    fn rejects_images_over_the_pixel_limit() {
+        let path = Path::new("a.png");
+        assert!(check_dimensions(path, 10, 10, &limits(100, 1)).is_ok());
+
+        let error = check_dimensions(path, 11, 10, &limits(100, 1)).unwrap_err();
+        assert!(matches!(error, CompressionError::ImageTooLarge { .. }));
+        assert!(error.to_string().contains("11×10 pixels"));
+
+        let budget = MemoryBudget::new(&limits(100, 1));
+        assert!(admit(path, (11, 10), &limits(100, 1), &budget).is_err());
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn admits_images_while_they_fit() {
+        let budget = MemoryBudget::new(&limits(u64::MAX, 1));
+        let first = budget.acquire(MB / 2);
+        assert!(!waits_for(&budget, first, MB / 2));
+
+        let first = budget.acquire(MB / 2);
+        let _second = budget.acquire(MB / 2);
+        assert_eq!(*budget.used.lock().unwrap(), MB);
+        drop(first);
+        assert_eq!(*budget.used.lock().unwrap(), MB / 2);
+    }
+
+    #[test]
+    fn waits_for_memory_to_be_released() {
+        let budget = MemoryBudget::new(&limits(u64::MAX, 1));
+        let held = budget.acquire(MB / 2);
+        assert!(waits_for(&budget, held, MB / 2 + 1));
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn runs_oversized_images_alone() {
+        let budget = MemoryBudget::new(&limits(u64::MAX, 1));
+        // Nothing else is in flight, so it runs despite the budget
+        let oversized = budget.acquire(10 * MB);
+        assert!(waits_for(&budget, oversized, 1));
+
+        let small = budget.acquire(1);
+        assert!(waits_for(&budget, small, 10 * MB));
+    }
+
+    #[test]
+    fn estimates_memory_and_decoder_allocations() {
+        assert_eq!(estimated_memory(1000, 500), 4_000_000);
+        let limits = DecodeLimits { max_alloc_mb: 64, ..DecodeLimits::default() };
+        assert_eq!(decoder_limits(&limits).max_alloc, Some(64 * MB));
+    }
+}
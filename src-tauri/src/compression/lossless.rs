@@ -0,0 +1,314 @@
+use std::mem;
+use std::os::raw::{c_int, c_uint, c_ulong};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use mozjpeg_sys::*;
+
+use super::error::{panic_message, CompressionError};
+use super::jpeg::reset_orientation;
+use super::types::{FlipDirection, JpegMetadata, LosslessJpegConfig, TransformOp};
+
+/// Lossless JPEG transform: a jpegtran transform code plus grayscale conversion
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LosslessTransform {
+    code: JXFORM_CODE,
+    grayscale: bool,
+}
+
+impl LosslessTransform {
+    /// Combine editing operations into one lossless transform.
+    /// Returns `None` if any operation has to change pixel values.
+    pub fn from_ops(ops: &[TransformOp]) -> Option<Self> {
+        // Each operation as a 2×2 matrix on (x, y) with y pointing down
+        let mut matrix = [[1, 0], [0, 1]];
+        let mut grayscale = false;
+
+        for op in ops {
+            let step = match *op {
+                TransformOp::Rotate { degrees: 90 } => [[0, -1], [1, 0]],
+                TransformOp::Rotate { degrees: 180 } => [[-1, 0], [0, -1]],
+                TransformOp::Rotate { degrees: 270 } => [[0, 1], [-1, 0]],
+                TransformOp::Rotate { .. } => return None,
+                TransformOp::Flip { direction: FlipDirection::Horizontal } => [[-1, 0], [0, 1]],
+                TransformOp::Flip { direction: FlipDirection::Vertical } => [[1, 0], [0, -1]],
+                TransformOp::Grayscale => {
+                    grayscale = true;
+                    continue;
+                }
+                _ => return None,
+            };
+            matrix = multiply(step, matrix);
+        }
+
+        let code = match matrix {
+            [[1, 0], [0, 1]] => JXFORM_CODE_JXFORM_NONE,
+            [[-1, 0], [0, 1]] => JXFORM_CODE_JXFORM_FLIP_H,
+            [[1, 0], [0, -1]] => JXFORM_CODE_JXFORM_FLIP_V,
+            [[0, 1], [1, 0]] => JXFORM_CODE_JXFORM_TRANSPOSE,
+            [[0, -1], [-1, 0]] => JXFORM_CODE_JXFORM_TRANSVERSE,
+            [[0, -1], [1, 0]] => JXFORM_CODE_JXFORM_ROT_90,
+            [[-1, 0], [0, -1]] => JXFORM_CODE_JXFORM_ROT_180,
+            [[0, 1], [-1, 0]] => JXFORM_CODE_JXFORM_ROT_270,
+            _ => unreachable!("rotations and flips only produce these matrices"),
+        };
+
+        Some(Self { code, grayscale })
+    }
+}
+
+fn multiply(a: [[i32; 2]; 2], b: [[i32; 2]; 2]) -> [[i32; 2]; 2] {
+    [
+        [a[0][0] * b[0][0] + a[0][1] * b[1][0], a[0][0] * b[0][1] + a[0][1] * b[1][1]],
+        [a[1][0] * b[0][0] + a[1][1] * b[1][0], a[1][0] * b[0][1] + a[1][1] * b[1][1]],
+    ]
+}
+
+/// Rewrite a JPEG file without decoding it to pixels: the DCT coefficients are kept,
+/// Huffman tables are optimized, scans are rearranged and unneeded segments dropped.
+///
+/// Rotations that don't fit the block grid trim the partial blocks at the right or bottom
+/// edge (at most 15 pixels), like `jpegtran -trim`. The EXIF orientation of the output is
+/// set to 1, so `transform` has to include turning the image upright.
+pub fn optimize_jpeg(
+    data: &[u8],
+    config: &LosslessJpegConfig,
+    transform: LosslessTransform,
+) -> Result<Vec<u8>, CompressionError> {
+    // libjpeg reports errors by unwinding; the transcoder is cleaned up on the way out
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut transcoder = Transcoder::new();
+        unsafe { transcoder.run(data, config, transform) }
+    }));
+
+    result.map_err(|payload| CompressionError::EncodeFailed {
+        format: "JPEG".to_string(),
+        cause: panic_message(payload.as_ref()).unwrap_or_else(|| "libjpeg error".to_string()),
+    })
+}
+
+/// libjpeg state for one transcode. Boxed because libjpeg keeps pointers into it.
+struct Transcoder {
+    src: jpeg_decompress_struct,
+    dst: jpeg_compress_struct,
+    err: jpeg_error_mgr,
+    out_buffer: *mut u8,
+    out_size: c_ulong,
+}
+
+impl Transcoder {
+    fn new() -> Box<Self> {
+        unsafe {
+            let mut t: Box<Self> = Box::new(mem::zeroed());
+            jpeg_std_error(&mut t.err);
+            t.err.error_exit = Some(error_exit);
+            t.err.emit_message = Some(emit_message);
+
+            t.src.common.err = &mut t.err;
+            t.dst.common.err = &mut t.err;
+            jpeg_create_decompress(&mut t.src);
+            jpeg_create_compress(&mut t.dst);
+            t
+        }
+    }
+
+    unsafe fn run(&mut self, data: &[u8], config: &LosslessJpegConfig, transform: LosslessTransform) -> Vec<u8> {
+        jpeg_mem_src(&mut self.src, data.as_ptr(), data.len() as c_ulong);
+
+        let marker_limit: c_uint = 0xFFFF;
+        match config.metadata {
+            JpegMetadata::None => {}
+            JpegMetadata::Essential => {
+                jpeg_save_markers(&mut self.src, APP1, marker_limit);
+                jpeg_save_markers(&mut self.src, APP2, marker_limit);
+            }
+            JpegMetadata::All => {
+                jpeg_save_markers(&mut self.src, COM, marker_limit);
+                for marker in APP0..=APP0 + 15 {
+                    jpeg_save_markers(&mut self.src, marker, marker_limit);
+                }
+            }
+        }
+
+        jpeg_read_header(&mut self.src, 1);
+
+        let mut info: jpeg_transform_info = mem::zeroed();
+        info.transform = transform.code;
+        info.force_grayscale = transform.grayscale as boolean;
+        info.perfect = 1;
+        if jtransform_request_workspace(&mut self.src, &mut info) == 0 {
+            // Not a whole number of blocks in the transformed direction
+            info.perfect = 0;
+            info.trim = 1;
+            jtransform_request_workspace(&mut self.src, &mut info);
+        }
+
+        let src_coefficients = jpeg_read_coefficients(&mut self.src);
+
+        // libjpeg pads truncated or damaged data and carries on; don't pass that off as lossless.
+        // Checked here because starting the output resets the warning count.
+        if self.err.num_warnings > 0 {
+            panic::resume_unwind(Box::new("corrupt JPEG data".to_string()));
+        }
+
+        jpeg_copy_critical_parameters(&self.src, &mut self.dst);
+        let dst_coefficients = jtransform_adjust_parameters(&mut self.src, &mut self.dst, src_coefficients, &mut info);
+
+        // The default profile already picks progressive scans with optimized scan layout
+        self.dst.optimize_coding = 1;
+        self.dst.arith_code = 0;
+        if config.progressive {
+            jpeg_simple_progression(&mut self.dst);
+        } else {
+            jpeg_c_set_bool_param(&mut self.dst, J_BOOLEAN_PARAM::JBOOLEAN_OPTIMIZE_SCANS, 0);
+            self.dst.num_scans = 0;
+            self.dst.scan_info = ptr::null();
+        }
+
+        jpeg_mem_dest(&mut self.dst, &mut self.out_buffer, &mut self.out_size);
+        jpeg_write_coefficients(&mut self.dst, dst_coefficients);
+        self.copy_markers(config.metadata);
+        jtransform_execute_transform(&mut self.src, &mut self.dst, src_coefficients, &mut info);
+
+        jpeg_finish_compress(&mut self.dst);
+        jpeg_finish_decompress(&mut self.src);
+
+        std::slice::from_raw_parts(self.out_buffer, self.out_size as usize).to_vec()
+    }
+
+    /// Write the saved source segments, skipping the ones libjpeg writes itself
+    unsafe fn copy_markers(&mut self, metadata: JpegMetadata) {
+        let mut marker = self.src.marker_list;
+        while let Some(m) = marker.as_ref() {
+            let data = std::slice::from_raw_parts(m.data, m.data_length as usize);
+            let code = m.marker as c_int;
+
+            let written_by_libjpeg = (code == APP0 && data.starts_with(b"JFIF\0"))
+                || (code == APP0 + 14 && data.starts_with(b"Adobe"));
+            let wanted = match metadata {
+                JpegMetadata::None => false,
+                JpegMetadata::Essential => {
+                    (code == APP1 && data.starts_with(b"Exif\0"))
+                        || (code == APP2 && data.starts_with(b"ICC_PROFILE\0"))
+                }
+                JpegMetadata::All => true,
+            };
+
+            if wanted && !written_by_libjpeg {
+                if code == APP1 && data.starts_with(b"Exif\0\0") {
+                    let mut exif = data.to_vec();
+                    reset_orientation(&mut exif[6..]);
+                    jpeg_write_marker(&mut self.dst, code, exif.as_ptr(), exif.len() as c_uint);
+                } else {
+                    jpeg_write_marker(&mut self.dst, code, m.data, m.data_length);
+                }
+            }
+            marker = m.next;
+        }
+    }
+}
+
+impl Drop for Transcoder {
+    fn drop(&mut self) {
+        unsafe {
+            jpeg_destroy_compress(&mut self.dst);
+            jpeg_destroy_decompress(&mut self.src);
+            if !self.out_buffer.is_null() {
+                libc::free(self.out_buffer.cast());
+            }
+        }
+    }
+}
+
+const APP0: c_int = 0xE0;
+const APP1: c_int = 0xE1;
+const APP2: c_int = 0xE2;
+const COM: c_int = 0xFE;
+
+extern "C-unwind" fn error_exit(cinfo: &mut jpeg_common_struct) {
+    let message = unsafe {
+        let err = &*cinfo.err;
+        match err.format_message {
+            Some(format_message) => {
+                // JMSG_LENGTH_MAX is 200 in libjpeg-turbo, larger than the binding's array type
+                let mut buffer = [0u8; 200];
+                let format_message: unsafe extern "C-unwind" fn(&mut jpeg_common_struct, *mut u8) =
+                    mem::transmute(format_message);
+                format_message(cinfo, buffer.as_mut_ptr());
+                let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+                String::from_utf8_lossy(&buffer[..end]).into_owned()
+            }
+            None => format!("libjpeg error {}", err.msg_code),
+        }
+    };
+
+    // Unwind without running the panic hook
+    panic::resume_unwind(Box::new(message));
+}
+
+extern "C-unwind" fn emit_message(cinfo: &mut jpeg_common_struct, level: c_int) {
+    // Only count warnings (negative levels); trace messages are ignored
+    if level < 0 {
+        unsafe { (*cinfo.err).num_warnings += 1 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTATE_90: TransformOp = TransformOp::Rotate { degrees: 90 };
+    const FLIP_H: TransformOp = TransformOp::Flip { direction: FlipDirection::Horizontal };
+    const FLIP_V: TransformOp = TransformOp::Flip { direction: FlipDirection::Vertical };
+
+    fn code(ops: &[TransformOp]) -> JXFORM_CODE {
+        LosslessTransform::from_ops(ops).expect("ops are lossless").code
+    }
+
+    #[test]
+    fn single_operations_map_to_their_transform() {
+        assert_eq!(code(&[]), JXFORM_CODE_JXFORM_NONE);
+        assert_eq!(code(&[ROTATE_90]), JXFORM_CODE_JXFORM_ROT_90);
+        assert_eq!(code(&[TransformOp::Rotate { degrees: 180 }]), JXFORM_CODE_JXFORM_ROT_180);
+        assert_eq!(code(&[TransformOp::Rotate { degrees: 270 }]), JXFORM_CODE_JXFORM_ROT_270);
+        assert_eq!(code(&[FLIP_H]), JXFORM_CODE_JXFORM_FLIP_H);
+        assert_eq!(code(&[FLIP_V]), JXFORM_CODE_JXFORM_FLIP_V);
+    }
+
+    #[test]
+    fn sequences_compose_in_order() {
+        assert_eq!(code(&[ROTATE_90, ROTATE_90]), JXFORM_CODE_JXFORM_ROT_180);
+        assert_eq!(code(&[ROTATE_90; 4]), JXFORM_CODE_JXFORM_NONE);
+        assert_eq!(code(&[FLIP_H, FLIP_V]), JXFORM_CODE_JXFORM_ROT_180);
+        assert_eq!(code(&[FLIP_H, FLIP_H]), JXFORM_CODE_JXFORM_NONE);
+        // Mirroring after a clockwise turn keeps the top-left corner in place, mirroring
+        // before it moves that corner to the bottom right
+        assert_eq!(code(&[ROTATE_90, FLIP_H]), JXFORM_CODE_JXFORM_TRANSPOSE);
+        assert_eq!(code(&[FLIP_H, ROTATE_90]), JXFORM_CODE_JXFORM_TRANSVERSE);
+    }
+
+    #[test]
+    fn grayscale_is_kept_alongside_the_geometry() {
+        let transform = LosslessTransform::from_ops(&[ROTATE_90, TransformOp::Grayscale, ROTATE_90]).unwrap();
+        assert_eq!(transform, LosslessTransform { code: JXFORM_CODE_JXFORM_ROT_180, grayscale: true });
+    }
+
+    #[test]
+    fn pixel_changes_are_not_lossless() {
+        assert_eq!(LosslessTransform::from_ops(&[TransformOp::Rotate { degrees: 45 }]), None);
+        assert_eq!(LosslessTransform::from_ops(&[ROTATE_90, TransformOp::Brightness { value: 10 }]), None);
+        assert_eq!(LosslessTransform::from_ops(&[TransformOp::Gamma { value: 1.2 }]), None);
+    }
+
+    #[test]
+    fn rotates_a_jpeg_and_rejects_garbage() {
+        let img = image::RgbImage::from_fn(32, 16, |x, y| image::Rgb([x as u8 * 8, y as u8 * 16, 128]));
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode_image(&img).unwrap();
+
+        let rotated = optimize_jpeg(&jpeg, &LosslessJpegConfig::default(), LosslessTransform::from_ops(&[ROTATE_90]).unwrap()).unwrap();
+        assert_eq!(image::load_from_memory(&rotated).unwrap().to_rgb8().dimensions(), (16, 32));
+
+        let identity = LosslessTransform::from_ops(&[]).unwrap();
+        assert!(optimize_jpeg(b"not a jpeg", &LosslessJpegConfig::default(), identity).is_err());
+    }
+}
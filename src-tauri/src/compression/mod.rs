@@ -1,17 +1,75 @@
 pub mod types;
+pub mod error;
 pub mod analyzer;
+pub mod header;
+pub mod formats;
+pub mod heif;
+pub mod psd;
+pub mod raw;
+pub mod svg;
+pub mod tonemap;
 pub mod processor;
+pub mod naming;
+pub mod pipeline;
+pub mod crop;
+pub mod transform;
+pub mod resample;
+pub mod encode;
+pub mod jpeg;
+pub mod lossless;
+pub mod limits;
+pub mod progress;
+pub mod report;
+pub mod responsive;
+pub mod thumbnail_cache;
+pub mod watermark;
+pub mod worker;
 
 // Re-export commonly used types
 pub use types::{
     CompressionConfig,
     CompressResult,
+    FileResult,
+    FileStatus,
+    ReportFormat,
     ImageInfo,
+    AnalysisSummary,
+    AnalysisOptions,
     ImageError,
+    FailureReason,
     ProgressUpdate,
+    CompressionPhase,
+    ActiveFile,
     PathValidation,
+    TransformOp,
+    FlipDirection,
+    CropConfig,
+    CropAnchor,
+    ResizeFilter,
+    SharpenConfig,
+    OutputFormat,
+    ResponsiveConfig,
+    WatermarkConfig,
+    WatermarkSource,
+    WatermarkPosition,
+    JpegEncoderConfig,
+    JpegBackend,
+    ChromaSubsampling,
+    QuantTables,
+    QuantTablePreset,
+    LosslessJpegConfig,
+    JpegMetadata,
+    LargerOutputPolicy,
+    DecodeLimits,
+    ExecutionMode,
+    RawConfig,
+    RawMode,
+    SvgConfig,
+    SvgSize,
 };
 
+pub use error::CompressionError;
+
 pub use analyzer::{
     analyze_image,
     analyze_images,
@@ -20,6 +78,26 @@ pub use analyzer::{
     has_valid_extension,
 };
 
+pub use formats::{
+    InputFormat,
+    INPUT_FORMATS,
+    enabled_formats,
+    supported_extensions,
+};
+
+pub use naming::{
+    FilenameTemplate,
+    get_unique_filename,
+    sanitize_filename,
+};
+
+pub use thumbnail_cache::{
+    ThumbnailCache,
+    DEFAULT_MAX_CACHE_BYTES,
+};
+
+pub use report::write_report;
+
 pub use processor::{
     compress_images,
     compress_folder,
@@ -0,0 +1,498 @@
+use std::fmt::Write;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+
+use super::error::CompressionError;
+
+/// Template used when no custom template is configured (keeps the original stem)
+pub const DEFAULT_TEMPLATE: &str = "{stem}.{ext}";
+
+/// Date format used by a bare `{date}` token
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d";
+
+/// Maximum length of a generated filename in bytes (common limit on NTFS, APFS and ext4)
+const MAX_FILENAME_BYTES: usize = 255;
+
+/// File names reserved by Windows regardless of extension
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A single placeholder inside a filename template
+#[derive(Clone, Debug)]
+enum Token {
+    Stem,
+    Counter { padding: usize },
+    Date { format: String },
+    Width,
+    Height,
+    Quality,
+    Ext,
+    Format,
+    Parent,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+}
+
+/// Values available to a template when naming a single output file
+pub struct NamingContext<'a> {
+    pub input_path: &'a Path,
+    pub counter: usize,      // 1-based position in the batch
+    pub width: u32,          // output dimensions
+    pub height: u32,
+    pub quality: f32,
+    pub extension: &'a str,  // output extension without the dot
+}
+
+/// Parsed output filename template, e.g. `{date:%Y%m%d}_{stem}_{w}px.{ext}`
+///
+/// Supported tokens: `{stem}`, `{counter}` / `{counter:N}` (zero-padded to N digits),
+/// `{date}` / `{date:FORMAT}` (date taken, strftime syntax), `{w}`, `{h}`, `{quality}`,
+/// `{ext}`, `{format}` and `{parent}`.
+#[derive(Clone, Debug)]
+pub struct FilenameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl FilenameTemplate {
+    /// Parse a template string, rejecting unknown tokens and unbalanced braces
+    pub fn parse(template: &str) -> Result<Self, CompressionError> {
+        if template.trim().is_empty() {
+            return Err(CompressionError::missing_setting("output_template"));
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut body = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        body.push(c);
+                    }
+
+                    if !closed {
+                        return Err(CompressionError::invalid_template(template, "unclosed '{'"));
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    let token = parse_token(&body)
+                        .map_err(|cause| CompressionError::invalid_template(template, cause))?;
+                    segments.push(Segment::Token(token));
+                }
+                '}' => {
+                    return Err(CompressionError::invalid_template(template, "unexpected '}'"));
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        // Always produce a file with the output extension
+        let has_ext = segments.iter().any(|s| matches!(s, Segment::Token(Token::Ext)));
+        if !has_ext {
+            segments.push(Segment::Literal(".".to_string()));
+            segments.push(Segment::Token(Token::Ext));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Whether rendering needs the image dimensions (which requires reading the image header)
+    pub fn needs_dimensions(&self) -> bool {
+        self.segments.iter().any(|s| matches!(s, Segment::Token(Token::Width | Token::Height)))
+    }
+
+    /// Make sure the rendered name contains the width, inserting `-{w}w` before the
+    /// extension if the template has no width token (used for responsive variants)
+    pub fn with_width_suffix(&self) -> Self {
+        if self.segments.iter().any(|s| matches!(s, Segment::Token(Token::Width))) {
+            return self.clone();
+        }
+
+        let mut segments = self.segments.clone();
+        let ext_index = segments
+            .iter()
+            .rposition(|s| matches!(s, Segment::Token(Token::Ext)))
+            .unwrap_or(segments.len());
+
+        let mut suffix = vec![
+            Segment::Literal("-".to_string()),
+            Segment::Token(Token::Width),
+            Segment::Literal("w".to_string()),
+        ];
+
+        // Keep the "." that introduces the extension after the suffix
+        if ext_index > 0 {
+            if let Segment::Literal(text) = &mut segments[ext_index - 1] {
+                if text.ends_with('.') {
+                    text.pop();
+                    suffix.push(Segment::Literal(".".to_string()));
+                }
+            }
+        }
+
+        segments.splice(ext_index..ext_index, suffix);
+
+        Self { segments }
+    }
+
+    /// Render the template into a sanitized filename
+    pub fn render(&self, ctx: &NamingContext) -> String {
+        let mut name = String::new();
+        // Only read EXIF data if a date token is actually used
+        let mut date_taken: Option<NaiveDateTime> = None;
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => name.push_str(text),
+                Segment::Token(token) => {
+                    let value = match token {
+                        Token::Stem => ctx.input_path
+                            .file_stem()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or("image")
+                            .to_string(),
+                        Token::Counter { padding } => format!("{:0width$}", ctx.counter, width = *padding),
+                        Token::Date { format } => {
+                            let date = *date_taken.get_or_insert_with(|| read_date_taken(ctx.input_path));
+                            date.format(format).to_string()
+                        }
+                        Token::Width => ctx.width.to_string(),
+                        Token::Height => ctx.height.to_string(),
+                        Token::Quality => format!("{}", ctx.quality.round() as u32),
+                        Token::Ext => ctx.extension.to_lowercase(),
+                        Token::Format => ctx.extension.to_uppercase(),
+                        Token::Parent => ctx.input_path
+                            .parent()
+                            .and_then(|p| p.file_name())
+                            .and_then(|n| n.to_str())
+                            .unwrap_or("")
+                            .to_string(),
+                    };
+                    name.push_str(&value);
+                }
+            }
+        }
+
+        sanitize_filename(&name)
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("default template is valid")
+    }
+}
+
+/// Parse the inside of a `{...}` placeholder, describing what is wrong on failure
+fn parse_token(body: &str) -> Result<Token, String> {
+    let (name, arg) = match body.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg)),
+        None => (body.trim(), None),
+    };
+
+    let token = match name {
+        "stem" | "name" => Token::Stem,
+        "counter" | "n" => {
+            let padding = match arg {
+                Some(digits) => digits.trim().parse::<usize>()
+                    .map_err(|_| format!("invalid counter padding '{}'", digits))?,
+                None => 0,
+            };
+            Token::Counter { padding: padding.min(10) }
+        }
+        "date" => {
+            let format = arg.unwrap_or(DEFAULT_DATE_FORMAT).to_string();
+            // chrono panics when formatting with an invalid specifier, or one that needs a
+            // time zone (the date taken has none), so reject those up front
+            if !is_valid_date_format(&format) {
+                return Err(format!("invalid date format '{}'", format));
+            }
+            return Ok(Token::Date { format });
+        }
+        "w" | "width" => Token::Width,
+        "h" | "height" => Token::Height,
+        "quality" | "q" => Token::Quality,
+        "ext" => Token::Ext,
+        "format" => Token::Format,
+        "parent" => Token::Parent,
+        _ => return Err(format!("unknown token '{{{}}}'", body)),
+    };
+
+    if arg.is_some() && !matches!(token, Token::Counter { .. }) {
+        return Err(format!("token '{{{}}}' does not take an argument", name));
+    }
+
+    Ok(token)
+}
+
+/// Whether a date token's format can be rendered. Invalid specifiers fail for every
+/// date, as do `%z`, `%Z` and the like on dates without a time zone, so formatting any
+/// date tells.
+fn is_valid_date_format(format: &str) -> bool {
+    let mut probe = String::new();
+    write!(probe, "{}", NaiveDateTime::default().format(format)).is_ok()
+}
+
+/// Read the date a photo was taken from EXIF, falling back to the file modification time
+fn read_date_taken(path: &Path) -> NaiveDateTime {
+    read_exif_date(path)
+        .or_else(|| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(|time| DateTime::<Local>::from(time).naive_local())
+        })
+        .unwrap_or_else(|| Local::now().naive_local())
+}
+
+/// Read `DateTimeOriginal` (or `DateTime`) from the EXIF block, if any
+fn read_exif_date(path: &Path) -> Option<NaiveDateTime> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+    NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)?
+        .and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32)
+}
+
+/// Get a unique filename by appending numbers if necessary, and reserve it by creating
+/// the file empty, so images processed in parallel can't claim the same name. The caller
+/// overwrites the file, or removes it when the output can't be written.
+pub fn get_unique_filename(path: PathBuf) -> PathBuf {
+    if reserve(&path) {
+        return path;
+    }
+
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image")
+        .to_string();  // Convert to owned String
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg")
+        .to_string();  // Convert to owned String
+
+    let parent = path.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
+
+    let mut counter = 1;
+    loop {
+        let new_path = parent.join(format!("{}_{}.{}", stem, counter, extension));
+        if reserve(&new_path) {
+            return new_path;
+        }
+        counter += 1;
+
+        // Prevent infinite loop
+        if counter > 10000 {
+            return parent.join(format!("{}_{}.{}", stem, uuid::Uuid::new_v4(), extension));
+        }
+    }
+}
+
+/// Remove an output file reserved by `get_unique_filename` when writing it failed, so that
+/// no empty or partial file is left behind
+pub fn discard_on_error<T>(output_path: &Path, result: Result<T, CompressionError>) -> Result<T, CompressionError> {
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+/// Create an empty file unless one exists. Other failures, such as a missing directory,
+/// count as free so that the write which follows reports them.
+fn reserve(path: &Path) -> bool {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(_) => true,
+        Err(e) => e.kind() != io::ErrorKind::AlreadyExists,
+    }
+}
+
+/// Make a filename safe on Windows, macOS and Linux
+///
+/// Replaces reserved and control characters with `_`, trims trailing dots and spaces,
+/// avoids Windows device names and limits the length to 255 bytes.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Windows silently strips trailing dots and spaces
+    let trimmed_len = sanitized.trim_end_matches(['.', ' ']).len();
+    sanitized.truncate(trimmed_len);
+
+    // Leading spaces are legal but confusing in file managers
+    let sanitized = sanitized.trim_start().to_string();
+
+    let mut sanitized = if sanitized.is_empty() {
+        "image".to_string()
+    } else {
+        sanitized
+    };
+
+    // "CON.jpg" is just as invalid as "CON" on Windows
+    let base = sanitized.split('.').next().unwrap_or("").trim_end();
+    if WINDOWS_RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(base)) {
+        sanitized.insert(0, '_');
+    }
+
+    truncate_filename(sanitized)
+}
+
+/// Shorten a filename to `MAX_FILENAME_BYTES`, keeping the extension intact
+fn truncate_filename(name: String) -> String {
+    if name.len() <= MAX_FILENAME_BYTES {
+        return name;
+    }
+
+    let (stem, ext) = match name.rfind('.') {
+        Some(idx) if idx > 0 && name.len() - idx <= 16 => (&name[..idx], &name[idx..]),
+        _ => (name.as_str(), ""),
+    };
+
+    let mut end = MAX_FILENAME_BYTES - ext.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{}", &stem[..end], ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn context<'a>(input_path: &'a Path, extension: &'a str) -> NamingContext<'a> {
+        NamingContext {
+            input_path,
+            counter: 7,
+            width: 800,
+            height: 600,
+            quality: 79.6,
+            extension,
+        }
+    }
+
+    /// An empty directory of its own for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("naming-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn renders_every_token() {
+        let template = FilenameTemplate::parse("{parent}_{stem}_{counter:3}_{w}x{h}_q{quality}_{format}.{ext}").unwrap();
+        let name = template.render(&context(Path::new("/photos/trip/IMG 1.JPG"), "jpg"));
+        assert_eq!(name, "trip_IMG 1_007_800x600_q80_JPG.jpg");
+    }
+
+    #[test]
+    fn appends_a_missing_extension() {
+        let template = FilenameTemplate::parse("{stem}-small").unwrap();
+        assert_eq!(template.render(&context(Path::new("a.png"), "webp")), "a-small.webp");
+    }
+
+    #[test]
+    fn inserts_the_width_before_the_extension() {
+        let template = FilenameTemplate::parse(DEFAULT_TEMPLATE).unwrap().with_width_suffix();
+        assert_eq!(template.render(&context(Path::new("a.png"), "jpg")), "a-800w.jpg");
+        assert!(!FilenameTemplate::default().needs_dimensions());
+        assert!(template.needs_dimensions());
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in ["", "  ", "{stem", "stem}", "{bogus}", "{w:3}", "{counter:x}", "{date:%Q}"] {
+            assert!(FilenameTemplate::parse(template).is_err(), "{:?} was accepted", template);
+        }
+    }
+
+    #[test]
+    fn rejects_date_formats_that_need_a_time_zone() {
+        for format in ["%z", "%:z", "%#z", "%Z", "%+"] {
+            let template = format!("{{date:{}}}", format);
+            assert!(FilenameTemplate::parse(&template).is_err(), "{:?} was accepted", template);
+        }
+        assert!(FilenameTemplate::parse("{date:%Y-%m-%d_%H%M%S}").is_ok());
+    }
+
+    #[test]
+    fn dates_fall_back_to_the_modification_time() {
+        let dir = temp_dir("date");
+        let path = dir.join("photo.png");
+        let file = fs::File::create(&path).unwrap();
+        // Mid-June at noon UTC is in June in every time zone
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1_623_758_400)).unwrap();
+
+        let template = FilenameTemplate::parse("{date:%Y-%m}_{stem}").unwrap();
+        assert_eq!(template.render(&context(&path, "jpg")), "2021-06_photo.jpg");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sanitizes_rendered_names() {
+        assert_eq!(sanitize_filename("a:b?<c>.jpg"), "a_b__c_.jpg");
+        assert_eq!(sanitize_filename("CON.jpg"), "_CON.jpg");
+        assert_eq!(sanitize_filename(" name. "), "name");
+        assert_eq!(sanitize_filename(""), "image");
+
+        let long = format!("{}.jpeg", "é".repeat(200));
+        let truncated = sanitize_filename(&long);
+        assert!(truncated.len() <= MAX_FILENAME_BYTES);
+        assert!(truncated.ends_with("é.jpeg"));
+    }
+
+    #[test]
+    fn reserves_each_unique_name_once() {
+        let dir = temp_dir("unique");
+        let names: Vec<PathBuf> = (0..3).map(|_| get_unique_filename(dir.join("out.jpg"))).collect();
+
+        assert_eq!(names, [dir.join("out.jpg"), dir.join("out_1.jpg"), dir.join("out_2.jpg")]);
+        assert!(names.iter().all(|name| name.exists()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
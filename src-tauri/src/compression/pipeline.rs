@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::Path;
+use image::{DynamicImage, GenericImageView};
+
+use super::crop::{crop_dimensions, crop_to_aspect, scaled_crop_dimensions};
+use super::encode::{encode_bytes, write_output};
+use super::error::CompressionError;
+use super::header::{open_image, read_metadata, read_orientation, ImageHeader, SourceFormat};
+use super::jpeg::insert_metadata;
+use super::lossless::{optimize_jpeg, LosslessTransform};
+use super::resample::{resize, unsharp_mask};
+use super::svg::render_size;
+use super::transform::{apply_transforms, orientation_ops, transformed_dimensions};
+use super::types::{CompressionConfig, CompressionPhase, CropConfig, DecodeLimits, JpegEncoderConfig, LosslessJpegConfig, OutputFormat, RawConfig, ResizeFilter, SharpenConfig, SvgConfig, TransformOp};
+use super::watermark::Watermark;
+
+/// Processing stages applied between decoding and encoding.
+///
+/// Plain compression is handed to `image_compressor`, which works on files. As soon as a
+/// stage needs the decoded pixels, the image is decoded, edited, resized and encoded here.
+pub struct ImagePipeline {
+    quality: f32,
+    size_ratio: f32,
+    transforms: Vec<TransformOp>,
+    crop: Option<CropConfig>,
+    resize_filter: ResizeFilter,
+    linear_light: bool,
+    sharpen: Option<SharpenConfig>,
+    watermark: Option<Watermark>,
+    jpeg: Option<JpegEncoderConfig>,
+    lossless_jpeg: Option<LosslessJpegConfig>,
+    limits: DecodeLimits,
+    raw: RawConfig,
+    svg: SvgConfig,
+}
+
+impl ImagePipeline {
+    /// Prepare all stages once for the whole batch
+    pub fn new(config: &CompressionConfig) -> Result<Self, CompressionError> {
+        let watermark = config.watermark
+            .as_ref()
+            .map(Watermark::new)
+            .transpose()?;
+
+        Ok(Self {
+            quality: config.quality,
+            size_ratio: config.size_ratio,
+            transforms: config.transforms.clone(),
+            crop: config.crop.clone(),
+            resize_filter: config.resize_filter,
+            linear_light: config.linear_light,
+            sharpen: config.sharpen,
+            watermark,
+            jpeg: config.jpeg.clone(),
+            lossless_jpeg: config.lossless_jpeg,
+            limits: config.limits,
+            raw: config.raw,
+            svg: config.svg,
+        })
+    }
+
+    /// Whether any stage requires decoding the image ourselves
+    pub fn has_stages(&self) -> bool {
+        // image_compressor resizes in sRGB with its own filter, so take over resizing
+        // whenever linear light or a different filter is requested
+        let custom_resize = self.size_ratio < 1.0
+            && (self.linear_light || self.resize_filter != ResizeFilter::default());
+
+        !self.transforms.is_empty()
+            || self.crop.is_some()
+            || custom_resize
+            || self.sharpen.is_some()
+            || self.watermark.is_some()
+            || self.jpeg.is_some()
+    }
+
+    /// Encoder quality (0-100)
+    pub fn quality(&self) -> f32 {
+        self.quality
+    }
+
+    /// Decoder limits, also applied to the images generated from a source
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Tuned JPEG encoder settings, if any
+    pub fn jpeg(&self) -> Option<&JpegEncoderConfig> {
+        self.jpeg.as_ref()
+    }
+
+    /// Lossless transform for a JPEG source, if lossless optimization is enabled and
+    /// the batch needs no pixel changes. Tuned encoder settings ask for a re-encode.
+    /// The image is turned upright before the configured operations, as when decoding.
+    pub fn lossless_transform(&self, input_path: &Path) -> Option<LosslessTransform> {
+        self.lossless_jpeg?;
+
+        if self.size_ratio < 1.0
+            || self.crop.is_some()
+            || self.sharpen.is_some()
+            || self.watermark.is_some()
+            || self.jpeg.is_some()
+        {
+            return None;
+        }
+
+        let ops = [orientation_ops(read_orientation(input_path)), &self.transforms].concat();
+        LosslessTransform::from_ops(&ops)
+    }
+
+    /// Final output dimensions for a source image
+    pub fn output_dimensions(&self, header: &ImageHeader) -> (u32, u32) {
+        let (width, height) = match header.format {
+            SourceFormat::Svg => render_size((header.width, header.height), &self.svg),
+            _ => (header.width, header.height),
+        };
+        let (width, height) = transformed_dimensions(width, height, &self.transforms);
+        let (width, height) = match &self.crop {
+            Some(crop) => crop_dimensions(width, height, crop),
+            None => (width, height),
+        };
+
+        scaled_dimensions(width, height, self.size_ratio, self.crop.as_ref())
+    }
+
+    /// Decode, run all stages, encode to JPEG and return the output size in bytes
+    pub fn process(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<u64, CompressionError> {
+        on_phase(CompressionPhase::Decoding);
+        let img = self.decode(input_path)?;
+
+        on_phase(CompressionPhase::Encoding);
+        let (width, height) = scaled_dimensions(img.width(), img.height(), self.size_ratio, self.crop.as_ref());
+        let img = self.finish(&img, width, height);
+        let bytes = encode_bytes(&img, OutputFormat::Jpeg, self.quality, self.jpeg())?;
+        let bytes = insert_metadata(bytes, &read_metadata(input_path));
+
+        on_phase(CompressionPhase::Writing);
+        write_output(output_path, &bytes)
+    }
+
+    /// Optimize a JPEG source without re-encoding and return the output size in bytes
+    pub fn process_lossless(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        transform: LosslessTransform,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<u64, CompressionError> {
+        on_phase(CompressionPhase::Decoding);
+        let data = fs::read(input_path)
+            .map_err(|e| CompressionError::read(input_path, &e))?;
+
+        on_phase(CompressionPhase::Encoding);
+        let config = self.lossless_jpeg.unwrap_or_default();
+        let bytes = optimize_jpeg(&data, &config, transform)?;
+
+        on_phase(CompressionPhase::Writing);
+        write_output(output_path, &bytes)
+    }
+
+    /// Decode an image and apply the stages that come before resizing
+    pub fn decode(&self, input_path: &Path) -> Result<DynamicImage, CompressionError> {
+        let img = open_image(input_path, &self.limits, &self.raw, &self.svg)
+            .map_err(|e| CompressionError::decode(input_path, &e))?;
+
+        // Rotate and adjust before cropping so the aspect ratio applies to the final orientation
+        let img = apply_transforms(img, &self.transforms);
+
+        // Crop before resizing so resizing yields exact final dimensions
+        Ok(match &self.crop {
+            Some(crop) => crop_to_aspect(img, crop),
+            None => img,
+        })
+    }
+
+    /// Resize a decoded image to the given size and apply the stages that come after resizing
+    pub fn finish(&self, img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let resized = if (width, height) == img.dimensions() {
+            img.clone()
+        } else {
+            resize(img, width, height, self.resize_filter, self.linear_light)
+        };
+
+        if self.sharpen.is_none() && self.watermark.is_none() {
+            return resized;
+        }
+
+        let has_alpha = resized.color().has_alpha();
+        let mut rgba = resized.to_rgba8();
+
+        // Recover detail lost by downscaling
+        if let Some(sharpen) = &self.sharpen {
+            rgba = unsharp_mask(&rgba, sharpen);
+        }
+
+        // Watermark last so its size is relative to the final dimensions
+        if let Some(watermark) = &self.watermark {
+            watermark.apply(&mut rgba);
+        }
+
+        if has_alpha {
+            DynamicImage::ImageRgba8(rgba)
+        } else {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+        }
+    }
+}
+
+/// Apply the size ratio to a pair of dimensions. A crop that is a whole number of aspect
+/// units is scaled by the unit, so the aspect ratio survives resizing exactly.
+fn scaled_dimensions(width: u32, height: u32, size_ratio: f32, crop: Option<&CropConfig>) -> (u32, u32) {
+    if size_ratio >= 1.0 {
+        return (width, height);
+    }
+
+    if let Some(scaled) = crop.and_then(|crop| scaled_crop_dimensions(width, height, crop, size_ratio)) {
+        return scaled;
+    }
+
+    (
+        ((width as f32 * size_ratio) as u32).max(1),
+        ((height as f32 * size_ratio) as u32).max(1),
+    )
+}
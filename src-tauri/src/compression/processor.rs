@@ -1,20 +1,33 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::Instant;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::sync::Mutex;
+use image::ImageFormat;
 use image_compressor::{Factor, FolderCompressor, compressor::Compressor};
 use walkdir::WalkDir;
 use rayon::prelude::*;
 
-use super::types::{CompressionConfig, CompressResult, ImageError, ProgressUpdate};
-use super::analyzer::{has_valid_extension, is_valid_image};
+use super::error::{catch_panic, CompressionError};
+use super::progress::ProgressTracker;
+use super::types::{
+    CompressionConfig, CompressionPhase, CompressResult, ExecutionMode, FileResult, FileStatus, ImageError, LargerOutputPolicy,
+    ProgressUpdate, RawConfig,
+};
+use super::analyzer::{has_valid_extension, is_image_candidate, is_valid_image};
+use super::header::{extension_format, read_header, read_orientation, sniff_format, SourceFormat};
+use super::limits::{admit, MemoryBudget};
+use super::lossless::LosslessTransform;
+use super::naming::{discard_on_error, get_unique_filename, FilenameTemplate, NamingContext};
+use super::pipeline::ImagePipeline;
+use super::responsive::{generate_variants, largest_variant};
+use super::svg::write_minified;
+use super::worker::WorkerPool;
 
 /// Compress images based on configuration
 pub fn compress_images<F>(
     config: CompressionConfig,
     progress_callback: F,
-) -> Result<CompressResult, String>
+) -> Result<CompressResult, CompressionError>
 where
     F: Fn(ProgressUpdate) + Send + Sync,
 {
@@ -26,183 +39,344 @@ where
     // Create output directory if it doesn't exist
     let output_path = Path::new(&config.output_folder);
     fs::create_dir_all(output_path)
-        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+        .map_err(|e| CompressionError::write(output_path, &e))?;
 
     // Collect all image files to process
-    let files_to_process = collect_image_files(&config.source_paths)?;
+    progress_callback(ProgressUpdate::scanning());
+    let files_to_process = collect_image_files(&config.source_paths, config.accept_extensionless)?;
     let total_files = files_to_process.len();
 
     if files_to_process.is_empty() {
-        return Err("No valid image files found to compress".to_string());
+        return Err(CompressionError::NoImagesFound);
     }
 
+    // Source sizes up front, so progress and ETA can be weighted by bytes
+    let file_sizes: Vec<u64> = files_to_process
+        .iter()
+        .map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        .collect();
+    let total_bytes = file_sizes.iter().sum();
+
+    // Parse the output filename template once for the whole batch
+    let template = match &config.output_template {
+        Some(template) => FilenameTemplate::parse(template)?,
+        None => FilenameTemplate::default(),
+    };
+
     // Create compression factor
     let factor = Factor::new(config.quality, config.size_ratio);
 
+    // Prepare editing stages (watermark logo, fonts) once for all files
+    let pipeline = ImagePipeline::new(&config)?;
+
     // Configure Rayon thread pool size
     let thread_count = config.thread_count;
     let pool = rayon::ThreadPoolBuilder::new()
         .num_threads(thread_count)
         .build()
-        .map_err(|e| format!("Failed to create thread pool: {}", e))?;
+        .map_err(|e| CompressionError::internal(format!("Failed to create thread pool: {}", e)))?;
+
+    let tracker = ProgressTracker::new(&progress_callback, total_files, total_bytes, thread_count);
+
+    // Memory shared by the images processed at the same time
+    let budget = MemoryBudget::new(&config.limits);
+
+    // Worker processes that decode and encode in isolation, started on demand
+    let workers = match config.execution {
+        ExecutionMode::InProcess => None,
+        ExecutionMode::Isolated { timeout_secs } => Some(WorkerPool::new(&config, Duration::from_secs(timeout_secs))),
+    };
 
     let mut result = pool.install(|| {
-            // Use atomic counter for thread-safe progress tracking
-            let processed = Arc::new(AtomicUsize::new(0));
             let mut compression_result = CompressResult::new();
             compression_result.total = total_files;
-            let result_mutex = Arc::new(Mutex::new(compression_result));
+            let result_mutex = Mutex::new(compression_result);
 
             // Process files in parallel
-            files_to_process.par_iter().for_each(|file_path| {
-                // Get original file size
-                let original_size = fs::metadata(file_path)
-                    .map(|m| m.len())
-                    .unwrap_or(0);
+            files_to_process.par_iter().enumerate().for_each(|(index, file_path)| {
+                let original_size = file_sizes[index];
 
-                // Determine output path
-                let output_file_path = match get_output_path(
-                    file_path,
-                    &output_path,
-                    config.preserve_structure,
-                    &config.source_paths,
-                ) {
-                    Ok(path) => path,
-                    Err(e) => {
-                        // Lock result to add error
-                        if let Ok(mut result) = result_mutex.lock() {
-                            result.add_error(ImageError::new(
-                                file_path.display().to_string(),
-                                e,
-                            ));
-                        }
-                        return;
-                    }
-                };
+                let filename = file_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("unknown");
 
-                // Ensure output directory exists
-                if let Some(parent) = output_file_path.parent() {
-                    if let Err(e) = fs::create_dir_all(parent) {
-                        if let Ok(mut result) = result_mutex.lock() {
-                            result.add_error(ImageError::new(
-                                file_path.display().to_string(),
-                                format!("Failed to create output directory: {}", e),
-                            ));
-                        }
-                        return;
-                    }
-                }
+                // Show the file as active on this worker until it is finished
+                let file_progress = tracker.start_file(filename, original_size);
+                let on_phase = |phase| file_progress.phase(phase);
 
-                // Compress the image
-                match compress_single_image(file_path, &output_file_path, factor) {
-                    Ok(compressed_size) => {
-                        let saved = original_size.saturating_sub(compressed_size);
+                let started = Instant::now();
 
-                        // Lock result to add success
-                        if let Ok(mut result) = result_mutex.lock() {
-                            result.add_success(saved);
-                        }
+                // Source dimensions for the per-file report
+                let (original_width, original_height) = header_dimensions(file_path, &config.raw);
+
+                // Determine output directory (mirrors the source tree when preserving structure)
+                let output_dir = get_output_dir(file_path, output_path, &config);
+
+                // Reject oversized images and wait until the batch has memory for this one (or
+                // its largest upscaled variant), then compress the image or generate its
+                // responsive variants. Unreadable headers report (0, 0) and are left to the
+                // decoder's allocation limit. A panic in a decoder or encoder only fails this file.
+                let admitted = match &config.responsive {
+                    Some(responsive) => largest_variant(original_width, original_height, responsive),
+                    None => (original_width, original_height),
+                };
+                let outcome = catch_panic(file_path, || {
+                    admit(file_path, admitted, &config.limits, &budget)
+                        .and_then(|_permit| {
+                            fs::create_dir_all(&output_dir)
+                                .map_err(|e| CompressionError::write(&output_dir, &e))?;
+
+                            // SVGs to pass through are minified, whatever else is configured
+                            if config.svg.passthrough && is_svg_source(file_path) {
+                                let output_file_path = get_output_path(file_path, &output_dir, &config, &template, None, "svg", index + 1)?;
+                                let size = discard_on_error(&output_file_path, write_minified(file_path, &output_file_path, &on_phase))?;
+                                return apply_size_policy(file_path, &output_file_path, original_size, size, None, &config);
+                            }
+
+                            match &config.responsive {
+                                Some(responsive) => match &workers {
+                                    Some(workers) => workers.generate_variants(file_path, &output_dir, index + 1, &on_phase),
+                                    None => generate_variants(
+                                        file_path,
+                                        &output_dir,
+                                        &pipeline,
+                                        responsive,
+                                        &template,
+                                        index + 1,
+                                        &on_phase,
+                                    ),
+                                }
+                                .and_then(|manifest| {
+                                    // Savings are measured against the variant that replaces the original
+                                    manifest.primary_variant()
+                                        .map(|variant| Written {
+                                            status: FileStatus::Compressed,
+                                            path: Some(PathBuf::from(&variant.path)),
+                                            size: variant.size,
+                                            dimensions: (variant.width, variant.height),
+                                            quality: Some(config.quality),
+                                        })
+                                        .ok_or_else(|| CompressionError::internal("No responsive variants were generated"))
+                                }),
+                                None => get_output_path(file_path, &output_dir, &config, &template, Some(&pipeline), "jpg", index + 1)
+                                    .and_then(|output_file_path| {
+                                        let compressed_size = discard_on_error(&output_file_path, match &workers {
+                                            Some(workers) => workers.compress(file_path, &output_file_path, &on_phase),
+                                            None => compress_single_image(file_path, &output_file_path, factor, &pipeline, &on_phase),
+                                        })?;
+                                        // Lossless optimization keeps the source's own quality
+                                        let quality = match jpeg_lossless_transform(file_path, &pipeline) {
+                                            Some(_) => None,
+                                            None => Some(config.quality),
+                                        };
+                                        apply_size_policy(file_path, &output_file_path, original_size, compressed_size, quality, &config)
+                                    }),
+                            }
+                        })
+                });
+
+                let mut file_result = FileResult {
+                    source_path: file_path.display().to_string(),
+                    output_path: None,
+                    original_size,
+                    output_size: 0,
+                    original_width,
+                    original_height,
+                    output_width: 0,
+                    output_height: 0,
+                    quality: None,
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    status: FileStatus::Compressed,
+                    error: None,
+                };
+
+                match outcome {
+                    Ok(written) => {
+                        file_result.status = written.status;
+                        file_result.output_path = written.path.map(|path| path.display().to_string());
+                        file_result.output_size = written.size;
+                        (file_result.output_width, file_result.output_height) = written.dimensions;
+                        file_result.quality = written.quality;
 
                         log::info!(
-                            "Compressed {} -> {} (saved {} bytes)",
+                            "{:?} {} -> {} (saved {} bytes)",
+                            file_result.status,
                             file_path.display(),
-                            output_file_path.display(),
-                            saved
+                            file_result.output_path.as_deref().unwrap_or("nothing written"),
+                            file_result.saved_bytes()
                         );
+
+                        // Lock result to add success (a poisoned lock still holds every earlier result)
+                        result_mutex.lock().unwrap_or_else(|e| e.into_inner()).add_success(file_result);
                     }
                     Err(e) => {
-                        let error = ImageError::new(
-                            file_path.display().to_string(),
-                            e,
-                        );
+                        log::error!("Failed to compress {}: {}", file_path.display(), e);
 
-                        // Lock result to add error
-                        if let Ok(mut result) = result_mutex.lock() {
-                            result.add_error(error.clone());
-                        }
+                        file_result.status = FileStatus::Failed;
+                        file_result.error = Some(e.clone());
+                        let error = ImageError::new(file_path.display().to_string(), e);
 
-                        log::error!(
-                            "Failed to compress {}: {}",
-                            file_path.display(),
-                            error.error
-                        );
+                        // Lock result to add error
+                        result_mutex.lock().unwrap_or_else(|e| e.into_inner()).add_error(error, file_result);
                     }
                 }
 
-                // Update progress counter and send progress update
-                let current = processed.fetch_add(1, Ordering::Relaxed) + 1;
-
-                // Send progress update directly via callback (like analysis does)
-                let filename = file_path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown")
-                    .to_string();
-
-                let progress_update = ProgressUpdate::new(
-                    current,
-                    total_files,
-                    filename,
-                );
-
-                // Call progress callback directly from Rayon thread
-                progress_callback(progress_update);
+                // Send progress update directly from the Rayon thread (like analysis does)
+                file_progress.finish();
             });
 
-            // Extract final result
-            result_mutex.lock()
-                .map(|r| r.clone())
-                .unwrap_or_else(|_| CompressResult::new())
+            // Extract final result (intact even if the lock was poisoned)
+            result_mutex.into_inner().unwrap_or_else(|e| e.into_inner())
         });
 
+    result.files.sort_by(|a, b| a.source_path.cmp(&b.source_path));
     result.duration_ms = start_time.elapsed().as_millis();
 
     Ok(result)
 }
 
+/// Output of one image after the larger-output policy was applied
+struct Written {
+    status: FileStatus,
+    path: Option<PathBuf>,      // None when skipped
+    size: u64,
+    dimensions: (u32, u32),
+    quality: Option<f32>,       // None unless encoded at the configured quality
+}
+
+/// Check a freshly written output against the required savings. Outputs that aren't
+/// small enough are kept anyway, replaced by a copy of the source or removed.
+/// `quality` is the quality the output was encoded at, if any.
+fn apply_size_policy(
+    source: &Path,
+    output_path: &Path,
+    original_size: u64,
+    output_size: u64,
+    quality: Option<f32>,
+    config: &CompressionConfig,
+) -> Result<Written, CompressionError> {
+    let max_size = original_size as f64 * (1.0 - config.min_savings_percent as f64 / 100.0);
+
+    if output_size as f64 <= max_size || config.larger_output == LargerOutputPolicy::Force {
+        return Ok(Written {
+            status: FileStatus::Compressed,
+            path: Some(output_path.to_path_buf()),
+            size: output_size,
+            dimensions: header_dimensions(output_path, &config.raw),
+            quality,
+        });
+    }
+
+    fs::remove_file(output_path)
+        .map_err(|e| CompressionError::write(output_path, &e))?;
+
+    match config.larger_output {
+        LargerOutputPolicy::Skip => Ok(Written {
+            status: FileStatus::Skipped,
+            path: None,
+            size: 0,
+            dimensions: (0, 0),
+            quality: None,
+        }),
+        _ => {
+            // The copy keeps the source's extension, as it still has the source's format
+            let extension = source.extension().unwrap_or_default();
+            let kept_path = get_unique_filename(output_path.with_extension(extension));
+            let size = discard_on_error(&kept_path, fs::copy(source, &kept_path)
+                .map_err(|e| CompressionError::write(&kept_path, &e)))?;
+
+            Ok(Written {
+                status: FileStatus::KeptOriginal,
+                dimensions: header_dimensions(&kept_path, &config.raw),
+                path: Some(kept_path),
+                size,
+                quality: None,
+            })
+        }
+    }
+}
+
 /// Compress a single image file
-fn compress_single_image(
+pub fn compress_single_image(
     input_path: &Path,
     output_path: &Path,
     factor: Factor,
-) -> Result<u64, String> {
-    // Validate input
-    is_valid_image(input_path)?;
+    pipeline: &ImagePipeline,
+    on_phase: &dyn Fn(CompressionPhase),
+) -> Result<u64, CompressionError> {
+    // Validate input (header only, the encoder decodes the pixels anyway)
+    on_phase(CompressionPhase::Decoding);
+    is_valid_image(input_path, false)?;
 
     // Ensure output directory exists
     if let Some(parent) = output_path.parent() {
         fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+            .map_err(|e| CompressionError::write(parent, &e))?;
     }
 
-    // Create compressor - note the API requires the destination to be a directory
+    // JPEG sources that need no pixel changes keep their DCT data
+    if let Some(transform) = jpeg_lossless_transform(input_path, pipeline) {
+        return pipeline.process_lossless(input_path, output_path, transform, on_phase);
+    }
+
+    // Editing stages need the decoded pixels, and image_compressor trusts the extension,
+    // only reads what the image crate reads and ignores the EXIF orientation, so bypass it
+    // for those, for misnamed or extensionless files, for formats with their own codec and
+    // for images stored sideways
+    if pipeline.has_stages() || !compressor_can_read(input_path) || read_orientation(input_path) != 1 {
+        return pipeline.process(input_path, output_path, on_phase);
+    }
+
+    // The compressor writes <stem>.jpg into a directory and refuses to overwrite, so let it
+    // write into a private directory next to the output and move the result onto the
+    // reserved output path
     let output_dir = output_path.parent().unwrap_or_else(|| Path::new("."));
-    let mut compressor = Compressor::new(input_path.to_path_buf(), output_dir.to_path_buf());
+    let temp_dir = output_dir.join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+    fs::create_dir(&temp_dir)
+        .map_err(|e| CompressionError::write(&temp_dir, &e))?;
+
+    let result = compress_to_jpg(input_path, &temp_dir, output_path, factor, on_phase);
+    let _ = fs::remove_dir_all(&temp_dir);
+    result
+}
+
+/// Lossless transform for a JPEG source, if the pipeline optimizes it without re-encoding
+fn jpeg_lossless_transform(input_path: &Path, pipeline: &ImagePipeline) -> Option<LosslessTransform> {
+    if !matches!(sniff_format(input_path), Ok(Some(SourceFormat::Image(ImageFormat::Jpeg)))) {
+        return None;
+    }
+    pipeline.lossless_transform(input_path)
+}
 
-    // Set the factor
+/// Compress with image_compressor into `temp_dir`, then move the output to `output_path`
+fn compress_to_jpg(
+    input_path: &Path,
+    temp_dir: &Path,
+    output_path: &Path,
+    factor: Factor,
+    on_phase: &dyn Fn(CompressionPhase),
+) -> Result<u64, CompressionError> {
+    let mut compressor = Compressor::new(input_path.to_path_buf(), temp_dir.to_path_buf());
     compressor.set_factor(factor);
 
-    // Perform compression
-    compressor
+    // image_compressor decodes, encodes and writes in one call
+    on_phase(CompressionPhase::Encoding);
+    let compressed = compressor
         .compress_to_jpg()
-        .map_err(|e| format!("Compression failed: {:?}", e))?;
-
-    // The compressor creates the file with the same name but .jpg extension in the output dir
-    // We need to rename it to our desired output path if different
-    let expected_output = output_dir.join(
-        input_path.file_stem()
-            .ok_or("Invalid input filename")?
-    ).with_extension("jpg");
-
-    if expected_output != output_path {
-        fs::rename(&expected_output, output_path)
-            .map_err(|e| format!("Failed to rename output file: {}", e))?;
-    }
+        .map_err(|e| CompressionError::EncodeFailed {
+            format: "JPEG".to_string(),
+            cause: format!("{:?}", e),
+        })?;
+
+    on_phase(CompressionPhase::Writing);
+    fs::rename(&compressed, output_path)
+        .map_err(|e| CompressionError::write(output_path, &e))?;
 
     // Get compressed file size
     fs::metadata(output_path)
         .map(|m| m.len())
-        .map_err(|e| format!("Failed to read compressed file size: {}", e))
+        .map_err(|e| CompressionError::read(output_path, &e))
 }
 
 /// Compress entire folder using FolderCompressor
@@ -211,7 +385,7 @@ pub async fn compress_folder<F>(
     output_folder: &Path,
     config: CompressionConfig,
     progress_callback: F,
-) -> Result<CompressResult, String>
+) -> Result<CompressResult, CompressionError>
 where
     F: Fn(ProgressUpdate) + Send + Sync,
 {
@@ -220,7 +394,7 @@ where
 
     // Create output directory
     fs::create_dir_all(output_folder)
-        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+        .map_err(|e| CompressionError::write(output_folder, &e))?;
 
     // Count total files first
     let total_files: Vec<_> = WalkDir::new(input_folder)
@@ -232,7 +406,7 @@ where
     result.total = total_files.len();
 
     if total_files.is_empty() {
-        return Err("No valid image files found in folder".to_string());
+        return Err(CompressionError::NoImagesFound);
     }
 
     // Create folder compressor
@@ -247,21 +421,29 @@ where
     folder_compressor.set_thread_count(config.thread_count as u32);
 
     // Process with progress updates
-    for (index, entry) in total_files.iter().enumerate() {
-        let filename = entry.file_name().to_string_lossy().to_string();
+    let sizes: Vec<u64> = total_files
+        .iter()
+        .map(|entry| entry.metadata().map(|m| m.len()).unwrap_or(0))
+        .collect();
+    let tracker = ProgressTracker::new(&progress_callback, result.total, sizes.iter().sum(), 1);
 
-        progress_callback(ProgressUpdate::new(
-            index + 1,
-            result.total,
-            filename.clone(),
-        ));
+    for (entry, size) in total_files.iter().zip(sizes) {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        tracker.start_file(&filename, size).finish();
 
         // Note: image_compressor's FolderCompressor processes all at once
         // We're simulating progress here, actual compression happens below
     }
 
-    // Perform actual compression
-    match folder_compressor.compress() {
+    // Perform actual compression (a panic fails the whole call, but not the app)
+    let compressed = catch_panic(input_folder, || {
+        folder_compressor.compress().map_err(|e| CompressionError::EncodeFailed {
+            format: "JPEG".to_string(),
+            cause: format!("{:?}", e),
+        })
+    });
+
+    match compressed {
         Ok(()) => {
             // Calculate total saved bytes
             for entry in total_files {
@@ -281,19 +463,44 @@ where
                         .map(|m| m.len())
                         .unwrap_or(original_size);
 
-                    let saved = original_size.saturating_sub(compressed_size);
-                    result.add_success(saved);
+                    match apply_size_policy(entry.path(), &output_path, original_size, compressed_size, Some(config.quality), &config) {
+                        Ok(written) => {
+                            let (output_width, output_height) = written.dimensions;
+                            result.add_success(FileResult {
+                                output_path: written.path.map(|path| path.display().to_string()),
+                                output_size: written.size,
+                                output_width,
+                                output_height,
+                                quality: written.quality,
+                                status: written.status,
+                                ..folder_file_result(entry.path(), original_size, &config)
+                            });
+                        }
+                        Err(error) => {
+                            result.add_error(
+                                ImageError::new(entry.path().display().to_string(), error.clone()),
+                                FileResult {
+                                    status: FileStatus::Failed,
+                                    error: Some(error),
+                                    ..folder_file_result(entry.path(), original_size, &config)
+                                },
+                            );
+                        }
+                    }
                 } else {
-                    result.add_error(ImageError::new(
-                        entry.path().display().to_string(),
-                        "Output file not found after compression".to_string(),
-                    ));
+                    let error = CompressionError::FileNotFound { path: output_path.display().to_string() };
+                    result.add_error(
+                        ImageError::new(entry.path().display().to_string(), error.clone()),
+                        FileResult {
+                            status: FileStatus::Failed,
+                            error: Some(error),
+                            ..folder_file_result(entry.path(), original_size, &config)
+                        },
+                    );
                 }
             }
         }
-        Err(e) => {
-            return Err(format!("Folder compression failed: {:?}", e));
-        }
+        Err(e) => return Err(e),
     }
 
     result.duration_ms = start_time.elapsed().as_millis();
@@ -301,8 +508,35 @@ where
     Ok(result)
 }
 
+/// Per-file entry for `compress_folder`, which compresses the whole folder in one call
+/// and so has no per-file timing
+fn folder_file_result(source: &Path, original_size: u64, config: &CompressionConfig) -> FileResult {
+    let (original_width, original_height) = header_dimensions(source, &config.raw);
+    FileResult {
+        source_path: source.display().to_string(),
+        output_path: None,
+        original_size,
+        output_size: 0,
+        original_width,
+        original_height,
+        output_width: 0,
+        output_height: 0,
+        quality: None,
+        duration_ms: 0,
+        status: FileStatus::Compressed,
+        error: None,
+    }
+}
+
+/// Image dimensions from the file header, (0, 0) if it cannot be read
+fn header_dimensions(path: &Path, raw_config: &RawConfig) -> (u32, u32) {
+    read_header(path, raw_config)
+        .map(|header| (header.width, header.height))
+        .unwrap_or((0, 0))
+}
+
 /// Collect all image files from the given paths
-fn collect_image_files(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+fn collect_image_files(paths: &[String], accept_extensionless: bool) -> Result<Vec<PathBuf>, CompressionError> {
     let mut files = Vec::new();
 
     for path_str in paths {
@@ -320,11 +554,11 @@ fn collect_image_files(paths: &[String]) -> Result<Vec<PathBuf>, String> {
                 .filter_map(|e| e.ok())
                 .filter(|e| e.file_type().is_file())
             {
-                if has_valid_extension(entry.path()) {
+                if is_image_candidate(entry.path(), accept_extensionless) {
                     files.push(entry.path().to_path_buf());
                 }
             }
-        } else if path.is_file() && has_valid_extension(path) {
+        } else if path.is_file() && is_image_candidate(path, accept_extensionless) {
             files.push(path.to_path_buf());
         }
     }
@@ -332,44 +566,100 @@ fn collect_image_files(paths: &[String]) -> Result<Vec<PathBuf>, String> {
     Ok(files)
 }
 
-/// Determine output path for a compressed image
-fn get_output_path(
+/// Determine the output directory for an image
+fn get_output_dir(
     input_path: &Path,
     output_base: &Path,
-    preserve_structure: bool,
-    source_paths: &[String],
-) -> Result<PathBuf, String> {
-    let mut output_path = if preserve_structure {
+    config: &CompressionConfig,
+) -> PathBuf {
+    if config.preserve_structure {
         // Try to find the common parent from source_paths
-        if let Some(common_parent) = find_common_parent(input_path, source_paths) {
-            let relative = input_path
-                .strip_prefix(&common_parent)
-                .unwrap_or(input_path);
-            output_base.join(relative)
-        } else {
-            // Fallback to just filename
-            output_base.join(
+        find_common_parent(input_path, &config.source_paths)
+            .and_then(|common_parent| {
                 input_path
-                    .file_name()
-                    .ok_or_else(|| "Invalid input filename".to_string())?
-            )
-        }
+                    .strip_prefix(&common_parent)
+                    .ok()
+                    .and_then(|relative| relative.parent())
+                    .map(|relative_dir| output_base.join(relative_dir))
+            })
+            .unwrap_or_else(|| output_base.to_path_buf())
     } else {
         // Put all files directly in output folder
-        output_base.join(
-            input_path
-                .file_name()
-                .ok_or_else(|| "Invalid input filename".to_string())?
-        )
+        output_base.to_path_buf()
+    }
+}
+
+/// Determine output path for a compressed image. `pipeline` is None for sources that
+/// are written without decoding (SVG passthrough), which keep their dimensions.
+fn get_output_path(
+    input_path: &Path,
+    output_dir: &Path,
+    config: &CompressionConfig,
+    template: &FilenameTemplate,
+    pipeline: Option<&ImagePipeline>,
+    extension: &str,
+    counter: usize,
+) -> Result<PathBuf, CompressionError> {
+    if input_path.file_name().is_none() {
+        return Err(CompressionError::InvalidFileName { path: input_path.display().to_string() });
+    }
+
+    // Only read the image header when the template actually uses the dimensions
+    let (width, height) = if template.needs_dimensions() {
+        let header = read_header(input_path, &config.raw)
+            .map_err(|e| CompressionError::decode(input_path, &e))?;
+        match pipeline {
+            Some(pipeline) => pipeline.output_dimensions(&header),
+            None => (header.width, header.height),
+        }
+    } else {
+        (0, 0)
     };
 
-    // Change extension to .jpg
-    output_path.set_extension("jpg");
+    let filename = template.render(&NamingContext {
+        input_path,
+        counter,
+        width,
+        height,
+        quality: config.quality,
+        extension,
+    });
 
     // Handle naming conflicts
-    output_path = get_unique_filename(output_path);
+    Ok(get_unique_filename(output_dir.join(filename)))
+}
+
+/// Formats image_compressor decodes itself. Others, such as HDR images that need tone
+/// mapping, go through the pipeline.
+const COMPRESSOR_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Jpeg,
+    ImageFormat::Png,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+    ImageFormat::Ico,
+    ImageFormat::Tiff,
+    ImageFormat::WebP,
+];
+
+/// Whether image_compressor can read the file: one of its formats, with an extension
+/// that names the format found in the file signature (unrecognised signatures are
+/// judged by the extension and left to the decoder)
+fn compressor_can_read(path: &Path) -> bool {
+    let format = match sniff_format(path) {
+        Ok(Some(format)) if extension_format(path) != Some(format) => return false,
+        Ok(Some(format)) => Some(format),
+        _ => extension_format(path),
+    };
 
-    Ok(output_path)
+    match format {
+        Some(SourceFormat::Image(format)) => COMPRESSOR_FORMATS.contains(&format),
+        Some(_) => false,
+        None => true,
+    }
+}
+
+fn is_svg_source(path: &Path) -> bool {
+    matches!(sniff_format(path), Ok(Some(SourceFormat::Svg)))
 }
 
 /// Find common parent directory from source paths
@@ -383,37 +673,286 @@ fn find_common_parent(file_path: &Path, source_paths: &[String]) -> Option<PathB
     None
 }
 
-/// Get a unique filename by appending numbers if necessary
-fn get_unique_filename(path: PathBuf) -> PathBuf {
-    if !path.exists() {
-        return path;
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    use super::*;
+    use crate::compression::types::TransformOp;
+
+    /// An empty directory of its own for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("processor-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Noise, so the source compresses badly and the JPEG output is smaller
+    fn write_image(path: &Path, format: ImageFormat) {
+        let mut state = 1u32;
+        let img = RgbImage::from_fn(96, 64, |_, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            Rgb([(state >> 24) as u8, (state >> 16) as u8, (state >> 8) as u8])
+        });
+        img.save_with_format(path, format).unwrap();
+    }
+
+    /// The configuration the frontend sends when nothing was changed
+    fn default_config(sources: &[&Path], output: &Path) -> CompressionConfig {
+        serde_json::from_value(serde_json::json!({
+            "source_paths": sources,
+            "output_folder": output,
+            "quality": 85.0,
+            "size_ratio": 0.8,
+            "thread_count": 2,
+            "preserve_structure": false,
+        }))
+        .unwrap()
+    }
+
+    /// A JPEG stored `width`×`height`, left half red and right half blue, with an EXIF orientation
+    fn write_oriented_jpeg(path: &Path, width: u32, height: u32, orientation: u16) {
+        let img = RgbImage::from_fn(width, height, |x, _| if x < width / 2 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) });
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 95).encode_image(&img).unwrap();
+
+        // Little-endian TIFF structure with one entry in its first IFD: the orientation (SHORT)
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0; 6]);
+
+        let mut file = jpeg[..2].to_vec();
+        file.extend_from_slice(&[0xFF, 0xE1]);
+        file.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        file.extend_from_slice(b"Exif\0\0");
+        file.extend_from_slice(&tiff);
+        file.extend_from_slice(&jpeg[2..]);
+        fs::write(path, file).unwrap();
+    }
+
+    fn compress(config: CompressionConfig) -> CompressResult {
+        compress_images(config, |_| {}).unwrap()
+    }
+
+    #[test]
+    fn compresses_images_with_the_default_config() {
+        let dir = temp_dir("default");
+        let output = dir.join("out");
+        write_image(&dir.join("a.png"), ImageFormat::Png);
+        write_image(&dir.join("c.bmp"), ImageFormat::Bmp);
+
+        let result = compress(default_config(&[&dir.join("a.png"), &dir.join("c.bmp")], &output));
+
+        assert_eq!((result.successful, result.failed), (2, 0), "{:?}", result.errors);
+        for (file, name) in result.files.iter().zip(["a.jpg", "c.jpg"]) {
+            assert_eq!(file.status, FileStatus::Compressed);
+            assert_eq!(file.output_path.as_deref(), Some(output.join(name).to_str().unwrap()));
+            assert_eq!(image::open(output.join(name)).unwrap().width(), 76);
+        }
+        // Nothing but the outputs is left in the output folder
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sources_with_the_same_stem_get_their_own_outputs() {
+        let dir = temp_dir("stem");
+        write_image(&dir.join("a.png"), ImageFormat::Png);
+        write_image(&dir.join("a.bmp"), ImageFormat::Bmp);
+        // An unrelated file in the output folder, which is also the source folder
+        fs::write(dir.join("a.jpg"), b"unrelated").unwrap();
+
+        let result = compress(default_config(&[&dir.join("a.png"), &dir.join("a.bmp")], &dir));
+
+        assert_eq!((result.successful, result.failed), (2, 0), "{:?}", result.errors);
+        assert_eq!(fs::read(dir.join("a.jpg")).unwrap(), b"unrelated");
+        for name in ["a_1.jpg", "a_2.jpg"] {
+            assert!(image::open(dir.join(name)).is_ok(), "{} is not a JPEG", name);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
     }
 
-    let stem = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("image")
-        .to_string();  // Convert to owned String
-
-    let extension = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("jpg")
-        .to_string();  // Convert to owned String
-
-    let parent = path.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
-
-    let mut counter = 1;
-    loop {
-        let new_path = parent.join(format!("{}_{}.{}", stem, counter, extension));
-        if !new_path.exists() {
-            return new_path;
+    #[test]
+    fn jpeg_sources_are_re_encoded_unless_lossless_is_requested() {
+        let dir = temp_dir("lossless");
+        let source = dir.join("a.jpg");
+        write_image(&source, ImageFormat::Jpeg);
+
+        let output_size = |quality: f32, lossless: serde_json::Value, jpeg: serde_json::Value| {
+            let config = CompressionConfig {
+                quality,
+                size_ratio: 1.0,
+                lossless_jpeg: serde_json::from_value(lossless).unwrap(),
+                jpeg: serde_json::from_value(jpeg).unwrap(),
+                larger_output: LargerOutputPolicy::Force,
+                ..default_config(&[&source], &dir.join("out"))
+            };
+            compress(config).files[0].output_size
+        };
+        let none = serde_json::Value::Null;
+        let enabled = serde_json::json!({});
+
+        // The quality applies by default
+        assert!(default_config(&[&source], &dir).lossless_jpeg.is_none());
+        assert!(output_size(10.0, none.clone(), none.clone()) < output_size(80.0, none.clone(), none.clone()));
+        // Lossless optimization keeps the DCT data whatever the quality
+        assert_eq!(output_size(10.0, enabled.clone(), none.clone()), output_size(80.0, enabled.clone(), none.clone()));
+        // Tuned encoder settings ask for a re-encode
+        assert!(output_size(10.0, enabled.clone(), enabled.clone()) < output_size(80.0, enabled.clone(), enabled));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn turns_sideways_photos_upright() {
+        let dir = temp_dir("orientation");
+        let source = dir.join("a.jpg");
+        // Stored landscape, shown turned 90° clockwise: red on top
+        write_oriented_jpeg(&source, 80, 40, 6);
+
+        let result = compress(default_config(&[&source], &dir.join("out")));
+
+        let file = &result.files[0];
+        assert_eq!((file.original_width, file.original_height), (40, 80));
+        assert_eq!((file.output_width, file.output_height), (32, 64));
+        let output = image::open(file.output_path.as_ref().unwrap()).unwrap().to_rgb8();
+        assert!(output.get_pixel(16, 4)[0] > 200 && output.get_pixel(16, 60)[2] > 200);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn crops_the_upright_image() {
+        let dir = temp_dir("orientation-crop");
+        let source = dir.join("a.jpg");
+        write_oriented_jpeg(&source, 400, 200, 6);
+
+        let config = CompressionConfig {
+            size_ratio: 1.0,
+            crop: serde_json::from_value(serde_json::json!({ "aspect_width": 4, "aspect_height": 5, "anchor": "top" })).unwrap(),
+            larger_output: LargerOutputPolicy::Force,
+            ..default_config(&[&source], &dir.join("out"))
+        };
+        let result = compress(config);
+
+        let output = image::open(result.files[0].output_path.as_ref().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output.dimensions(), (200, 250));
+        // The top anchor keeps the whole red half and part of the blue one
+        assert!(output.get_pixel(100, 10)[0] > 200 && output.get_pixel(100, 240)[2] > 200);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotations_apply_to_the_upright_image() {
+        let dir = temp_dir("orientation-rotate");
+        let source = dir.join("a.jpg");
+        // Shown 48×96 with red on top, so turning it clockwise puts red on the right
+        write_oriented_jpeg(&source, 96, 48, 6);
+
+        for lossless in [serde_json::Value::Null, serde_json::json!({})] {
+            let config = CompressionConfig {
+                size_ratio: 1.0,
+                transforms: vec![TransformOp::Rotate { degrees: 90 }],
+                lossless_jpeg: serde_json::from_value(lossless.clone()).unwrap(),
+                larger_output: LargerOutputPolicy::Force,
+                ..default_config(&[&source], &dir.join("out"))
+            };
+            let result = compress(config);
+
+            let output_path = Path::new(result.files[0].output_path.as_ref().unwrap());
+            let output = image::open(output_path).unwrap().to_rgb8();
+            assert_eq!(output.dimensions(), (96, 48), "lossless: {}", lossless);
+            assert!(output.get_pixel(88, 24)[0] > 200 && output.get_pixel(8, 24)[2] > 200, "lossless: {}", lossless);
+            assert_eq!(read_orientation(output_path), 1, "lossless: {}", lossless);
         }
-        counter += 1;
 
-        // Prevent infinite loop
-        if counter > 10000 {
-            return parent.join(format!("{}_{}.{}", stem, uuid::Uuid::new_v4(), extension));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_the_quality_only_for_re_encoded_outputs() {
+        let dir = temp_dir("quality");
+        write_image(&dir.join("a.png"), ImageFormat::Png);
+        write_image(&dir.join("b.jpg"), ImageFormat::Jpeg);
+        // Smaller than any JPEG, so the source is kept
+        RgbImage::new(1, 1).save(dir.join("c.png")).unwrap();
+
+        let sources = [dir.join("a.png"), dir.join("b.jpg"), dir.join("c.png")];
+        let config = CompressionConfig {
+            size_ratio: 1.0,
+            lossless_jpeg: serde_json::from_value(serde_json::json!({})).unwrap(),
+            ..default_config(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>(), &dir.join("out"))
+        };
+        let result = compress(config);
+
+        let reported: Vec<_> = result.files.iter().map(|file| (file.status, file.quality)).collect();
+        assert_eq!(reported, [
+            (FileStatus::Compressed, Some(85.0)),
+            (FileStatus::Compressed, None),
+            (FileStatus::KeptOriginal, None),
+        ]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_skips_or_forces_outputs_that_are_not_smaller() {
+        let dir = temp_dir("policy");
+        write_image(&dir.join("a.png"), ImageFormat::Png);
+        // Smaller than any JPEG
+        RgbImage::new(1, 1).save(dir.join("b.png")).unwrap();
+        let sources = [dir.join("a.png"), dir.join("b.png")];
+
+        for (policy, status, outputs) in [
+            (LargerOutputPolicy::KeepOriginal, FileStatus::KeptOriginal, vec!["a.jpg", "b.png"]),
+            (LargerOutputPolicy::Skip, FileStatus::Skipped, vec!["a.jpg"]),
+            (LargerOutputPolicy::Force, FileStatus::Compressed, vec!["a.jpg", "b.jpg"]),
+        ] {
+            let output = dir.join(format!("{:?}", policy));
+            let config = CompressionConfig {
+                larger_output: policy,
+                ..default_config(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>(), &output)
+            };
+            let result = compress(config);
+
+            assert_eq!(result.failed, 0, "{:?}", result.errors);
+            assert_eq!(result.files[0].status, FileStatus::Compressed);
+            assert_eq!(result.files[1].status, status);
+            let mut written: Vec<_> = fs::read_dir(&output).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+            written.sort();
+            assert_eq!(written, outputs, "{:?}", policy);
         }
+        // The kept copy is the source itself
+        assert_eq!(fs::read(dir.join("KeepOriginal/b.png")).unwrap(), fs::read(dir.join("b.png")).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn requires_the_minimum_savings() {
+        let dir = temp_dir("savings");
+        let source = dir.join("a.png");
+        write_image(&source, ImageFormat::Png);
+        let config = CompressionConfig {
+            min_savings_percent: 20.0,
+            larger_output: LargerOutputPolicy::Skip,
+            ..default_config(&[&source], &dir)
+        };
+
+        let policy = |output_size: u64| {
+            let output_path = dir.join("a.jpg");
+            write_image(&output_path, ImageFormat::Jpeg);
+            let written = apply_size_policy(&source, &output_path, 1000, output_size, Some(85.0), &config).unwrap();
+            (written.status, written.quality, output_path.exists())
+        };
+
+        assert_eq!(policy(800), (FileStatus::Compressed, Some(85.0), true));
+        assert_eq!(policy(801), (FileStatus::Skipped, None, false));
+
+        fs::remove_dir_all(dir).unwrap();
     }
-}
\ No newline at end of file
+}
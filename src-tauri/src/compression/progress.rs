@@ -0,0 +1,245 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::types::{ActiveFile, CompressionPhase, ProgressUpdate};
+
+/// Minimum time between updates for phase changes; finished files always send one
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tracks batch progress across worker threads and reports it through a callback.
+///
+/// Throughput and ETA are based on source bytes, so a batch mixing tiny and huge files
+/// gets a realistic estimate. Each worker owns one slot in the active file list.
+pub struct ProgressTracker<'a, F>
+where
+    F: Fn(ProgressUpdate) + Sync,
+{
+    callback: &'a F,
+    total_files: usize,
+    total_bytes: u64,
+    files_done: AtomicUsize,
+    bytes_done: AtomicU64,
+    start: Instant,
+    active: Mutex<Vec<Option<ActiveFile>>>,
+    last_update: Mutex<Instant>,
+}
+
+impl<'a, F> ProgressTracker<'a, F>
+where
+    F: Fn(ProgressUpdate) + Sync,
+{
+    pub fn new(callback: &'a F, total_files: usize, total_bytes: u64, workers: usize) -> Self {
+        let start = Instant::now();
+        Self {
+            callback,
+            total_files,
+            total_bytes,
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            start,
+            active: Mutex::new(vec![None; workers.max(1)]),
+            last_update: Mutex::new(start),
+        }
+    }
+
+    /// Register a file on the calling worker thread
+    pub fn start_file(&self, filename: &str, size: u64) -> FileProgress<'_, 'a, F> {
+        let file = FileProgress {
+            tracker: self,
+            worker: rayon::current_thread_index().unwrap_or(0),
+            filename: filename.to_string(),
+            size,
+            phase: Cell::new(CompressionPhase::Decoding),
+        };
+        file.phase(CompressionPhase::Decoding);
+        file
+    }
+
+    fn set_active(&self, worker: usize, entry: Option<ActiveFile>) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if worker >= active.len() {
+            active.resize(worker + 1, None);
+        }
+        active[worker] = entry;
+    }
+
+    /// Send an update, skipping it if one was sent very recently unless `force` is set.
+    /// Updates are sent one at a time so the counters never go backwards.
+    fn report(&self, current_file: &str, phase: CompressionPhase, force: bool) {
+        let mut last_update = self.last_update.lock().unwrap_or_else(|e| e.into_inner());
+        if !force && last_update.elapsed() < MIN_UPDATE_INTERVAL {
+            return;
+        }
+        *last_update = Instant::now();
+
+        let active_files: Vec<ActiveFile> = self.active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        let current = self.files_done.load(Ordering::Relaxed);
+        let bytes_processed = self.bytes_done.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed();
+        let seconds = elapsed.as_secs_f64();
+
+        let percent = if self.total_bytes > 0 {
+            (bytes_processed as f64 / self.total_bytes as f64 * 100.0) as f32
+        } else if self.total_files > 0 {
+            current as f32 / self.total_files as f32 * 100.0
+        } else {
+            0.0
+        };
+
+        let (images_per_second, bytes_per_second) = if seconds > 0.0 {
+            (current as f64 / seconds, bytes_processed as f64 / seconds)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let eta_ms = if bytes_per_second > 0.0 {
+            let remaining = self.total_bytes.saturating_sub(bytes_processed);
+            Some((remaining as f64 / bytes_per_second * 1000.0) as u64)
+        } else if images_per_second > 0.0 {
+            // Only empty files so far
+            let remaining = self.total_files.saturating_sub(current);
+            Some((remaining as f64 / images_per_second * 1000.0) as u64)
+        } else {
+            None
+        };
+
+        (self.callback)(ProgressUpdate {
+            current,
+            total: self.total_files,
+            current_file: current_file.to_string(),
+            percent,
+            phase,
+            bytes_processed,
+            total_bytes: self.total_bytes,
+            elapsed_ms: elapsed.as_millis() as u64,
+            images_per_second,
+            bytes_per_second,
+            eta_ms,
+            active_files,
+        });
+    }
+}
+
+/// Handle for one file in progress; `finish` it once the file is done (or failed)
+pub struct FileProgress<'t, 'a, F>
+where
+    F: Fn(ProgressUpdate) + Sync,
+{
+    tracker: &'t ProgressTracker<'a, F>,
+    worker: usize,
+    filename: String,
+    size: u64,
+    phase: Cell<CompressionPhase>,
+}
+
+impl<F> FileProgress<'_, '_, F>
+where
+    F: Fn(ProgressUpdate) + Sync,
+{
+    /// Move the file to a new phase, reporting it unless an update was sent very recently
+    pub fn phase(&self, phase: CompressionPhase) {
+        self.phase.set(phase);
+        self.tracker.set_active(self.worker, Some(ActiveFile {
+            worker: self.worker,
+            filename: self.filename.clone(),
+            phase,
+        }));
+
+        self.tracker.report(&self.filename, phase, false);
+    }
+
+    /// Count the file as processed and always report it
+    pub fn finish(self) {
+        self.tracker.set_active(self.worker, None);
+        self.tracker.files_done.fetch_add(1, Ordering::Relaxed);
+        self.tracker.bytes_done.fetch_add(self.size, Ordering::Relaxed);
+        self.tracker.report(&self.filename, self.phase.get(), true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn collect(updates: &Mutex<Vec<ProgressUpdate>>) -> impl Fn(ProgressUpdate) + Sync + '_ {
+        move |update| updates.lock().unwrap().push(update)
+    }
+
+    #[test]
+    fn counts_progress_by_source_bytes() {
+        let updates = Mutex::new(Vec::new());
+        let callback = collect(&updates);
+        let tracker = ProgressTracker::new(&callback, 2, 400, 1);
+
+        let file = tracker.start_file("small.jpg", 100);
+        thread::sleep(Duration::from_millis(20));
+        file.finish();
+
+        let updates = updates.lock().unwrap();
+        let last = updates.last().unwrap();
+        assert_eq!((last.current, last.total, last.current_file.as_str()), (1, 2, "small.jpg"));
+        assert_eq!((last.bytes_processed, last.total_bytes), (100, 400));
+        assert_eq!(last.percent, 25.0);
+        assert!(last.active_files.is_empty());
+        // Three quarters of the bytes are left, at the rate of the first quarter
+        let eta_ms = last.eta_ms.unwrap();
+        assert!(eta_ms.abs_diff(3 * last.elapsed_ms) <= 3, "eta {} after {}", eta_ms, last.elapsed_ms);
+        assert!(last.bytes_per_second > 0.0 && last.images_per_second > 0.0);
+    }
+
+    #[test]
+    fn estimates_batches_of_empty_files_by_count() {
+        let updates = Mutex::new(Vec::new());
+        let callback = collect(&updates);
+        let tracker = ProgressTracker::new(&callback, 4, 0, 1);
+
+        thread::sleep(Duration::from_millis(5));
+        tracker.start_file("empty.svg", 0).finish();
+
+        let last = updates.lock().unwrap().pop().unwrap();
+        assert_eq!(last.percent, 25.0);
+        assert!(last.eta_ms.is_some());
+    }
+
+    #[test]
+    fn throttles_phase_changes_but_not_finished_files() {
+        let updates = Mutex::new(Vec::new());
+        let callback = collect(&updates);
+        let tracker = ProgressTracker::new(&callback, 2, 200, 1);
+
+        // Right after the start, phase changes are too soon to report
+        let file = tracker.start_file("a.png", 100);
+        file.phase(CompressionPhase::Encoding);
+        assert!(updates.lock().unwrap().is_empty());
+
+        thread::sleep(MIN_UPDATE_INTERVAL);
+        file.phase(CompressionPhase::Writing);
+        {
+            let updates = updates.lock().unwrap();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].phase, CompressionPhase::Writing);
+            assert_eq!(updates[0].active_files.len(), 1);
+            assert_eq!(updates[0].active_files[0].filename, "a.png");
+            assert_eq!(updates[0].eta_ms, None);
+        }
+
+        file.finish();
+        tracker.start_file("b.png", 100).finish();
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates.iter().map(|u| u.current).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(updates[2].percent, 100.0);
+        assert_eq!(updates[2].eta_ms, Some(0));
+    }
+}
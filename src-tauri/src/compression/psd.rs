@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind};
+use image::{ColorType, DynamicImage, GrayImage, ImageBuffer, ImageError, ImageResult, Luma, Rgb, RgbImage};
+
+use super::header::{ImageHeader, SourceFormat};
+use super::types::DecodeLimits;
+
+/// File extensions of Photoshop documents (PSB is the large document format)
+pub const EXTENSIONS: &[&str] = &["psd", "psb"];
+
+const SIGNATURE: &[u8] = b"8BPS";
+
+const MODE_GRAYSCALE: u16 = 1;
+const MODE_RGB: u16 = 3;
+
+pub fn is_psd_signature(buf: &[u8]) -> bool {
+    buf.starts_with(SIGNATURE)
+}
+
+/// The fixed header at the start of every PSD/PSB file
+struct PsdHeader {
+    large: bool,      // PSB: 64-bit section lengths and 32-bit RLE row sizes
+    channels: usize,
+    width: u32,
+    height: u32,
+    depth: u16,       // bits per sample
+    mode: u16,
+}
+
+impl PsdHeader {
+    fn read(reader: &mut impl Read) -> ImageResult<Self> {
+        let mut buf = [0u8; 26];
+        reader.read_exact(&mut buf)?;
+
+        if !buf.starts_with(SIGNATURE) {
+            return Err(decoding_error("Missing 8BPS signature"));
+        }
+        let u16_at = |at: usize| u16::from_be_bytes([buf[at], buf[at + 1]]);
+        let u32_at = |at: usize| u32::from_be_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]);
+
+        let header = Self {
+            large: match u16_at(4) {
+                1 => false,
+                2 => true,
+                _ => return Err(decoding_error("Unknown PSD version")),
+            },
+            channels: u16_at(12) as usize,
+            height: u32_at(14),
+            width: u32_at(18),
+            depth: u16_at(22),
+            mode: u16_at(24),
+        };
+
+        if header.width == 0 || header.height == 0 {
+            return Err(decoding_error("PSD image has no pixels"));
+        }
+        if header.channels < header.color_channels() {
+            return Err(decoding_error("PSD image has fewer channels than its color mode needs"));
+        }
+        if !matches!(header.mode, MODE_GRAYSCALE | MODE_RGB) || !matches!(header.depth, 8 | 16) {
+            return Err(unsupported(&format!(
+                "PSD color mode {} at {} bits per channel",
+                header.mode, header.depth
+            )));
+        }
+
+        Ok(header)
+    }
+
+    /// Channels of the composite that carry color; extra channels are masks or spot colors
+    fn color_channels(&self) -> usize {
+        if self.mode == MODE_RGB { 3 } else { 1 }
+    }
+
+    fn color_type(&self) -> ColorType {
+        match (self.mode, self.depth) {
+            (MODE_RGB, 16) => ColorType::Rgb16,
+            (MODE_RGB, _) => ColorType::Rgb8,
+            (_, 16) => ColorType::L16,
+            _ => ColorType::L8,
+        }
+    }
+}
+
+/// Dimensions and color type from the 26-byte file header
+pub fn read_header(path: &Path) -> ImageResult<ImageHeader> {
+    let header = PsdHeader::read(&mut File::open(path)?)?;
+
+    Ok(ImageHeader {
+        format: SourceFormat::Psd,
+        width: header.width,
+        height: header.height,
+        color_type: header.color_type(),
+    })
+}
+
+/// Decode the flattened composite that Photoshop stores after the layers. Documents saved
+/// without "Maximize Compatibility" may only contain a placeholder there.
+pub fn decode(path: &Path, limits: &DecodeLimits) -> ImageResult<DynamicImage> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = PsdHeader::read(&mut reader)?;
+
+    let sample_size = header.depth as usize / 8;
+    let row_len = header.width as usize * sample_size;
+    let plane_len = row_len * header.height as usize;
+    let color_channels = header.color_channels();
+
+    // The planes, plus the PackBits row size table of every channel
+    let row_count = header.channels as u64 * header.height as u64;
+    let alloc = (plane_len * color_channels) as u64 + row_count * std::mem::size_of::<usize>() as u64;
+    if alloc > limits.max_alloc_mb.saturating_mul(1024 * 1024) {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+    }
+
+    // Color mode data, image resources, then layer and mask information
+    skip_section(&mut reader, false)?;
+    skip_section(&mut reader, false)?;
+    skip_section(&mut reader, header.large)?;
+
+    let compression = read_u16(&mut reader)?;
+
+    // The composite is stored planar, one channel after the other
+    let mut planes = Vec::with_capacity(color_channels);
+    match compression {
+        0 => {
+            for _ in 0..color_channels {
+                let mut plane = vec![0u8; plane_len];
+                reader.read_exact(&mut plane)?;
+                planes.push(plane);
+            }
+        }
+        1 => {
+            // Compressed size of every row of every channel, then the PackBits rows
+            // Longest a PackBits row can be: one header byte per 128 literal bytes
+            let max_row_size = row_len + row_len.div_ceil(128);
+
+            let mut row_sizes = Vec::with_capacity(row_count as usize);
+            for _ in 0..row_count {
+                let size = if header.large { read_u32(&mut reader)? as usize } else { read_u16(&mut reader)? as usize };
+                if size > max_row_size {
+                    return Err(decoding_error("PackBits row is longer than the image row"));
+                }
+                row_sizes.push(size);
+            }
+
+            let mut packed = Vec::new();
+            for channel_rows in row_sizes.chunks(header.height as usize).take(color_channels) {
+                let mut plane = Vec::with_capacity(plane_len);
+                for &size in channel_rows {
+                    packed.resize(size, 0);
+                    reader.read_exact(&mut packed)?;
+                    unpack_bits(&packed, row_len, &mut plane)?;
+                }
+                planes.push(plane);
+            }
+        }
+        _ => return Err(unsupported("ZIP-compressed PSD composite")),
+    }
+
+    let (width, height) = (header.width, header.height);
+    let img = match sample_size {
+        1 if color_channels == 3 => {
+            let data = (0..plane_len).flat_map(|i| [planes[0][i], planes[1][i], planes[2][i]]).collect();
+            RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        1 => GrayImage::from_raw(width, height, planes.swap_remove(0)).map(DynamicImage::ImageLuma8),
+        _ => {
+            // 16-bit samples are big-endian
+            let sample = |plane: &[u8], i: usize| u16::from_be_bytes([plane[i * 2], plane[i * 2 + 1]]);
+            let pixels = plane_len / 2;
+            if color_channels == 3 {
+                let data = (0..pixels)
+                    .flat_map(|i| [sample(&planes[0], i), sample(&planes[1], i), sample(&planes[2], i)])
+                    .collect();
+                ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+            } else {
+                let data = (0..pixels).map(|i| sample(&planes[0], i)).collect();
+                ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+            }
+        }
+    };
+
+    img.ok_or_else(|| decoding_error("Pixel data does not match the image size"))
+}
+
+/// Skip a section that starts with its length
+fn skip_section(reader: &mut BufReader<File>, large: bool) -> ImageResult<()> {
+    let len = if large {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        u64::from_be_bytes(buf)
+    } else {
+        read_u32(reader)? as u64
+    };
+
+    let len = i64::try_from(len).map_err(|_| decoding_error("Invalid section length"))?;
+    reader.seek(SeekFrom::Current(len))?;
+    Ok(())
+}
+
+fn read_u16(reader: &mut impl Read) -> ImageResult<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> ImageResult<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// Decode one PackBits row of `row_len` bytes and append it to `out`
+fn unpack_bits(packed: &[u8], row_len: usize, out: &mut Vec<u8>) -> ImageResult<()> {
+    let end = out.len() + row_len;
+    let mut input = packed.iter();
+
+    while out.len() < end {
+        let Some(&header) = input.next() else { break };
+        match header as i8 {
+            // 1 + n literal bytes
+            n @ 0.. => {
+                for _ in 0..=n {
+                    out.push(*input.next().ok_or_else(|| decoding_error("Truncated PackBits run"))?);
+                }
+            }
+            -128 => {}
+            // The next byte 1 - n times
+            n => {
+                let value = *input.next().ok_or_else(|| decoding_error("Truncated PackBits run"))?;
+                out.extend(std::iter::repeat(value).take((1 - n as isize) as usize));
+            }
+        }
+    }
+
+    if out.len() != end {
+        return Err(decoding_error("PackBits row has the wrong length"));
+    }
+    Ok(())
+}
+
+fn decoding_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("PSD".to_string()), message.to_string()))
+}
+
+fn unsupported(feature: &str) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Name("PSD".to_string()),
+        UnsupportedErrorKind::GenericFeature(feature.to_string()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(packed: &[u8], row_len: usize) -> ImageResult<Vec<u8>> {
+        let mut out = Vec::new();
+        unpack_bits(packed, row_len, &mut out).map(|()| out)
+    }
+
+    #[test]
+    fn expands_literal_and_repeat_runs() {
+        // 2 literal bytes, a no-op header, then 0x07 three times
+        assert_eq!(unpack(&[1, 0xAA, 0xBB, 0x80, 0xFE, 0x07], 5).unwrap(), [0xAA, 0xBB, 7, 7, 7]);
+        assert_eq!(unpack(&[0x81, 0x00], 128).unwrap(), vec![0; 128]);
+    }
+
+    #[test]
+    fn appends_rows_to_the_existing_output() {
+        let mut out = vec![1, 2];
+        unpack_bits(&[0xFF, 9], 2, &mut out).unwrap();
+        assert_eq!(out, [1, 2, 9, 9]);
+    }
+
+    #[test]
+    fn rejects_truncated_runs() {
+        assert!(unpack(&[3, 1, 2], 4).is_err());
+        assert!(unpack(&[0xFD], 4).is_err());
+    }
+
+    #[test]
+    fn rejects_rows_of_the_wrong_length() {
+        // Too short: the data runs out before the row is filled
+        assert!(unpack(&[1, 1, 2], 4).is_err());
+        // Too long: the last run crosses the end of the row
+        assert!(unpack(&[0xFE, 5], 3).is_ok());
+        assert!(unpack(&[0xFD, 5], 3).is_err());
+        assert!(unpack(&[2, 1, 2, 3], 2).is_err());
+    }
+}
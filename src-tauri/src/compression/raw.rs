@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use image::codecs::jpeg::JpegDecoder;
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::io::Reader as ImageReader;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult, RgbImage};
+use rawloader::{RawImage, RawImageData, RawLoader};
+
+use super::header::{ImageHeader, SourceFormat};
+use super::limits::decoder_limits;
+use super::resample::linear_to_srgb_lut;
+use super::transform::{apply_orientation, swaps_dimensions};
+use super::types::{DecodeLimits, RawConfig, RawMode};
+
+/// File extensions of the camera RAW formats read here
+pub const EXTENSIONS: &[&str] = &["cr2", "nef", "nrw", "arw", "srf", "sr2", "dng", "orf", "rw2", "pef", "srw", "raf"];
+
+/// IFDs visited at most while looking for previews, against loops and corrupt files
+const MAX_IFDS: usize = 32;
+
+/// Entries read at most from one IFD
+const MAX_IFD_ENTRIES: u16 = 1024;
+
+/// Whether a file is a camera RAW. Most RAW formats are plain TIFF files, so for those
+/// the extension decides; CR2, ORF, RW2 and RAF have signatures of their own.
+pub fn is_raw(path: &Path, buf: &[u8]) -> bool {
+    let own_signature = [&b"FUJIFILMCCD-RAW"[..], b"IIRO", b"IIRS", b"MMOR", b"IIU\0"];
+    if own_signature.iter().any(|signature| buf.starts_with(signature)) {
+        return true;
+    }
+
+    let is_tiff = buf.starts_with(b"II*\0") || buf.starts_with(b"MM\0*");
+    is_tiff && (buf.get(8..10) == Some(b"CR") || has_raw_extension(path))
+}
+
+fn has_raw_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Dimensions (after rotation) of what `decode` returns: the embedded preview, or the
+/// usable sensor area when developing (read without decoding the sensor data)
+pub fn read_header(path: &Path, config: &RawConfig) -> ImageResult<ImageHeader> {
+    let layout = read_layout(path)?;
+
+    let (width, height) = match preview_to_use(path, &layout, config)? {
+        Some(preview) => (preview.width, preview.height),
+        None => {
+            let (_, _, width, height) = crop_area(&read_sensor(path)?)?;
+            (width as u32, height as u32)
+        }
+    };
+
+    let (width, height) = if swaps_dimensions(layout.orientation) { (height, width) } else { (width, height) };
+
+    Ok(ImageHeader {
+        format: SourceFormat::Raw,
+        width,
+        height,
+        color_type: ColorType::Rgb8,
+    })
+}
+
+/// Decode a RAW file to upright 8-bit RGB, from its embedded preview or by developing
+/// the sensor data
+pub fn decode(path: &Path, limits: &DecodeLimits, config: &RawConfig) -> ImageResult<DynamicImage> {
+    let layout = read_layout(path)?;
+
+    let img = match preview_to_use(path, &layout, config)? {
+        Some(preview) => decode_preview(path, &preview, limits)?,
+        None => develop(path, limits, config)?,
+    };
+
+    Ok(apply_orientation(img, layout.orientation))
+}
+
+/// Embedded JPEGs and orientation found in the TIFF structure of a RAW file
+#[derive(Default)]
+struct Layout {
+    jpegs: Vec<(u64, u64)>,  // offset and length
+    orientation: u16,        // EXIF orientation of IFD0, 1 = upright
+}
+
+/// An embedded JPEG whose header could be read
+struct Preview {
+    offset: u64,
+    length: u64,
+    width: u32,
+    height: u32,
+}
+
+fn read_layout(path: &Path) -> io::Result<Layout> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    let mut signature = [0u8; 16];
+    file.read_exact(&mut signature)?;
+
+    // RAF keeps its preview offset and length in a header of its own (big-endian)
+    if signature.starts_with(b"FUJIFILMCCD-RAW") {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(84))?;
+        file.read_exact(&mut header)?;
+
+        return Ok(Layout {
+            jpegs: vec![(
+                u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64,
+                u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64,
+            )],
+            orientation: 1,
+        });
+    }
+
+    let big_endian = match &signature[0..2] {
+        b"MM" => true,
+        b"II" => false,
+        _ => return Ok(Layout::default()),
+    };
+    let mut tiff = Tiff { file, big_endian };
+    let first_ifd = tiff.u32_at(4)? as u64;
+
+    let mut layout = Layout { jpegs: Vec::new(), orientation: 1 };
+    let mut pending = vec![first_ifd];
+    let mut visited = HashSet::new();
+
+    while let Some(ifd) = pending.pop() {
+        if ifd == 0 || visited.len() >= MAX_IFDS || !visited.insert(ifd) {
+            continue;
+        }
+        // Corrupt entries end the walk through this IFD, not the whole search
+        let _ = tiff.read_ifd(ifd, ifd == first_ifd, &mut layout, &mut pending);
+    }
+
+    Ok(layout)
+}
+
+/// Byte-order aware reads from a TIFF structure
+struct Tiff {
+    file: BufReader<File>,
+    big_endian: bool,
+}
+
+impl Tiff {
+    fn bytes<const N: usize>(&mut self, offset: u64) -> io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u16_at(&mut self, offset: u64) -> io::Result<u16> {
+        let b = self.bytes::<2>(offset)?;
+        Ok(if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    }
+
+    fn u32_at(&mut self, offset: u64) -> io::Result<u32> {
+        let b = self.bytes::<4>(offset)?;
+        Ok(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    /// Value `index` of a SHORT or LONG entry, stored inline when it fits into 4 bytes
+    fn value(&mut self, entry: u64, index: u32) -> io::Result<u32> {
+        let short = self.u16_at(entry + 2)? == 3;
+        let count = self.u32_at(entry + 4)?;
+        let size = if short { 2 } else { 4 };
+
+        let values = if count as u64 * size <= 4 { entry + 8 } else { self.u32_at(entry + 8)? as u64 };
+        let at = values + index as u64 * size;
+        if short { self.u16_at(at).map(u32::from) } else { self.u32_at(at) }
+    }
+
+    /// Collect the JPEGs referenced by one IFD and queue its sub-IFDs and successor
+    fn read_ifd(&mut self, ifd: u64, is_first: bool, layout: &mut Layout, pending: &mut Vec<u64>) -> io::Result<()> {
+        let count = self.u16_at(ifd)?.min(MAX_IFD_ENTRIES);
+
+        let mut compression = 0;
+        let (mut strip_offset, mut strip_length) = (None, None);
+        let (mut jpeg_offset, mut jpeg_length) = (None, None);
+
+        for entry in (0..count as u64).map(|i| ifd + 2 + i * 12) {
+            let tag = self.u16_at(entry)?;
+            let values = self.u32_at(entry + 4)?;
+
+            match tag {
+                0x0103 => compression = self.value(entry, 0)?,
+                0x0111 if values == 1 => strip_offset = Some(self.value(entry, 0)?),
+                0x0117 if values == 1 => strip_length = Some(self.value(entry, 0)?),
+                0x0201 => jpeg_offset = Some(self.value(entry, 0)?),
+                0x0202 => jpeg_length = Some(self.value(entry, 0)?),
+                // Panasonic's JpgFromRaw: the JPEG itself is the entry's (undefined) data
+                0x002e => layout.jpegs.push((self.u32_at(entry + 8)? as u64, values as u64)),
+                0x0112 if is_first => layout.orientation = self.value(entry, 0)? as u16,
+                0x014a => {
+                    for index in 0..values.min(MAX_IFDS as u32) {
+                        pending.push(self.value(entry, index)? as u64);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let (Some(offset), Some(length)) = (jpeg_offset, jpeg_length) {
+            layout.jpegs.push((offset as u64, length as u64));
+        }
+        // Single-strip JPEG images (CR2 and DNG previews)
+        if let (6 | 7, Some(offset), Some(length)) = (compression, strip_offset, strip_length) {
+            layout.jpegs.push((offset as u64, length as u64));
+        }
+
+        pending.push(self.u32_at(ifd + 2 + count as u64 * 12)? as u64);
+        Ok(())
+    }
+}
+
+/// The preview `decode` returns, `None` when the sensor data is developed instead
+fn preview_to_use(path: &Path, layout: &Layout, config: &RawConfig) -> io::Result<Option<Preview>> {
+    match config.mode {
+        RawMode::Preview => largest_preview(path, layout),
+        RawMode::Develop => Ok(None),
+    }
+}
+
+/// The embedded JPEG with the most pixels. Only 8-bit color JPEGs count, which leaves
+/// out the lossless JPEG that some formats use for the sensor data itself.
+fn largest_preview(path: &Path, layout: &Layout) -> io::Result<Option<Preview>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut largest: Option<Preview> = None;
+
+    for &(offset, length) in &layout.jpegs {
+        if length == 0 || file.seek(SeekFrom::Start(offset)).is_err() {
+            continue;
+        }
+        let Ok(decoder) = JpegDecoder::new((&mut file).take(length)) else { continue };
+        if decoder.color_type() != ColorType::Rgb8 {
+            continue;
+        }
+
+        let (width, height) = decoder.dimensions();
+        let pixels = width as u64 * height as u64;
+        if largest.as_ref().map_or(true, |p| pixels > p.width as u64 * p.height as u64) {
+            largest = Some(Preview { offset, length, width, height });
+        }
+    }
+
+    Ok(largest)
+}
+
+fn decode_preview(path: &Path, preview: &Preview, limits: &DecodeLimits) -> ImageResult<DynamicImage> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(preview.offset))?;
+
+    let mut jpeg = Vec::new();
+    file.take(preview.length).read_to_end(&mut jpeg)?;
+
+    let mut reader = ImageReader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg);
+    reader.limits(decoder_limits(limits));
+    reader.decode()
+}
+
+fn decode_raw(path: &Path) -> ImageResult<RawImage> {
+    rawloader::decode_file(path).map_err(|e| decoding_error(&e.to_string()))
+}
+
+/// Sensor size, crops and levels, without decoding (or allocating) the sensor data
+fn read_sensor(path: &Path) -> ImageResult<RawImage> {
+    let mut file = BufReader::new(File::open(path)?);
+    RawLoader::new()
+        .decode(&mut file, true)
+        .map_err(|e| decoding_error(&e.to_string()))
+}
+
+/// Top, left, width and height of the usable sensor area
+fn crop_area(raw: &RawImage) -> ImageResult<(usize, usize, usize, usize)> {
+    let [top, right, bottom, left] = raw.crops;
+    let width = raw.width.saturating_sub(left + right);
+    let height = raw.height.saturating_sub(top + bottom);
+
+    if width == 0 || height == 0 {
+        return Err(decoding_error("RAW image has no pixels"));
+    }
+    Ok((top, left, width, height))
+}
+
+/// Demosaic the sensor data (bilinear) with black and white levels, white balance and
+/// exposure applied. Colors stay in the camera's RGB space, no color matrix is applied.
+fn develop(path: &Path, limits: &DecodeLimits, config: &RawConfig) -> ImageResult<DynamicImage> {
+    // Check the sensor data (16 bits per sample) and the developed image against the
+    // limit before rawloader allocates the former
+    let sensor = read_sensor(path)?;
+    let (_, _, width, height) = crop_area(&sensor)?;
+    let sensor_bytes = sensor.width as u64 * sensor.height as u64 * sensor.cpp as u64 * 2;
+    if sensor_bytes + width as u64 * height as u64 * 3 > limits.max_alloc_mb.saturating_mul(1024 * 1024) {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+    }
+
+    let raw = decode_raw(path)?;
+    let data = match &raw.data {
+        RawImageData::Integer(data) => data,
+        RawImageData::Float(_) => return Err(decoding_error("Floating-point RAW data is not supported")),
+    };
+    if raw.cpp != 1 && raw.cpp != 3 {
+        return Err(decoding_error(&format!("Unsupported RAW layout with {} components per pixel", raw.cpp)));
+    }
+
+    let (top, left, width, height) = crop_area(&raw)?;
+
+    // One factor per CFA color (R, G, B and the fourth color of some sensors) that maps
+    // the level range to 0-1, balanced and exposed
+    let white_balance = if config.camera_white_balance { camera_white_balance(&raw) } else { [1.0; 4] };
+    let exposure = 2f32.powf(config.exposure);
+    let scale: [f32; 4] = std::array::from_fn(|color| {
+        let range = raw.whitelevels[color].saturating_sub(raw.blacklevels[color]).max(1);
+        white_balance[color] * exposure / range as f32
+    });
+    let level = |value: u16, color: usize| value.saturating_sub(raw.blacklevels[color]) as f32 * scale[color];
+
+    let srgb = linear_to_srgb_lut();
+    let encode = |linear: f32| srgb[(linear.clamp(0.0, 1.0) * 65535.0).round() as usize];
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for row in top..top + height {
+        for col in left..left + width {
+            let rgb = if raw.cpp == 3 {
+                let at = (row * raw.width + col) * 3;
+                std::array::from_fn(|channel| level(data[at + channel], channel))
+            } else {
+                interpolate(&raw, data, row, col, &level)
+            };
+            pixels.extend(rgb.map(encode));
+        }
+    }
+
+    RgbImage::from_raw(width as u32, height as u32, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| decoding_error("Pixel data does not match the image size"))
+}
+
+/// Bilinear demosaicing: the pixel's own color from the sensor, the others averaged
+/// over its 3×3 neighbourhood
+fn interpolate(raw: &RawImage, data: &[u16], row: usize, col: usize, level: &dyn Fn(u16, usize) -> f32) -> [f32; 3] {
+    // The fourth color of RGBE sensors is a green
+    let channel = |color: usize| if color == 3 { 1 } else { color };
+
+    let own_color = raw.cfa.color_at(row, col);
+    let mut sum = [0f32; 3];
+    let mut count = [0u32; 3];
+
+    for r in row.saturating_sub(1)..=(row + 1).min(raw.height - 1) {
+        for c in col.saturating_sub(1)..=(col + 1).min(raw.width - 1) {
+            let color = raw.cfa.color_at(r, c);
+            sum[channel(color)] += level(data[r * raw.width + c], color);
+            count[channel(color)] += 1;
+        }
+    }
+
+    std::array::from_fn(|ch| {
+        if ch == channel(own_color) {
+            level(data[row * raw.width + col], own_color)
+        } else if count[ch] > 0 {
+            sum[ch] / count[ch] as f32
+        } else {
+            0.0
+        }
+    })
+}
+
+/// As-shot white balance relative to green; missing coefficients count as 1
+fn camera_white_balance(raw: &RawImage) -> [f32; 4] {
+    let valid = |c: f32| c.is_finite() && c > 0.0;
+    let green = raw.wb_coeffs[1];
+    if !valid(green) {
+        return [1.0; 4];
+    }
+
+    raw.wb_coeffs.map(|c| if valid(c) { c / green } else { 1.0 })
+}
+
+fn decoding_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("RAW".to_string()), message.to_string()))
+}
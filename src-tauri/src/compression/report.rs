@@ -0,0 +1,447 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chrono::Local;
+use serde::Serialize;
+
+use super::error::CompressionError;
+use super::types::{CompressResult, FileResult, FileStatus, ImageError, ReportFormat};
+
+/// Number of images listed under "largest savings"
+const LARGEST_SAVINGS_COUNT: usize = 10;
+
+/// Batch totals; sizes only count images that produced an output
+#[derive(Serialize, Clone, Debug)]
+pub struct ReportTotals {
+    pub total: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub kept_original: usize,
+    pub skipped: usize,
+    pub original_bytes: u64,
+    pub output_bytes: u64,
+    pub saved_bytes: i64,       // negative when the outputs grew
+    pub savings_percent: f64,
+    pub duration_ms: u128,
+}
+
+/// Totals for all images of one source format (judged by file extension)
+#[derive(Serialize, Clone, Debug)]
+pub struct FormatBreakdown {
+    pub format: String,
+    pub count: usize,
+    pub failed: usize,
+    pub original_bytes: u64,
+    pub output_bytes: u64,
+    pub saved_bytes: i64,
+}
+
+/// Everything a report contains, built from a finished job
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchReport<'a> {
+    pub generated_at: String,
+    pub totals: ReportTotals,
+    pub formats: Vec<FormatBreakdown>,
+    pub largest_savings: Vec<&'a FileResult>,
+    pub errors: &'a [ImageError],
+    pub files: &'a [FileResult],
+}
+
+impl<'a> BatchReport<'a> {
+    pub fn new(result: &'a CompressResult) -> Self {
+        let written = || result.files.iter().filter(|f| f.output_path.is_some());
+
+        let original_bytes: u64 = written().map(|f| f.original_size).sum();
+        let output_bytes: u64 = written().map(|f| f.output_size).sum();
+        let saved_bytes = original_bytes as i64 - output_bytes as i64;
+
+        let totals = ReportTotals {
+            total: result.total,
+            successful: result.successful,
+            failed: result.failed,
+            kept_original: result.kept_original,
+            skipped: result.skipped,
+            original_bytes,
+            output_bytes,
+            saved_bytes,
+            savings_percent: if original_bytes > 0 {
+                saved_bytes as f64 / original_bytes as f64 * 100.0
+            } else {
+                0.0
+            },
+            duration_ms: result.duration_ms,
+        };
+
+        // Sorted by format name
+        let mut formats: BTreeMap<String, FormatBreakdown> = BTreeMap::new();
+        for file in &result.files {
+            let format = source_format(file);
+            let entry = formats.entry(format.clone()).or_insert_with(|| FormatBreakdown {
+                format,
+                count: 0,
+                failed: 0,
+                original_bytes: 0,
+                output_bytes: 0,
+                saved_bytes: 0,
+            });
+
+            entry.count += 1;
+            if file.status == FileStatus::Failed {
+                entry.failed += 1;
+            }
+            if file.output_path.is_some() {
+                entry.original_bytes += file.original_size;
+                entry.output_bytes += file.output_size;
+                entry.saved_bytes += file.saved_bytes();
+            }
+        }
+
+        let mut largest_savings: Vec<&FileResult> = written().filter(|f| f.saved_bytes() > 0).collect();
+        largest_savings.sort_by_key(|f| std::cmp::Reverse(f.saved_bytes()));
+        largest_savings.truncate(LARGEST_SAVINGS_COUNT);
+
+        Self {
+            generated_at: Local::now().to_rfc3339(),
+            totals,
+            formats: formats.into_values().collect(),
+            largest_savings,
+            errors: &result.errors,
+            files: &result.files,
+        }
+    }
+
+    /// Render the report in the given format
+    pub fn render(&self, format: ReportFormat) -> Result<String, CompressionError> {
+        match format {
+            ReportFormat::Csv => Ok(self.to_csv()),
+            ReportFormat::Json => serde_json::to_string_pretty(self).map_err(CompressionError::internal),
+            ReportFormat::Html => Ok(self.to_html()),
+        }
+    }
+
+    /// One row per image
+    fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "source_path,output_path,status,original_size,output_size,saved_bytes,original_width,original_height,output_width,output_height,quality,duration_ms,error\n",
+        );
+
+        for file in self.files {
+            let row = [
+                csv_field(&file.source_path),
+                csv_field(file.output_path.as_deref().unwrap_or_default()),
+                status_name(file.status).to_string(),
+                file.original_size.to_string(),
+                file.output_size.to_string(),
+                file.saved_bytes().to_string(),
+                file.original_width.to_string(),
+                file.original_height.to_string(),
+                file.output_width.to_string(),
+                file.output_height.to_string(),
+                file.quality.map(|quality| quality.to_string()).unwrap_or_default(),
+                file.duration_ms.to_string(),
+                csv_field(&file.error.as_ref().map(|e| e.to_string()).unwrap_or_default()),
+            ];
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// Self-contained page (inline styles, no external resources)
+    fn to_html(&self) -> String {
+        let totals = &self.totals;
+        let mut html = String::new();
+
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Image Compression Report</title>\n");
+        html.push_str(REPORT_STYLE);
+        html.push_str("</head>\n<body>\n");
+        html.push_str("<h1>Image Compression Report</h1>\n");
+        html.push_str(&format!("<p class=\"muted\">Generated {}</p>\n", escape_html(&self.generated_at)));
+
+        // Totals
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        for (label, value) in [
+            ("Images", totals.total.to_string()),
+            ("Compressed", totals.successful.to_string()),
+            ("Kept original", totals.kept_original.to_string()),
+            ("Skipped", totals.skipped.to_string()),
+            ("Failed", totals.failed.to_string()),
+            ("Original size", format_bytes(totals.original_bytes)),
+            ("Output size", format_bytes(totals.output_bytes)),
+            ("Saved", format!("{} ({:.1}%)", format_saved(totals.saved_bytes), totals.savings_percent)),
+            ("Duration", format!("{:.1} s", totals.duration_ms as f64 / 1000.0)),
+        ] {
+            html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(&value)));
+        }
+        html.push_str("</table>\n");
+
+        // Per-format breakdown
+        html.push_str("<h2>By format</h2>\n<table>\n");
+        html.push_str("<tr><th>Format</th><th>Images</th><th>Failed</th><th>Original</th><th>Output</th><th>Saved</th></tr>\n");
+        for format in &self.formats {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&format.format),
+                format.count,
+                format.failed,
+                format_bytes(format.original_bytes),
+                format_bytes(format.output_bytes),
+                format_saved(format.saved_bytes)
+            ));
+        }
+        html.push_str("</table>\n");
+
+        // Largest savings
+        if !self.largest_savings.is_empty() {
+            html.push_str("<h2>Largest savings</h2>\n<table>\n");
+            html.push_str("<tr><th>Image</th><th>Original</th><th>Output</th><th>Saved</th></tr>\n");
+            for file in &self.largest_savings {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(&file.source_path),
+                    format_bytes(file.original_size),
+                    format_bytes(file.output_size),
+                    format_saved(file.saved_bytes())
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        // Errors
+        if !self.errors.is_empty() {
+            html.push_str("<h2>Errors</h2>\n<table>\n<tr><th>Image</th><th>Error</th></tr>\n");
+            for error in self.errors {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td class=\"error\">{}</td></tr>\n",
+                    escape_html(&error.path),
+                    escape_html(&error.error)
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        // Every image
+        html.push_str("<h2>All images</h2>\n<table>\n");
+        html.push_str("<tr><th>Image</th><th>Status</th><th>Original</th><th>Output</th><th>Dimensions</th><th>Quality</th><th>Time</th></tr>\n");
+        for file in self.files {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{} ms</td></tr>\n",
+                escape_html(&file.source_path),
+                status_name(file.status),
+                format_bytes(file.original_size),
+                if file.output_path.is_some() { format_bytes(file.output_size) } else { "–".to_string() },
+                format_dimensions(file),
+                file.quality.map_or_else(|| "–".to_string(), |quality| quality.to_string()),
+                file.duration_ms
+            ));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+
+        html
+    }
+}
+
+/// Render a report of a finished job and write it to `path`
+pub fn write_report(result: &CompressResult, format: ReportFormat, path: &Path) -> Result<(), CompressionError> {
+    let contents = BatchReport::new(result).render(format)?;
+    fs::write(path, contents).map_err(|e| CompressionError::write(path, &e))
+}
+
+const REPORT_STYLE: &str = "<style>
+body { font-family: system-ui, sans-serif; margin: 2rem auto; max-width: 960px; color: #1f2937; }
+h1 { margin-bottom: 0; }
+h2 { margin-top: 2rem; font-size: 1.1rem; }
+.muted { color: #6b7280; }
+table { border-collapse: collapse; width: 100%; font-size: 0.875rem; }
+th, td { border-bottom: 1px solid #e5e7eb; padding: 0.35rem 0.5rem; text-align: left; word-break: break-all; }
+th { background: #f9fafb; }
+.error { color: #b91c1c; }
+</style>
+";
+
+/// Upper-case extension of the source file, e.g. "JPG"
+fn source_format(file: &FileResult) -> String {
+    Path::new(&file.source_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_uppercase())
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+fn status_name(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::Compressed => "compressed",
+        FileStatus::Skipped => "skipped",
+        FileStatus::KeptOriginal => "kept original",
+        FileStatus::Failed => "failed",
+    }
+}
+
+fn format_dimensions(file: &FileResult) -> String {
+    let dims = |w: u32, h: u32| if w > 0 && h > 0 { format!("{}×{}", w, h) } else { "–".to_string() };
+    if file.output_path.is_some() {
+        format!("{} → {}", dims(file.original_width, file.original_height), dims(file.output_width, file.output_height))
+    } else {
+        dims(file.original_width, file.original_height)
+    }
+}
+
+/// Saved bytes, with a minus sign when the output grew
+fn format_saved(bytes: i64) -> String {
+    if bytes < 0 {
+        format!("-{}", format_bytes(bytes.unsigned_abs()))
+    } else {
+        format_bytes(bytes as u64)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break.
+/// Values that spreadsheets would run as formulas get a leading apostrophe.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::types::FailureReason;
+
+    use super::*;
+
+    fn file(source_path: &str, output_size: Option<u64>, status: FileStatus) -> FileResult {
+        FileResult {
+            source_path: source_path.to_string(),
+            output_path: output_size.map(|_| format!("out/{}", source_path)),
+            original_size: 1000,
+            output_size: output_size.unwrap_or(0),
+            original_width: 40,
+            original_height: 30,
+            output_width: 20,
+            output_height: 15,
+            quality: output_size.map(|_| 80.0),
+            duration_ms: 5,
+            status,
+            error: None,
+        }
+    }
+
+    fn batch() -> CompressResult {
+        let mut result = CompressResult::new();
+        result.total = 4;
+        result.add_success(file("a.jpg", Some(400), FileStatus::Compressed));
+        result.add_success(file("b.png", Some(1200), FileStatus::Compressed));
+        result.add_success(file("c.JPG", None, FileStatus::Skipped));
+
+        let error = CompressionError::DecodeFailed { path: "<d>.jpg".to_string(), cause: "bad \"marker\"".to_string() };
+        let failed = FileResult { error: Some(error.clone()), ..file("<d>.jpg", None, FileStatus::Failed) };
+        result.add_error(ImageError {
+            path: "<d>.jpg".to_string(),
+            filename: "<d>.jpg".to_string(),
+            error: error.to_string(),
+            reason: FailureReason::Corrupt,
+            details: error,
+        }, failed);
+        result
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("photos/a.jpg"), "photos/a.jpg");
+        assert_eq!(csv_field("a,b.jpg"), "\"a,b.jpg\"");
+        assert_eq!(csv_field("say \"hi\".jpg"), "\"say \"\"hi\"\".jpg\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        // Would run as formulas in a spreadsheet
+        assert_eq!(csv_field("=SUM(A1)"), "'=SUM(A1)");
+        assert_eq!(csv_field("-1,2"), "\"'-1,2\"");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape_html("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn totals_only_count_written_outputs() {
+        let result = batch();
+        let report = BatchReport::new(&result);
+
+        assert_eq!((report.totals.successful, report.totals.skipped, report.totals.failed), (2, 1, 1));
+        assert_eq!((report.totals.original_bytes, report.totals.output_bytes, report.totals.saved_bytes), (2000, 1600, 400));
+        assert_eq!(report.totals.savings_percent, 20.0);
+
+        let formats: Vec<_> = report.formats.iter().map(|f| (f.format.as_str(), f.count, f.failed, f.saved_bytes)).collect();
+        assert_eq!(formats, [("JPG", 3, 1, 600), ("PNG", 1, 0, -200)]);
+        // Outputs that grew are not savings
+        assert_eq!(report.largest_savings.iter().map(|f| f.source_path.as_str()).collect::<Vec<_>>(), ["a.jpg"]);
+    }
+
+    #[test]
+    fn renders_one_csv_row_per_image() {
+        let result = batch();
+        let csv = BatchReport::new(&result).render(ReportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("source_path,output_path,status,"));
+        assert_eq!(lines[1], "a.jpg,out/a.jpg,compressed,1000,400,600,40,30,20,15,80,5,");
+        assert_eq!(lines[3], "c.JPG,,skipped,1000,0,0,40,30,20,15,,5,");
+        assert!(lines[4].ends_with(",failed,1000,0,0,40,30,20,15,,5,\"Invalid or corrupted image file: bad \"\"marker\"\"\""));
+    }
+
+    #[test]
+    fn renders_json_with_the_totals_and_files() {
+        let result = batch();
+        let json = BatchReport::new(&result).render(ReportFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["totals"]["saved_bytes"], 400);
+        assert_eq!(value["files"].as_array().unwrap().len(), 4);
+        assert_eq!(value["files"][3]["error"]["code"], "decode_failed");
+        assert_eq!(value["errors"][0]["path"], "<d>.jpg");
+    }
+
+    #[test]
+    fn escapes_names_and_errors_in_html() {
+        let result = batch();
+        let html = BatchReport::new(&result).render(ReportFormat::Html).unwrap();
+
+        assert!(!html.contains("<d>"));
+        assert!(html.contains("<td>&lt;d&gt;.jpg</td><td class=\"error\">Invalid or corrupted image file: bad &quot;marker&quot;</td>"));
+        assert!(html.contains("<tr><th>Saved</th><td>400 B (20.0%)</td></tr>"));
+        assert!(html.contains("<td>-200 B</td>"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::OnceLock;
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba, RgbaImage};
+use image::imageops::{self, FilterType};
+
+use super::types::{ResizeFilter, SharpenConfig};
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Resize to exact dimensions, optionally in linear light.
+///
+/// Averaging gamma-encoded sRGB values darkens fine detail and shifts dark areas, so
+/// linear resizing converts to 16-bit linear light (premultiplied when there is alpha),
+/// resamples, and converts back.
+pub fn resize(img: &DynamicImage, width: u32, height: u32, filter: ResizeFilter, linear: bool) -> DynamicImage {
+    let filter_type: FilterType = filter.into();
+
+    // Nearest neighbour never blends pixels, so gamma does not matter
+    if !linear || filter == ResizeFilter::Nearest {
+        return img.resize_exact(width, height, filter_type);
+    }
+
+    let to_linear = srgb_to_linear_lut();
+    let to_srgb = linear_to_srgb_lut();
+
+    if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        let linear: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let p = rgba.get_pixel(x, y);
+            let alpha = p[3] as u32;
+            let premultiply = |c: u8| (to_linear[c as usize] as u32 * alpha / 255) as u16;
+            Rgba([premultiply(p[0]), premultiply(p[1]), premultiply(p[2]), p[3] as u16 * 257])
+        });
+
+        let resized = imageops::resize(&linear, width, height, filter_type);
+
+        let srgb = ImageBuffer::from_fn(width, height, |x, y| {
+            let p = resized.get_pixel(x, y);
+            let alpha = p[3] as u32;
+            // Fully transparent pixels have no colour to recover
+            let unpremultiply = |c: u16| {
+                (c as u32 * 65535)
+                    .checked_div(alpha)
+                    .map_or(0, |linear| to_srgb[linear.min(65535) as usize])
+            };
+            Rgba([unpremultiply(p[0]), unpremultiply(p[1]), unpremultiply(p[2]), (alpha / 257) as u8])
+        });
+
+        DynamicImage::ImageRgba8(srgb)
+    } else {
+        let rgb = img.to_rgb8();
+        let linear: ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+            let p = rgb.get_pixel(x, y);
+            Rgb([to_linear[p[0] as usize], to_linear[p[1] as usize], to_linear[p[2] as usize]])
+        });
+
+        let resized = imageops::resize(&linear, width, height, filter_type);
+
+        let srgb = ImageBuffer::from_fn(width, height, |x, y| {
+            let p = resized.get_pixel(x, y);
+            Rgb([to_srgb[p[0] as usize], to_srgb[p[1] as usize], to_srgb[p[2] as usize]])
+        });
+
+        DynamicImage::ImageRgb8(srgb)
+    }
+}
+
+/// Unsharp mask: add back the difference to a blurred copy where it exceeds the threshold
+///
+/// Only the colour channels are sharpened; alpha is left untouched.
+pub fn unsharp_mask(img: &RgbaImage, sharpen: &SharpenConfig) -> RgbaImage {
+    let blurred = imageops::blur(img, sharpen.radius);
+    let threshold = sharpen.threshold as i32;
+
+    let mut output = img.clone();
+    for (out, (orig, blur)) in output.pixels_mut().zip(img.pixels().zip(blurred.pixels())) {
+        for channel in 0..3 {
+            let diff = orig[channel] as i32 - blur[channel] as i32;
+            if diff.abs() >= threshold {
+                let sharpened = orig[channel] as f32 + diff as f32 * sharpen.amount;
+                out[channel] = sharpened.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    output
+}
+
+/// 8-bit sRGB to 16-bit linear light
+fn srgb_to_linear_lut() -> &'static [u16; 256] {
+    static LUT: OnceLock<[u16; 256]> = OnceLock::new();
+    LUT.get_or_init(|| {
+        let mut lut = [0u16; 256];
+        for (i, value) in lut.iter_mut().enumerate() {
+            let c = i as f64 / 255.0;
+            let linear = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+            *value = (linear * 65535.0).round() as u16;
+        }
+        lut
+    })
+}
+
+/// 16-bit linear light to 8-bit sRGB
+pub fn linear_to_srgb_lut() -> &'static [u8] {
+    static LUT: OnceLock<Vec<u8>> = OnceLock::new();
+    LUT.get_or_init(|| {
+        (0..=65535u32)
+            .map(|i| {
+                let linear = i as f64 / 65535.0;
+                let c = if linear <= 0.0031308 {
+                    linear * 12.92
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                };
+                (c * 255.0).round().clamp(0.0, 255.0) as u8
+            })
+            .collect()
+    })
+}
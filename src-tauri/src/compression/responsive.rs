@@ -0,0 +1,333 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use super::encode::{encode_bytes, write_output};
+use super::error::CompressionError;
+use super::header::read_metadata;
+use super::jpeg::insert_metadata;
+use super::limits::check_dimensions;
+use super::naming::{discard_on_error, get_unique_filename, FilenameTemplate, NamingContext};
+use super::pipeline::ImagePipeline;
+use super::types::{CompressionPhase, OutputFormat, ResponsiveConfig};
+
+/// `sizes` attribute used in the generated `<picture>` snippet
+const DEFAULT_SIZES: &str = "100vw";
+
+/// Largest variant width, the most a WebP image can store
+pub const MAX_WIDTH: u32 = 16383;
+
+/// A single generated file of a responsive image set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResponsiveVariant {
+    pub path: String,
+    pub filename: String,
+    pub format: OutputFormat,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,  // bytes
+}
+
+/// Manifest written next to the variants of one source image
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResponsiveManifest {
+    pub source: String,
+    pub original_size: u64,
+    pub width: u32,   // after transforms and crop
+    pub height: u32,
+    pub variants: Vec<ResponsiveVariant>,
+}
+
+impl ResponsiveManifest {
+    /// The variant that stands in for the original: largest width in the fallback format
+    pub fn primary_variant(&self) -> Option<&ResponsiveVariant> {
+        let fallback = fallback_format(&self.variants)?;
+        self.variants
+            .iter()
+            .filter(|v| v.format == fallback)
+            .max_by_key(|v| v.width)
+    }
+}
+
+/// Generate every configured width/format of one source image, plus its manifest
+/// (`<name>.json`) and optionally a `<picture>` snippet (`<name>.html`)
+pub fn generate_variants(
+    input_path: &Path,
+    output_dir: &Path,
+    pipeline: &ImagePipeline,
+    responsive: &ResponsiveConfig,
+    template: &FilenameTemplate,
+    counter: usize,
+    on_phase: &dyn Fn(CompressionPhase),
+) -> Result<ResponsiveManifest, CompressionError> {
+    let quality = pipeline.quality();
+    let original_size = fs::metadata(input_path)
+        .map(|m| m.len())
+        .map_err(|e| CompressionError::read(input_path, &e))?;
+
+    // Decode once, then resize from the full-resolution image for every width
+    on_phase(CompressionPhase::Decoding);
+    let img = pipeline.decode(input_path)?;
+    let (source_width, source_height) = (img.width(), img.height());
+    let metadata = read_metadata(input_path);
+
+    let variant_template = template.with_width_suffix();
+    let mut variants = Vec::new();
+
+    for width in target_widths(&responsive.widths, source_width, responsive.allow_upscale) {
+        let height = ((source_height as f64 * width as f64 / source_width as f64).round() as u32).max(1);
+
+        // Upscaled variants are larger than the source that was admitted
+        check_dimensions(input_path, width, height, pipeline.limits())?;
+
+        on_phase(CompressionPhase::Encoding);
+        let resized = pipeline.finish(&img, width, height);
+
+        for &format in &responsive.formats {
+            let filename = variant_template.render(&NamingContext {
+                input_path,
+                counter,
+                width,
+                height,
+                quality,
+                extension: format.extension(),
+            });
+
+            on_phase(CompressionPhase::Encoding);
+            let mut bytes = encode_bytes(&resized, format, quality, pipeline.jpeg())?;
+            if format == OutputFormat::Jpeg {
+                bytes = insert_metadata(bytes, &metadata);
+            }
+
+            // Reserved once the bytes are ready, so a failed encode leaves no empty file
+            let output_path = get_unique_filename(output_dir.join(filename));
+
+            on_phase(CompressionPhase::Writing);
+            let size = discard_on_error(&output_path, write_output(&output_path, &bytes))?;
+
+            variants.push(ResponsiveVariant {
+                filename: output_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                path: output_path.display().to_string(),
+                format,
+                mime_type: format.mime_type().to_string(),
+                width,
+                height,
+                size,
+            });
+        }
+    }
+
+    let manifest = ResponsiveManifest {
+        source: input_path.display().to_string(),
+        original_size,
+        width: source_width,
+        height: source_height,
+        variants,
+    };
+
+    // Manifest and snippet share the base name of the variants
+    let base_name = |extension: &str| {
+        template.render(&NamingContext {
+            input_path,
+            counter,
+            width: source_width,
+            height: source_height,
+            quality,
+            extension,
+        })
+    };
+
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(CompressionError::internal)?;
+    let manifest_path = get_unique_filename(output_dir.join(base_name("json")));
+    discard_on_error(&manifest_path, fs::write(&manifest_path, json)
+        .map_err(|e| CompressionError::write(&manifest_path, &e)))?;
+
+    if responsive.emit_html {
+        let alt = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        let html_path = get_unique_filename(output_dir.join(base_name("html")));
+        discard_on_error(&html_path, fs::write(&html_path, picture_html(&manifest, alt))
+            .map_err(|e| CompressionError::write(&html_path, &e)))?;
+    }
+
+    Ok(manifest)
+}
+
+/// Largest dimensions among the variants of a `width`×`height` source, for admitting it
+/// to the memory budget and pixel limit before decoding
+pub fn largest_variant(width: u32, height: u32, responsive: &ResponsiveConfig) -> (u32, u32) {
+    let largest = target_widths(&responsive.widths, width, responsive.allow_upscale)
+        .last()
+        .copied()
+        .unwrap_or(width);
+    if largest <= width || width == 0 {
+        return (width, height);
+    }
+
+    (largest, (height as f64 * largest as f64 / width as f64).round() as u32)
+}
+
+/// Sorted, de-duplicated widths; unless upscaling is allowed, widths above the source
+/// collapse into a single variant at the source width
+fn target_widths(widths: &[u32], source_width: u32, allow_upscale: bool) -> Vec<u32> {
+    let mut targets: Vec<u32> = widths
+        .iter()
+        .map(|&w| if allow_upscale { w } else { w.min(source_width) })
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+}
+
+/// Format used for the `<img>` fallback: JPEG if generated, else PNG, else whatever exists
+fn fallback_format(variants: &[ResponsiveVariant]) -> Option<OutputFormat> {
+    [OutputFormat::Jpeg, OutputFormat::Png]
+        .into_iter()
+        .find(|format| variants.iter().any(|v| v.format == *format))
+        .or_else(|| variants.first().map(|v| v.format))
+}
+
+/// Build a `<picture>` element with one `<source>` per modern format and an `<img>` fallback
+fn picture_html(manifest: &ResponsiveManifest, alt: &str) -> String {
+    let Some(fallback) = fallback_format(&manifest.variants) else {
+        return String::new();
+    };
+
+    let srcset = |format: OutputFormat| {
+        manifest.variants
+            .iter()
+            .filter(|v| v.format == format)
+            .map(|v| format!("{} {}w", srcset_url(&v.filename), v.width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut html = String::from("<picture>\n");
+
+    // Modern formats first so the browser picks them when supported
+    let mut formats: Vec<OutputFormat> = Vec::new();
+    for variant in &manifest.variants {
+        if variant.format != fallback && !formats.contains(&variant.format) {
+            formats.push(variant.format);
+        }
+    }
+    for format in formats {
+        html.push_str(&format!(
+            "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+            format.mime_type(),
+            escape_attr(&srcset(format)),
+            DEFAULT_SIZES
+        ));
+    }
+
+    if let Some(primary) = manifest.primary_variant() {
+        html.push_str(&format!(
+            "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\" loading=\"lazy\" decoding=\"async\">\n",
+            escape_attr(&srcset_url(&primary.filename)),
+            escape_attr(&srcset(fallback)),
+            DEFAULT_SIZES,
+            primary.width,
+            primary.height,
+            escape_attr(alt)
+        ));
+    }
+
+    html.push_str("</picture>\n");
+    html
+}
+
+/// Percent-encode characters that would break srcset parsing
+fn srcset_url(filename: &str) -> String {
+    filename.replace('%', "%25").replace(' ', "%20").replace(',', "%2C")
+}
+
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::compression::types::CompressionConfig;
+
+    fn responsive(widths: &[u32], allow_upscale: bool) -> ResponsiveConfig {
+        ResponsiveConfig {
+            widths: widths.to_vec(),
+            formats: vec![OutputFormat::Jpeg],
+            allow_upscale,
+            emit_html: false,
+        }
+    }
+
+    #[test]
+    fn widths_above_the_source_collapse_unless_upscaling() {
+        assert_eq!(target_widths(&[800, 320, 2000, 1600], 1000, false), [320, 800, 1000]);
+        assert_eq!(target_widths(&[800, 320, 2000, 320], 1000, true), [320, 800, 2000]);
+    }
+
+    #[test]
+    fn admits_the_largest_variant() {
+        assert_eq!(largest_variant(1000, 500, &responsive(&[320, 2000], false)), (1000, 500));
+        assert_eq!(largest_variant(1000, 500, &responsive(&[320, 2000], true)), (2000, 1000));
+        assert_eq!(largest_variant(1000, 500, &responsive(&[320, 640], true)), (1000, 500));
+        assert_eq!(largest_variant(0, 0, &responsive(&[320], true)), (0, 0));
+    }
+
+    #[test]
+    fn rejects_widths_no_format_can_store() {
+        assert!(responsive(&[320, MAX_WIDTH], true).validate().is_ok());
+        assert!(responsive(&[320, MAX_WIDTH + 1], true).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_upscaled_variants_over_the_pixel_limit() {
+        let dir = std::env::temp_dir().join(format!("responsive-limit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.png");
+        RgbImage::new(40, 20).save(&source).unwrap();
+
+        let config: CompressionConfig = serde_json::from_value(serde_json::json!({
+            "source_paths": [&source],
+            "output_folder": &dir,
+            "quality": 80.0,
+            "size_ratio": 1.0,
+            "thread_count": 1,
+            "preserve_structure": false,
+            "limits": { "max_pixels": 10_000 },
+        }))
+        .unwrap();
+        let pipeline = ImagePipeline::new(&config).unwrap();
+
+        let result = generate_variants(
+            &source,
+            &dir,
+            &pipeline,
+            &responsive(&[20, 400], true),
+            &FilenameTemplate::default(),
+            1,
+            &|_| {},
+        );
+
+        assert!(matches!(result, Err(CompressionError::ImageTooLarge { .. })), "{:?}", result);
+        let mut written: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        written.sort();
+        assert_eq!(written, ["a-20w.jpg", "a.png"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::{ColorType, DynamicImage, ImageError, ImageResult, RgbaImage};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, ImageHrefResolver, ImageHrefStringResolverFn, Indent, Options, Tree, WriteOptions};
+
+use super::encode::write_output;
+use super::error::CompressionError;
+use super::header::{ImageHeader, SourceFormat};
+use super::types::{CompressionPhase, DecodeLimits, SvgConfig, SvgSize};
+
+/// File extensions of SVG images (SVGZ is gzip-compressed SVG)
+pub const EXTENSIONS: &[&str] = &["svg", "svgz"];
+
+/// Pixel density at which SVG user units are one pixel
+const CSS_DPI: f32 = 96.0;
+
+/// Whether a file is an SVG image. A leading `<svg` is enough; XML prologs, comments
+/// and gzip-compressed files count when the extension says SVG.
+pub fn is_svg(path: &Path, buf: &[u8]) -> bool {
+    let text = buf.strip_prefix(b"\xef\xbb\xbf").unwrap_or(buf);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(text.len());
+    let text = &text[start..];
+
+    if text.starts_with(b"<svg") {
+        return true;
+    }
+
+    let has_svg_extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+    has_svg_extension && (text.starts_with(b"<?xml") || text.starts_with(b"<!") || buf.starts_with(&[0x1f, 0x8b]))
+}
+
+/// Intrinsic size of the drawing (its `width` and `height`, or the viewBox)
+pub fn read_header(path: &Path) -> ImageResult<ImageHeader> {
+    let tree = parse(path)?;
+    let size = tree.size().to_int_size();
+
+    Ok(ImageHeader {
+        format: SourceFormat::Svg,
+        width: size.width(),
+        height: size.height(),
+        color_type: ColorType::Rgba8,
+    })
+}
+
+/// Pixel size at which an SVG of the given intrinsic size is rendered
+pub fn render_size((width, height): (u32, u32), config: &SvgConfig) -> (u32, u32) {
+    let scale = match config.size {
+        SvgSize::Dpi { dpi } => dpi / CSS_DPI,
+        SvgSize::Width { width: target } => target as f32 / width.max(1) as f32,
+    };
+
+    let scaled = |length: u32| (length as f32 * scale).round().max(1.0) as u32;
+    (scaled(width), scaled(height))
+}
+
+/// Render an SVG to RGBA at the configured size
+pub fn decode(path: &Path, limits: &DecodeLimits, config: &SvgConfig) -> ImageResult<DynamicImage> {
+    let tree = parse(path)?;
+    let size = tree.size();
+
+    let intrinsic = size.to_int_size();
+    let (width, height) = render_size((intrinsic.width(), intrinsic.height()), config);
+
+    // The render size comes from the settings, so the header check doesn't cover it
+    let pixels = width as u64 * height as u64;
+    if pixels > limits.max_pixels {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)));
+    }
+    if pixels * 4 > limits.max_alloc_mb.saturating_mul(1024 * 1024) {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+    }
+
+    let mut pixmap = Pixmap::new(width, height).ok_or_else(|| decoding_error("Invalid render size"))?;
+    let transform = Transform::from_scale(width as f32 / size.width(), height as f32 / size.height());
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia keeps premultiplied alpha
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    RgbaImage::from_raw(width, height, data)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| decoding_error("Pixel data does not match the render size"))
+}
+
+/// Write an SVG minified instead of rasterizing it, returning the output size.
+/// The output is usvg's normalized form: styles resolved, shapes as paths and no
+/// whitespace, comments or editor metadata. Text stays text, so it doesn't depend on
+/// the fonts installed here.
+pub fn write_minified(
+    input_path: &Path,
+    output_path: &Path,
+    on_phase: &dyn Fn(CompressionPhase),
+) -> Result<u64, CompressionError> {
+    on_phase(CompressionPhase::Decoding);
+    let tree = parse(input_path).map_err(|e| CompressionError::decode(input_path, &e))?;
+
+    on_phase(CompressionPhase::Encoding);
+    let svg = tree.to_string(&WriteOptions {
+        indent: Indent::None,
+        attributes_indent: Indent::None,
+        preserve_text: true,
+        coordinates_precision: 3,
+        transforms_precision: 5,
+        ..WriteOptions::default()
+    });
+
+    on_phase(CompressionPhase::Writing);
+    write_output(output_path, svg.as_bytes())
+}
+
+fn parse(path: &Path) -> ImageResult<Tree> {
+    let data = fs::read(path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let options = Options {
+        // Relative image references resolve next to the SVG
+        resources_dir: Some(dir.clone()),
+        fontdb: system_fonts(),
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: ImageHrefResolver::default_data_resolver(),
+            resolve_string: contained_file_resolver(dir),
+        },
+        ..Options::default()
+    };
+
+    Tree::from_data(&data, &options).map_err(|e| decoding_error(&e.to_string()))
+}
+
+/// Resolve `<image>` file references only inside the SVG's own directory. usvg would
+/// otherwise embed any readable file, e.g. an absolute path, into the output.
+fn contained_file_resolver(dir: PathBuf) -> ImageHrefStringResolverFn<'static> {
+    let resolve = ImageHrefResolver::default_string_resolver();
+    let root = dir.canonicalize().ok();
+
+    Box::new(move |href, options| {
+        // Canonical paths, so neither `..` nor symlinks lead outside
+        let path = options.get_abs_path(Path::new(href)).canonicalize().ok()?;
+        if !root.as_ref().is_some_and(|root| path.starts_with(root)) {
+            log::warn!("Ignoring SVG image outside the SVG's directory: {}", href);
+            return None;
+        }
+        resolve(path.to_str()?, options)
+    })
+}
+
+/// Fonts for text elements, loaded from the system once
+fn system_fonts() -> Arc<fontdb::Database> {
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut database = fontdb::Database::new();
+            database.load_system_fonts();
+
+            // The generic families default to Windows fonts and unmatched text falls back to
+            // serif, so point missing generics at an installed font rather than drop text
+            let installed = database.faces().next().and_then(|face| face.families.first()).map(|(name, _)| name.clone());
+            if let Some(installed) = installed {
+                let is_missing = |database: &fontdb::Database, family| {
+                    let query = fontdb::Query { families: &[family], ..Default::default() };
+                    database.query(&query).is_none()
+                };
+                if is_missing(&database, fontdb::Family::Serif) {
+                    database.set_serif_family(installed.as_str());
+                }
+                if is_missing(&database, fontdb::Family::SansSerif) {
+                    database.set_sans_serif_family(installed.as_str());
+                }
+                if is_missing(&database, fontdb::Family::Monospace) {
+                    database.set_monospace_family(installed.as_str());
+                }
+            }
+
+            Arc::new(database)
+        })
+        .clone()
+}
+
+fn decoding_error(message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("SVG".to_string()), message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// An empty directory of its own for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("svg-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_the_minified_svg() {
+        let dir = temp_dir("minify");
+        let source = dir.join("a.svg");
+        fs::write(&source, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"10\" height=\"10\">\n  <!-- comment -->\n  <rect width=\"10\" height=\"10\" style=\"fill: red\"/>\n</svg>\n").unwrap();
+
+        let size = write_minified(&source, &dir.join("out.svg"), &|_| {}).unwrap();
+
+        let minified = fs::read_to_string(dir.join("out.svg")).unwrap();
+        assert_eq!(size, minified.len() as u64);
+        assert!(!minified.contains("comment") && !minified.contains('\n'));
+
+        // Write errors are reported like those of every other output
+        let missing = dir.join("missing").join("out.svg");
+        assert!(matches!(write_minified(&source, &missing, &|_| {}), Err(CompressionError::WriteFailed { .. })));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_embeds_images_inside_the_svg_directory() {
+        let dir = temp_dir("href");
+        let svg_dir = dir.join("svg");
+        fs::create_dir_all(svg_dir.join("sub")).unwrap();
+        RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255])).save(svg_dir.join("sub/inside.png")).unwrap();
+        RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])).save(dir.join("outside.png")).unwrap();
+
+        let outside = dir.join("outside.png");
+        let source = svg_dir.join("a.svg");
+        fs::write(&source, format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"12\" height=\"4\">\
+             <image href=\"sub/inside.png\" width=\"4\" height=\"4\"/>\
+             <image x=\"4\" href=\"../outside.png\" width=\"4\" height=\"4\"/>\
+             <image x=\"8\" xlink:href=\"{}\" width=\"4\" height=\"4\"/>\
+             </svg>",
+            outside.display()
+        )).unwrap();
+
+        let img = decode(&source, &DecodeLimits::default(), &SvgConfig::default()).unwrap().to_rgba8();
+
+        assert_eq!(img.get_pixel(2, 2), &Rgba([0, 255, 0, 255]));
+        assert_eq!(img.get_pixel(6, 2)[3], 0);
+        assert_eq!(img.get_pixel(10, 2)[3], 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
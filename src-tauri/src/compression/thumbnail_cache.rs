@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::error::CompressionError;
+
+/// Default upper bound for the cache directory (roughly 20-40k thumbnails)
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+/// File extension of cache entries
+const ENTRY_EXTENSION: &str = "thumb";
+
+/// Identifies a thumbnail: any change to the file (size or mtime) or thumbnail size misses
+pub struct ThumbnailKey {
+    path: PathBuf,
+    file_size: u64,
+    modified_nanos: u128,
+    thumbnail_size: u32,
+}
+
+impl ThumbnailKey {
+    pub fn new(path: &Path, metadata: &fs::Metadata, thumbnail_size: u32) -> Self {
+        let modified_nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+
+        Self {
+            path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            file_size: metadata.len(),
+            modified_nanos,
+            thumbnail_size,
+        }
+    }
+
+    /// Entry file name: a hash of the key that stays the same across Rust releases
+    /// (unlike `DefaultHasher`), so upgrades keep the cache
+    fn file_name(&self) -> String {
+        let path = self.path.as_os_str().as_encoded_bytes();
+
+        let mut hash = Fnv1a::new();
+        hash.write(&(path.len() as u64).to_le_bytes());
+        hash.write(path);
+        hash.write(&self.file_size.to_le_bytes());
+        hash.write(&self.modified_nanos.to_le_bytes());
+        hash.write(&self.thumbnail_size.to_le_bytes());
+
+        format!("{:016x}.{}", hash.0, ENTRY_EXTENSION)
+    }
+}
+
+/// 64-bit FNV-1a
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Cached thumbnail together with the image dimensions, so a hit needs no decoding at all
+pub struct CachedThumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub png: Vec<u8>,
+}
+
+/// Thumbnail cache stored in the app cache directory.
+///
+/// Each entry is one file: 8 bytes of width/height (little endian) followed by the PNG.
+/// Hits touch the file's mtime, so eviction removes the least recently used entries.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+
+    /// Look up a thumbnail, returning `None` on a miss or an unreadable entry
+    pub fn get(&self, key: &ThumbnailKey) -> Option<CachedThumbnail> {
+        let entry_path = self.dir.join(key.file_name());
+        let bytes = fs::read(&entry_path).ok()?;
+        if bytes.len() <= 8 {
+            return None;
+        }
+
+        // Mark as recently used for eviction
+        if let Ok(file) = fs::File::options().write(true).open(&entry_path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(CachedThumbnail {
+            width: u32::from_le_bytes(bytes[0..4].try_into().ok()?),
+            height: u32::from_le_bytes(bytes[4..8].try_into().ok()?),
+            png: bytes[8..].to_vec(),
+        })
+    }
+
+    /// Store a thumbnail; failures are logged and otherwise ignored
+    pub fn put(&self, key: &ThumbnailKey, width: u32, height: u32, png: &[u8]) {
+        if let Err(e) = self.try_put(key, width, height, png) {
+            log::warn!("Failed to write thumbnail cache entry: {}", e);
+        }
+    }
+
+    fn try_put(&self, key: &ThumbnailKey, width: u32, height: u32, png: &[u8]) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut bytes = Vec::with_capacity(8 + png.len());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(png);
+
+        // Write to a temporary file first so readers never see a partial entry
+        let entry_path = self.dir.join(key.file_name());
+        let temp_path = entry_path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+        fs::write(&temp_path, &bytes)?;
+        fs::rename(&temp_path, &entry_path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+
+    /// Remove least recently used entries until the cache fits in `max_bytes`.
+    /// Returns the number of bytes freed.
+    pub fn evict(&self) -> Result<u64, CompressionError> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return Ok(0);
+        }
+
+        // Oldest first
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut freed = 0;
+        for (path, size, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+                freed += size;
+            }
+        }
+
+        Ok(freed)
+    }
+
+    /// Delete the whole cache directory (including stale temporary files).
+    /// Returns the number of bytes freed.
+    pub fn clear(&self) -> Result<u64, CompressionError> {
+        let freed = self.size()?;
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(freed),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(CompressionError::write(&self.dir, &e)),
+        }
+    }
+
+    /// Total size of all entries in bytes
+    pub fn size(&self) -> Result<u64, CompressionError> {
+        Ok(self.entries()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// All cache entries as (path, size, last used)
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>, CompressionError> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(CompressionError::read(&self.dir, &e)),
+        };
+
+        Ok(read_dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some(ENTRY_EXTENSION))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), modified))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,92 @@
+use image::{DynamicImage, Rgb32FImage, Rgba32FImage, RgbImage, RgbaImage};
+
+use super::resample::linear_to_srgb_lut;
+
+/// Scene brightness that the average luminance is mapped to (Reinhard's "key")
+const KEY: f32 = 0.18;
+
+/// Offset that keeps black pixels out of the logarithm
+const LOG_EPSILON: f32 = 1e-4;
+
+/// Map floating-point (HDR or OpenEXR) images to 8-bit sRGB with the global Reinhard
+/// operator: the average luminance is exposed to a mid-grey and the brightest pixel to
+/// white. Other images are returned unchanged.
+pub fn tone_map(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageRgb32F(rgb) => DynamicImage::ImageRgb8(map_rgb(&rgb)),
+        DynamicImage::ImageRgba32F(rgba) => DynamicImage::ImageRgba8(map_rgba(&rgba)),
+        img => img,
+    }
+}
+
+fn map_rgb(img: &Rgb32FImage) -> RgbImage {
+    let operator = Reinhard::fit(img.pixels().map(|p| [p[0], p[1], p[2]]));
+    let data = img.pixels().flat_map(|p| operator.apply([p[0], p[1], p[2]])).collect();
+    RgbImage::from_raw(img.width(), img.height(), data).expect("buffer matches the image size")
+}
+
+fn map_rgba(img: &Rgba32FImage) -> RgbaImage {
+    let operator = Reinhard::fit(img.pixels().map(|p| [p[0], p[1], p[2]]));
+    let data = img
+        .pixels()
+        .flat_map(|p| {
+            let [r, g, b] = operator.apply([p[0], p[1], p[2]]);
+            [r, g, b, (finite(p[3]).clamp(0.0, 1.0) * 255.0).round() as u8]
+        })
+        .collect();
+    RgbaImage::from_raw(img.width(), img.height(), data).expect("buffer matches the image size")
+}
+
+/// Global Reinhard operator fitted to one image
+struct Reinhard {
+    exposure: f32,      // scales luminance so the log-average lands on KEY
+    white_squared: f32, // exposed luminance that maps to white, squared
+}
+
+impl Reinhard {
+    fn fit(pixels: impl Iterator<Item = [f32; 3]>) -> Self {
+        let (mut log_sum, mut max, mut count) = (0f64, 0f32, 0usize);
+        for rgb in pixels {
+            let luminance = luminance(rgb);
+            log_sum += (LOG_EPSILON + luminance).ln() as f64;
+            max = max.max(luminance);
+            count += 1;
+        }
+
+        let log_average = (log_sum / count.max(1) as f64).exp() as f32;
+        let exposure = KEY / log_average.max(LOG_EPSILON);
+        let white = (max * exposure).max(1.0);
+
+        Self {
+            exposure,
+            white_squared: white * white,
+        }
+    }
+
+    /// Compress the pixel's luminance and scale its color with it, then encode to sRGB
+    fn apply(&self, rgb: [f32; 3]) -> [u8; 3] {
+        let rgb = rgb.map(|c| finite(c).max(0.0));
+        let luminance = luminance(rgb);
+        if luminance <= 0.0 {
+            return [0; 3];
+        }
+
+        let exposed = luminance * self.exposure;
+        let mapped = exposed * (1.0 + exposed / self.white_squared) / (1.0 + exposed);
+        let scale = mapped / luminance;
+
+        let srgb = linear_to_srgb_lut();
+        rgb.map(|c| srgb[((c * scale).clamp(0.0, 1.0) * 65535.0).round() as usize])
+    }
+}
+
+/// Relative luminance of linear Rec. 709 primaries
+fn luminance(rgb: [f32; 3]) -> f32 {
+    let [r, g, b] = rgb.map(|c| finite(c).max(0.0));
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// NaN and infinite samples (which OpenEXR allows) count as black
+fn finite(value: f32) -> f32 {
+    if value.is_finite() { value } else { 0.0 }
+}
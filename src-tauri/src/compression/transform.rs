@@ -0,0 +1,160 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use super::types::{FlipDirection, TransformOp};
+
+/// Apply the configured operations in order
+pub fn apply_transforms(img: DynamicImage, ops: &[TransformOp]) -> DynamicImage {
+    ops.iter().fold(img, apply_transform)
+}
+
+/// Dimensions after the transforms (only rotations by 90° and 270° change them)
+pub fn transformed_dimensions(width: u32, height: u32, ops: &[TransformOp]) -> (u32, u32) {
+    ops.iter().fold((width, height), |(w, h), op| match op {
+        TransformOp::Rotate { degrees: 90 | 270 } => (h, w),
+        _ => (w, h),
+    })
+}
+
+/// Whether an EXIF orientation turns the image by 90 degrees
+pub fn swaps_dimensions(orientation: u16) -> bool {
+    (5..=8).contains(&orientation)
+}
+
+/// Operations that turn an image upright according to its EXIF orientation
+pub fn orientation_ops(orientation: u16) -> &'static [TransformOp] {
+    const FLIP_H: TransformOp = TransformOp::Flip { direction: FlipDirection::Horizontal };
+
+    match orientation {
+        2 => &[FLIP_H],
+        3 => &[TransformOp::Rotate { degrees: 180 }],
+        4 => &[TransformOp::Flip { direction: FlipDirection::Vertical }],
+        5 => &[TransformOp::Rotate { degrees: 90 }, FLIP_H],
+        6 => &[TransformOp::Rotate { degrees: 90 }],
+        7 => &[TransformOp::Rotate { degrees: 270 }, FLIP_H],
+        8 => &[TransformOp::Rotate { degrees: 270 }],
+        _ => &[],
+    }
+}
+
+/// Turn an image upright according to its EXIF orientation
+pub fn apply_orientation(img: DynamicImage, orientation: u16) -> DynamicImage {
+    apply_transforms(img, orientation_ops(orientation))
+}
+
+fn apply_transform(img: DynamicImage, op: &TransformOp) -> DynamicImage {
+    match *op {
+        TransformOp::Rotate { degrees } => match degrees {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img,
+        },
+        TransformOp::Flip { direction: FlipDirection::Horizontal } => img.fliph(),
+        TransformOp::Flip { direction: FlipDirection::Vertical } => img.flipv(),
+        TransformOp::Grayscale => img.grayscale(),
+        TransformOp::Brightness { value } => img.brighten(value),
+        TransformOp::Contrast { value } => img.adjust_contrast(value),
+        TransformOp::Gamma { value } => adjust_gamma(&img, value),
+        TransformOp::Saturation { value } => adjust_saturation(&img, value),
+    }
+}
+
+/// Gamma correction via a lookup table (values above 1 brighten midtones)
+fn adjust_gamma(img: &DynamicImage, gamma: f32) -> DynamicImage {
+    let exponent = 1.0 / gamma;
+    let mut lut = [0u8; 256];
+    for (i, value) in lut.iter_mut().enumerate() {
+        *value = ((i as f32 / 255.0).powf(exponent) * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+
+    map_rgba(img, |p| Rgba([lut[p[0] as usize], lut[p[1] as usize], lut[p[2] as usize], p[3]]))
+}
+
+/// Scale colour saturation around each pixel's luma (0 = grayscale, 1 = unchanged)
+fn adjust_saturation(img: &DynamicImage, factor: f32) -> DynamicImage {
+    map_rgba(img, |p| {
+        // Rec. 601 luma, same weights as `image`'s grayscale conversion
+        let luma = 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32;
+        let channel = |c: u8| (luma + (c as f32 - luma) * factor).round().clamp(0.0, 255.0) as u8;
+        Rgba([channel(p[0]), channel(p[1]), channel(p[2]), p[3]])
+    })
+}
+
+fn map_rgba<F>(img: &DynamicImage, f: F) -> DynamicImage
+where
+    F: Fn(&Rgba<u8>) -> Rgba<u8>,
+{
+    let mut rgba: RgbaImage = img.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        *pixel = f(pixel);
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use super::*;
+
+    const ROTATE_90: TransformOp = TransformOp::Rotate { degrees: 90 };
+    const FLIP_H: TransformOp = TransformOp::Flip { direction: FlipDirection::Horizontal };
+
+    /// 3×2 image whose pixels are numbered row by row, so every layout is distinct
+    fn numbered() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, y| Luma([(y * 3 + x) as u8])))
+    }
+
+    fn rows(img: &DynamicImage) -> Vec<Vec<u8>> {
+        let gray = img.to_luma8();
+        gray.rows().map(|row| row.map(|p| p[0]).collect()).collect()
+    }
+
+    #[test]
+    fn applies_operations_in_order() {
+        // 0 1 2      3 0
+        // 3 4 5  ->  4 1  (rotated clockwise)
+        //            5 2
+        assert_eq!(rows(&apply_transforms(numbered(), &[ROTATE_90])), [[3, 0], [4, 1], [5, 2]]);
+        assert_eq!(rows(&apply_transforms(numbered(), &[ROTATE_90, FLIP_H])), [[0, 3], [1, 4], [2, 5]]);
+        assert_eq!(rows(&apply_transforms(numbered(), &[FLIP_H, ROTATE_90])), [[5, 2], [4, 1], [3, 0]]);
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_dimensions() {
+        assert_eq!(transformed_dimensions(3, 2, &[ROTATE_90]), (2, 3));
+        assert_eq!(transformed_dimensions(3, 2, &[ROTATE_90, TransformOp::Rotate { degrees: 270 }]), (3, 2));
+        assert_eq!(transformed_dimensions(3, 2, &[TransformOp::Rotate { degrees: 180 }, FLIP_H, TransformOp::Grayscale]), (3, 2));
+    }
+
+    #[test]
+    fn turns_every_orientation_upright() {
+        // Where the stored top-left pixel is shown, per the EXIF definition of each orientation
+        let corners = [(1, (0, 0)), (2, (2, 0)), (3, (2, 1)), (4, (0, 1)), (5, (0, 0)), (6, (1, 0)), (7, (1, 2)), (8, (0, 2))];
+        for (orientation, (x, y)) in corners {
+            let upright = apply_orientation(numbered(), orientation).to_luma8();
+            let expected = if swaps_dimensions(orientation) { (2, 3) } else { (3, 2) };
+            assert_eq!(upright.dimensions(), expected, "orientation {}", orientation);
+            assert_eq!(upright.get_pixel(x, y)[0], 0, "orientation {}", orientation);
+        }
+        assert_eq!(rows(&apply_orientation(numbered(), 0)), rows(&numbered()));
+        assert_eq!(rows(&apply_orientation(numbered(), 9)), rows(&numbered()));
+    }
+
+    #[test]
+    fn adjusts_colors() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 100, 50])));
+        let pixel = |ops: &[TransformOp]| apply_transforms(img.clone(), ops).to_rgb8().get_pixel(0, 0).0;
+
+        assert_eq!(pixel(&[TransformOp::Brightness { value: 100 }]), [255, 200, 150]);
+        assert_eq!(pixel(&[TransformOp::Gamma { value: 1.0 }]), [200, 100, 50]);
+        assert_eq!(pixel(&[TransformOp::Saturation { value: 1.0 }]), [200, 100, 50]);
+        let [r, g, b] = pixel(&[TransformOp::Saturation { value: 0.0 }]);
+        assert!(r == g && g == b);
+        // Brightening first clips red, so the order matters
+        assert_ne!(
+            pixel(&[TransformOp::Brightness { value: 100 }, TransformOp::Gamma { value: 2.0 }]),
+            pixel(&[TransformOp::Gamma { value: 2.0 }, TransformOp::Brightness { value: 100 }])
+        );
+    }
+}
@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use super::error::CompressionError;
+use super::naming::FilenameTemplate;
+use super::responsive::MAX_WIDTH;
+use super::watermark::parse_hex_color;
+
 /// Configuration for image compression operations
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CompressionConfig {
@@ -9,28 +14,620 @@ pub struct CompressionConfig {
     pub size_ratio: f32,     // 0-1
     pub thread_count: usize,
     pub preserve_structure: bool,  // Keep folder structure
+    #[serde(default)]
+    pub output_template: Option<String>,  // e.g. "{date:%Y%m%d}_{stem}_{w}px.{ext}"
+    #[serde(default)]
+    pub transforms: Vec<TransformOp>,  // applied in order
+    #[serde(default)]
+    pub crop: Option<CropConfig>,
+    #[serde(default)]
+    pub resize_filter: ResizeFilter,
+    #[serde(default)]
+    pub linear_light: bool,  // resize in linear light instead of gamma-encoded sRGB
+    #[serde(default)]
+    pub sharpen: Option<SharpenConfig>,  // unsharp mask applied after resizing
+    #[serde(default)]
+    pub watermark: Option<WatermarkConfig>,
+    #[serde(default)]
+    pub responsive: Option<ResponsiveConfig>,  // generate srcset variants instead of one output
+    #[serde(default)]
+    pub accept_extensionless: bool,  // also pick up files without an extension, judged by content
+    #[serde(default)]
+    pub jpeg: Option<JpegEncoderConfig>,  // tuned JPEG encoding; None keeps the default encoder
+    #[serde(default)]
+    pub lossless_jpeg: Option<LosslessJpegConfig>,  // JPEG sources without pixel edits keep their DCT data; None re-encodes them
+    #[serde(default)]
+    pub larger_output: LargerOutputPolicy,  // what to do when the output isn't min_savings_percent smaller
+    #[serde(default)]
+    pub min_savings_percent: f32,  // 0-99; 0 only rejects outputs larger than the source
+    #[serde(default)]
+    pub limits: DecodeLimits,  // decoder limits and memory budget
+    #[serde(default)]
+    pub execution: ExecutionMode,  // decode and encode in this process or in worker processes
+    #[serde(default)]
+    pub raw: RawConfig,  // how camera RAW files are turned into pixels
+    #[serde(default)]
+    pub svg: SvgConfig,  // rasterization size of SVG sources, or passthrough
 }
 
 impl CompressionConfig {
-    pub fn validate(&self) -> Result<(), String> {
+    pub fn validate(&self) -> Result<(), CompressionError> {
         if self.source_paths.is_empty() {
-            return Err("No source paths provided".to_string());
+            return Err(CompressionError::NoPaths);
         }
 
         if self.output_folder.is_empty() {
-            return Err("No output folder specified".to_string());
+            return Err(CompressionError::missing_setting("output_folder"));
         }
 
         if self.quality < 0.0 || self.quality > 100.0 {
-            return Err(format!("Quality must be between 0 and 100, got {}", self.quality));
+            return Err(CompressionError::out_of_range("quality", self.quality, 0, 100));
         }
 
         if self.size_ratio < 0.0 || self.size_ratio > 1.0 {
-            return Err(format!("Size ratio must be between 0 and 1, got {}", self.size_ratio));
+            return Err(CompressionError::out_of_range("size_ratio", self.size_ratio, 0, 1));
         }
 
         if self.thread_count == 0 {
-            return Err("Thread count must be at least 1".to_string());
+            return Err(CompressionError::invalid_setting("thread_count", 0, "at least 1"));
+        }
+
+        if let Some(template) = &self.output_template {
+            FilenameTemplate::parse(template)?;
+        }
+
+        for op in &self.transforms {
+            op.validate()?;
+        }
+
+        if let Some(crop) = &self.crop {
+            crop.validate()?;
+        }
+
+        if let Some(sharpen) = &self.sharpen {
+            sharpen.validate()?;
+        }
+
+        if let Some(watermark) = &self.watermark {
+            watermark.validate()?;
+        }
+
+        if let Some(responsive) = &self.responsive {
+            responsive.validate()?;
+        }
+
+        if let Some(jpeg) = &self.jpeg {
+            jpeg.validate()?;
+        }
+
+        if !(0.0..=99.0).contains(&self.min_savings_percent) {
+            return Err(CompressionError::out_of_range("min_savings_percent", self.min_savings_percent, 0, 99));
+        }
+
+        self.limits.validate()?;
+
+        if let ExecutionMode::Isolated { timeout_secs } = self.execution {
+            if timeout_secs == 0 {
+                return Err(CompressionError::invalid_setting("execution.timeout_secs", 0, "at least 1"));
+            }
+        }
+
+        self.raw.validate()?;
+        self.svg.validate()?;
+
+        Ok(())
+    }
+}
+
+/// A single editing operation, applied to every image before cropping and resizing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransformOp {
+    Rotate { degrees: u32 },        // 90, 180 or 270 (clockwise)
+    Flip { direction: FlipDirection },
+    Grayscale,
+    Brightness { value: i32 },      // -255 to 255, added to every channel
+    Contrast { value: f32 },        // -100 to 100, percent
+    Gamma { value: f32 },           // 0.1 to 10, 1 = unchanged
+    Saturation { value: f32 },      // 0 to 3, 0 = grayscale, 1 = unchanged
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipDirection {
+    Horizontal,
+    Vertical,
+}
+
+impl TransformOp {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        match *self {
+            TransformOp::Rotate { degrees } if !matches!(degrees, 90 | 180 | 270) => {
+                Err(CompressionError::invalid_setting("rotate.degrees", degrees, "90, 180 or 270"))
+            }
+            TransformOp::Brightness { value } if !(-255..=255).contains(&value) => {
+                Err(CompressionError::out_of_range("brightness", value, -255, 255))
+            }
+            TransformOp::Contrast { value } if !(-100.0..=100.0).contains(&value) => {
+                Err(CompressionError::out_of_range("contrast", value, -100, 100))
+            }
+            TransformOp::Gamma { value } if !(0.1..=10.0).contains(&value) => {
+                Err(CompressionError::out_of_range("gamma", value, 0.1, 10))
+            }
+            TransformOp::Saturation { value } if !(0.0..=3.0).contains(&value) => {
+                Err(CompressionError::out_of_range("saturation", value, 0, 3))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Crop to a fixed aspect ratio before resizing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CropConfig {
+    pub aspect_width: f32,   // e.g. 4 for 4:5
+    pub aspect_height: f32,  // e.g. 5 for 4:5
+    pub anchor: CropAnchor,
+}
+
+/// Which part of the image is kept when cropping
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CropAnchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    Entropy,    // keep the region with the most varied tones
+    Attention,  // keep the region with the most edge detail
+}
+
+impl CropConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if !(self.aspect_width > 0.0 && self.aspect_height > 0.0) {
+            return Err(CompressionError::invalid_setting(
+                "crop.aspect_ratio",
+                format!("{}:{}", self.aspect_width, self.aspect_height),
+                "positive",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Resampling filter used when resizing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+/// Unsharp mask settings
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SharpenConfig {
+    pub amount: f32,    // 0-5, strength (0.5 = +50% of the detail)
+    pub radius: f32,    // 0.1-10, blur sigma in pixels
+    pub threshold: u8,  // minimum difference (0-255) before a pixel is sharpened
+}
+
+impl SharpenConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if !(0.0..=5.0).contains(&self.amount) {
+            return Err(CompressionError::out_of_range("sharpen.amount", self.amount, 0, 5));
+        }
+
+        if !(0.1..=10.0).contains(&self.radius) {
+            return Err(CompressionError::out_of_range("sharpen.radius", self.radius, 0.1, 10));
+        }
+
+        Ok(())
+    }
+}
+
+/// JPEG encoder backend and its tuning options
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JpegEncoderConfig {
+    #[serde(default)]
+    pub backend: JpegBackend,
+    #[serde(default = "default_true")]
+    pub progressive: bool,
+    #[serde(default = "default_true")]
+    pub trellis: bool,            // trellis quantization (mozjpeg only)
+    #[serde(default = "default_true")]
+    pub optimize_huffman: bool,   // always on for progressive files
+    #[serde(default)]
+    pub subsampling: ChromaSubsampling,
+    #[serde(default)]
+    pub quant_tables: Option<QuantTables>,  // None uses the backend's default tables
+}
+
+impl JpegEncoderConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if let Some(QuantTables::Custom { luma, chroma }) = &self.quant_tables {
+            if self.backend != JpegBackend::Rust {
+                return Err(CompressionError::invalid_setting(
+                    "jpeg.quant_tables",
+                    "custom",
+                    "a preset with the mozjpeg backend",
+                ));
+            }
+
+            for (setting, table) in [("jpeg.quant_tables.luma", luma), ("jpeg.quant_tables.chroma", chroma)] {
+                if table.len() != 64 {
+                    return Err(CompressionError::invalid_setting(setting, table.len(), "64 values"));
+                }
+                if let Some(&value) = table.iter().find(|v| !(1..=255).contains(*v)) {
+                    return Err(CompressionError::out_of_range(setting, value, 1, 255));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Limits that protect against huge images and decompression bombs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct DecodeLimits {
+    #[serde(default = "default_max_pixels")]
+    pub max_pixels: u64,        // width × height; larger images are rejected before decoding
+    #[serde(default = "default_max_alloc_mb")]
+    pub max_alloc_mb: u64,      // memory a single decoder may allocate
+    #[serde(default = "default_memory_budget_mb")]
+    pub memory_budget_mb: u64,  // estimated memory of all images being processed at once
+}
+
+impl DecodeLimits {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        for (setting, value) in [
+            ("limits.max_pixels", self.max_pixels),
+            ("limits.max_alloc_mb", self.max_alloc_mb),
+            ("limits.memory_budget_mb", self.memory_budget_mb),
+        ] {
+            if value == 0 {
+                return Err(CompressionError::invalid_setting(setting, value, "at least 1"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: default_max_pixels(),
+            max_alloc_mb: default_max_alloc_mb(),
+            memory_budget_mb: default_memory_budget_mb(),
+        }
+    }
+}
+
+fn default_max_pixels() -> u64 {
+    150_000_000
+}
+
+fn default_max_alloc_mb() -> u64 {
+    512  // the image crate's default
+}
+
+fn default_memory_budget_mb() -> u64 {
+    2048
+}
+
+/// Where images are decoded and encoded
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ExecutionMode {
+    #[default]
+    InProcess,
+    /// One worker process per thread. A crash, hang or out-of-memory in a codec only
+    /// kills that worker, which is restarted for the next image.
+    Isolated {
+        #[serde(default = "default_worker_timeout")]
+        timeout_secs: u64,  // per image; the worker is killed when it takes longer
+    },
+}
+
+fn default_worker_timeout() -> u64 {
+    120
+}
+
+/// How camera RAW files (CR2, NEF, ARW, DNG, ...) are turned into pixels
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RawMode {
+    #[default]
+    Preview,  // the largest embedded JPEG preview; files without one are developed
+    Develop,  // demosaic the sensor data
+}
+
+/// Camera RAW decoding
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RawConfig {
+    #[serde(default)]
+    pub mode: RawMode,
+    #[serde(default)]
+    pub exposure: f32,  // EV, -5 to 5; develop mode only
+    #[serde(default = "default_true")]
+    pub camera_white_balance: bool,  // develop mode: white balance as shot, false keeps the sensor's colors
+}
+
+impl RawConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if !(-5.0..=5.0).contains(&self.exposure) {
+            return Err(CompressionError::out_of_range("raw.exposure", self.exposure, -5, 5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        Self {
+            mode: RawMode::default(),
+            exposure: 0.0,
+            camera_white_balance: true,
+        }
+    }
+}
+
+/// Size at which SVG sources are rendered, before the usual resizing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SvgSize {
+    Dpi { dpi: f32 },      // 96 renders at the intrinsic size, 192 at twice that
+    Width { width: u32 },  // pixels, height follows the aspect ratio
+}
+
+impl Default for SvgSize {
+    fn default() -> Self {
+        SvgSize::Dpi { dpi: 96.0 }
+    }
+}
+
+/// SVG sources
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct SvgConfig {
+    #[serde(default)]
+    pub size: SvgSize,
+    #[serde(default)]
+    pub passthrough: bool,  // write minified SVGs instead of rasterizing them
+}
+
+impl SvgConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        match self.size {
+            SvgSize::Dpi { dpi } if !(1.0..=2400.0).contains(&dpi) => {
+                Err(CompressionError::out_of_range("svg.dpi", dpi, 1, 2400))
+            }
+            SvgSize::Width { width: 0 } => Err(CompressionError::invalid_setting("svg.width", 0, "at least 1")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What to do with an image whose output is not enough smaller than the source
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LargerOutputPolicy {
+    #[default]
+    KeepOriginal,  // copy the source to the output folder instead
+    Skip,          // write nothing
+    Force,         // keep the output anyway
+}
+
+/// Lossless optimization of JPEG sources (like jpegtran).
+///
+/// Used instead of re-encoding when the batch needs no pixel changes: only rotations,
+/// flips and grayscale, no crop, resize, sharpening or watermark, and no tuned encoder.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LosslessJpegConfig {
+    #[serde(default = "default_true")]
+    pub progressive: bool,      // otherwise baseline; Huffman tables are always optimized
+    #[serde(default)]
+    pub metadata: JpegMetadata,
+}
+
+impl Default for LosslessJpegConfig {
+    fn default() -> Self {
+        Self {
+            progressive: true,
+            metadata: JpegMetadata::default(),
+        }
+    }
+}
+
+/// Metadata segments copied from the source file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JpegMetadata {
+    None,
+    #[default]
+    Essential,  // EXIF and ICC profile; drops comments, XMP, IPTC and other app segments
+    All,
+}
+
+/// Library used to encode JPEG files
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JpegBackend {
+    #[default]
+    Mozjpeg,  // smallest files, supports trellis quantization
+    Rust,     // pure Rust, supports custom quantization tables
+}
+
+/// Resolution of the color (chroma) channels relative to brightness
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    #[serde(rename = "4:4:4")]
+    Full,        // no subsampling, best for text and sharp color edges
+    #[serde(rename = "4:2:2")]
+    Horizontal,  // half horizontal color resolution
+    #[default]
+    #[serde(rename = "4:2:0")]
+    Both,        // half horizontal and vertical color resolution, smallest files
+}
+
+/// Quantization tables used instead of the backend's defaults
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuantTables {
+    /// Well-known table pair, scaled by the quality setting
+    Preset { preset: QuantTablePreset },
+    /// 64 values per table in row-major order, used as-is (quality is ignored)
+    Custom { luma: Vec<u16>, chroma: Vec<u16> },
+}
+
+/// Quantization tables available in both backends
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuantTablePreset {
+    AnnexK,                  // tables from the JPEG standard
+    Flat,
+    MsSsim,                  // tuned for MS-SSIM
+    PsnrHvs,                 // tuned for PSNR-HVS
+    KleinSilversteinCarney,
+}
+
+/// Watermark overlaid on every image before encoding
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WatermarkConfig {
+    pub source: WatermarkSource,
+    pub position: WatermarkPosition,
+    pub opacity: f32,  // 0-1
+    pub margin: f32,   // 0-0.5, fraction of the shorter image side
+}
+
+/// What gets drawn as the watermark
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WatermarkSource {
+    /// Text rendered with the bundled DejaVu Sans Bold font
+    Text {
+        text: String,
+        size: f32,      // 0-1, text height as fraction of the shorter image side
+        color: String,  // "#RRGGBB" or "#RGB"
+    },
+    /// PNG logo (transparency is respected)
+    Image {
+        path: String,
+        scale: f32,  // 0-1, logo width as fraction of the image width
+    },
+}
+
+/// Where the watermark is placed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    Tiled,
+}
+
+impl WatermarkConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(CompressionError::out_of_range("watermark.opacity", self.opacity, 0, 1));
+        }
+
+        if !(0.0..=0.5).contains(&self.margin) {
+            return Err(CompressionError::out_of_range("watermark.margin", self.margin, 0, 0.5));
+        }
+
+        match &self.source {
+            WatermarkSource::Text { text, size, color } => {
+                if text.trim().is_empty() {
+                    return Err(CompressionError::missing_setting("watermark.text"));
+                }
+                if *size <= 0.0 || *size > 1.0 {
+                    return Err(CompressionError::out_of_range("watermark.size", *size, 0, 1));
+                }
+                parse_hex_color(color)?;
+            }
+            WatermarkSource::Image { path, scale } => {
+                if !std::path::Path::new(path).is_file() {
+                    return Err(CompressionError::FileNotFound { path: path.clone() });
+                }
+                if *scale <= 0.0 || *scale > 1.0 {
+                    return Err(CompressionError::out_of_range("watermark.scale", *scale, 0, 1));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Encoded output format
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Png => "image/png",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Responsive image set: every source produces one output per width and format
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResponsiveConfig {
+    pub widths: Vec<u32>,             // e.g. [320, 640, 1280, 1920]
+    pub formats: Vec<OutputFormat>,   // e.g. [webp, jpeg]
+    #[serde(default)]
+    pub allow_upscale: bool,          // otherwise widths above the source width use the source width
+    #[serde(default)]
+    pub emit_html: bool,              // write a <picture> snippet next to the manifest
+}
+
+impl ResponsiveConfig {
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if self.widths.is_empty() {
+            return Err(CompressionError::missing_setting("responsive.widths"));
+        }
+
+        if self.widths.contains(&0) {
+            return Err(CompressionError::invalid_setting("responsive.widths", 0, "greater than 0"));
+        }
+
+        if let Some(&width) = self.widths.iter().find(|&&width| width > MAX_WIDTH) {
+            return Err(CompressionError::invalid_setting("responsive.widths", width, &format!("at most {}", MAX_WIDTH)));
+        }
+
+        if self.formats.is_empty() {
+            return Err(CompressionError::missing_setting("responsive.formats"));
         }
 
         Ok(())
@@ -47,17 +644,48 @@ pub struct ImageInfo {
     pub format: String,          // PNG, JPEG, BMP, etc.
     pub width: u32,
     pub height: u32,
+    pub extension_mismatch: bool, // extension missing or naming a different format than the content
+    pub color_type: String,      // RGB, RGBA, Gray, GrayAlpha
+    pub bit_depth: u8,           // bits per channel
     pub thumbnail: Option<String>, // base64 encoded thumbnail
 }
 
+/// Settings shared by every image of an analysis run
+#[derive(Clone, Copy, Debug)]
+pub struct AnalysisOptions {
+    pub quality: f32,              // 0-100
+    pub size_ratio: f32,           // 0-1
+    pub generate_thumbnails: bool,
+    pub deep_validation: bool,     // fully decode every image instead of reading headers only
+    pub accept_extensionless: bool, // also pick up files without an extension, judged by content
+    pub limits: DecodeLimits,
+    pub raw: RawConfig,
+}
+
+/// Totals of an analysis run (the images themselves are streamed in batches)
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct AnalysisSummary {
+    pub total: usize,               // image files found
+    pub analyzed: usize,            // successfully analyzed
+    pub total_original_size: u64,   // bytes, analyzed images only
+    pub total_estimated_size: u64,
+    pub failed: usize,              // unreadable or corrupt files
+    pub errors: Vec<ImageError>,    // why each failed file was skipped
+    pub cancelled: bool,
+    pub duration_ms: u128,
+}
+
 /// Result of a compression operation
 #[derive(Serialize, Clone, Debug)]
 pub struct CompressResult {
     pub total: usize,
     pub successful: usize,
     pub failed: usize,
-    pub saved_bytes: u64,
+    pub kept_original: usize,       // copied unchanged because the output was not smaller
+    pub skipped: usize,             // not written because the output was not smaller
+    pub saved_bytes: i64,           // negative when the outputs are larger than the sources
     pub errors: Vec<ImageError>,
+    pub files: Vec<FileResult>,     // one entry per image, sorted by source path
     pub duration_ms: u128,
 }
 
@@ -67,33 +695,117 @@ impl CompressResult {
             total: 0,
             successful: 0,
             failed: 0,
+            kept_original: 0,
+            skipped: 0,
             saved_bytes: 0,
             errors: Vec::new(),
+            files: Vec::new(),
             duration_ms: 0,
         }
     }
 
-    pub fn add_success(&mut self, saved: u64) {
-        self.successful += 1;
-        self.saved_bytes += saved;
+    /// Record an image that did not fail; the counter follows its status
+    pub fn add_success(&mut self, file: FileResult) {
+        match file.status {
+            FileStatus::KeptOriginal => self.kept_original += 1,
+            FileStatus::Skipped => self.skipped += 1,
+            _ => self.successful += 1,
+        }
+        self.saved_bytes += file.saved_bytes();
+        self.files.push(file);
     }
 
-    pub fn add_error(&mut self, error: ImageError) {
+    pub fn add_error(&mut self, error: ImageError, file: FileResult) {
         self.failed += 1;
         self.errors.push(error);
+        self.files.push(file);
+    }
+}
+
+/// What happened to one image of a batch
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Compressed,
+    Skipped,        // not written at all
+    KeptOriginal,   // source copied because compressing did not pay off
+    Failed,
+}
+
+/// Per-image outcome of a batch
+#[derive(Serialize, Clone, Debug)]
+pub struct FileResult {
+    pub source_path: String,
+    pub output_path: Option<String>,        // None if nothing was written
+    pub original_size: u64,
+    pub output_size: u64,                   // 0 if nothing was written
+    pub original_width: u32,                // dimensions are 0 if unknown
+    pub original_height: u32,
+    pub output_width: u32,
+    pub output_height: u32,
+    pub quality: Option<f32>,               // None unless re-encoded at the configured quality
+    pub duration_ms: u64,
+    pub status: FileStatus,
+    pub error: Option<CompressionError>,    // set when failed
+}
+
+impl FileResult {
+    /// Bytes saved by this image; negative if the output grew, 0 if nothing was written
+    pub fn saved_bytes(&self) -> i64 {
+        if self.output_path.is_some() {
+            self.original_size as i64 - self.output_size as i64
+        } else {
+            0
+        }
+    }
+}
+
+/// File format of an exported batch report
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Csv,    // one row per image
+    Json,   // totals, breakdowns and every image
+    Html,   // self-contained page for clients
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Html => "html",
+        }
     }
 }
 
+/// Broad category of a per-image failure, so the UI can group and explain them
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    NotFound,
+    PermissionDenied,
+    UnsupportedFormat,
+    Corrupt,
+    TooLarge,      // exceeds decoder limits
+    Io,            // other read/write errors (network share dropped, disk full, ...)
+    Crashed,       // a decoder or encoder panicked, or its worker process died
+    TimedOut,      // its worker process exceeded the per-image timeout
+    Other,
+}
+
 /// Error information for a specific image
 #[derive(Serialize, Clone, Debug)]
 pub struct ImageError {
     pub path: String,
     pub filename: String,
-    pub error: String,
+    pub error: String,                // English message, fallback for the UI
+    pub reason: FailureReason,        // broad category for grouping
+    pub details: CompressionError,    // stable code and parameters for localized messages
 }
 
 impl ImageError {
-    pub fn new(path: String, error: String) -> Self {
+    pub fn new(path: String, error: CompressionError) -> Self {
         let filename = std::path::Path::new(&path)
             .file_name()
             .and_then(|n| n.to_str())
@@ -103,33 +815,64 @@ impl ImageError {
         Self {
             path,
             filename,
-            error,
+            error: error.to_string(),
+            reason: error.reason(),
+            details: error,
         }
     }
 }
 
+/// What the batch (or one of its files) is currently doing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionPhase {
+    Scanning,   // collecting files before the batch starts
+    Decoding,   // reading and decoding the source
+    Encoding,   // editing stages and encoding
+    Writing,    // writing the output file
+}
+
+/// File a worker thread is currently processing
+#[derive(Serialize, Clone, Debug)]
+pub struct ActiveFile {
+    pub worker: usize,
+    pub filename: String,
+    pub phase: CompressionPhase,
+}
+
 /// Progress update during batch processing
 #[derive(Serialize, Clone, Debug)]
 pub struct ProgressUpdate {
-    pub current: usize,
+    pub current: usize,                 // files finished
     pub total: usize,
-    pub current_file: String,
-    pub percent: f32,
+    pub current_file: String,           // file that triggered this update
+    pub percent: f32,                   // by bytes, so large files weigh more
+    pub phase: CompressionPhase,        // phase of current_file
+    pub bytes_processed: u64,           // source bytes of finished files
+    pub total_bytes: u64,
+    pub elapsed_ms: u64,
+    pub images_per_second: f64,
+    pub bytes_per_second: f64,          // source bytes per second
+    pub eta_ms: Option<u64>,            // None until the first file is finished
+    pub active_files: Vec<ActiveFile>,  // one entry per busy worker
 }
 
 impl ProgressUpdate {
-    pub fn new(current: usize, total: usize, current_file: String) -> Self {
-        let percent = if total > 0 {
-            (current as f32 / total as f32) * 100.0
-        } else {
-            0.0
-        };
-
+    /// Update sent while the source paths are still being scanned
+    pub fn scanning() -> Self {
         Self {
-            current,
-            total,
-            current_file,
-            percent,
+            current: 0,
+            total: 0,
+            current_file: String::new(),
+            percent: 0.0,
+            phase: CompressionPhase::Scanning,
+            bytes_processed: 0,
+            total_bytes: 0,
+            elapsed_ms: 0,
+            images_per_second: 0.0,
+            bytes_per_second: 0.0,
+            eta_ms: None,
+            active_files: Vec::new(),
         }
     }
 }
@@ -139,7 +882,8 @@ impl ProgressUpdate {
 pub struct PathValidation {
     pub path: String,
     pub is_valid: bool,
-    pub error: Option<String>,
+    pub error: Option<String>,                 // English message
+    pub details: Option<CompressionError>,     // stable code and parameters
 }
 
 impl PathValidation {
@@ -148,14 +892,16 @@ impl PathValidation {
             path,
             is_valid: true,
             error: None,
+            details: None,
         }
     }
 
-    pub fn invalid(path: String, error: String) -> Self {
+    pub fn invalid(path: String, error: CompressionError) -> Self {
         Self {
             path,
             is_valid: false,
-            error: Some(error),
+            error: Some(error.to_string()),
+            details: Some(error),
         }
     }
-}
\ No newline at end of file
+}
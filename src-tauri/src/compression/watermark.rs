@@ -0,0 +1,306 @@
+use std::path::Path;
+use ab_glyph::{point, Font, FontRef, Glyph, PxScale, ScaleFont};
+use image::{Rgb, Rgba, RgbaImage};
+use image::imageops::FilterType;
+
+use super::error::CompressionError;
+use super::header::open_image;
+use super::types::{DecodeLimits, RawConfig, SvgConfig, WatermarkConfig, WatermarkPosition, WatermarkSource};
+
+/// Font used for text watermarks, bundled so output looks the same on every system
+const FONT_BYTES: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+
+/// Smallest text height in pixels, so tiny images still get a legible watermark
+const MIN_TEXT_PX: f32 = 8.0;
+
+/// Watermark prepared once per batch (logo decoded, colour parsed) and applied to every image
+pub struct Watermark {
+    config: WatermarkConfig,
+    content: WatermarkContent,
+}
+
+enum WatermarkContent {
+    Text { text: String, size: f32, color: Rgb<u8> },
+    Logo { logo: RgbaImage, scale: f32 },
+}
+
+impl Watermark {
+    /// Load the logo or parse the text settings from the configuration
+    pub fn new(config: &WatermarkConfig) -> Result<Self, CompressionError> {
+        let content = match &config.source {
+            WatermarkSource::Text { text, size, color } => WatermarkContent::Text {
+                text: text.clone(),
+                size: *size,
+                color: parse_hex_color(color)?,
+            },
+            WatermarkSource::Image { path, scale } => {
+                let logo = open_image(Path::new(path), &DecodeLimits::default(), &RawConfig::default(), &SvgConfig::default())
+                    .map_err(|e| CompressionError::decode(Path::new(path), &e))?
+                    .to_rgba8();
+                WatermarkContent::Logo { logo, scale: *scale }
+            }
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            content,
+        })
+    }
+
+    /// Draw the watermark onto an image in place
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 || self.config.opacity <= 0.0 {
+            return;
+        }
+
+        // Size the stamp relative to the image so every output looks alike
+        let stamp = match &self.content {
+            WatermarkContent::Text { text, size, color } => {
+                let px = (width.min(height) as f32 * size).max(MIN_TEXT_PX);
+                render_text(text, px, *color)
+            }
+            WatermarkContent::Logo { logo, scale } => {
+                let target_width = ((width as f32 * scale).round() as u32).max(1);
+                let target_height = ((logo.height() as f32 * target_width as f32 / logo.width().max(1) as f32)
+                    .round() as u32)
+                    .max(1);
+                image::imageops::resize(logo, target_width, target_height, FilterType::Triangle)
+            }
+        };
+
+        let margin = (width.min(height) as f32 * self.config.margin).round() as i64;
+        let (stamp_w, stamp_h) = (stamp.width() as i64, stamp.height() as i64);
+        let (img_w, img_h) = (width as i64, height as i64);
+
+        match self.config.position {
+            WatermarkPosition::Tiled => {
+                // Repeat across the whole image, spaced by the margin (at least one stamp gap)
+                let step_x = stamp_w + margin.max(stamp_w / 2).max(1);
+                let step_y = stamp_h + margin.max(stamp_h / 2).max(1);
+                let mut y = margin / 2;
+                while y < img_h {
+                    let mut x = margin / 2;
+                    while x < img_w {
+                        blend(img, &stamp, x, y, self.config.opacity);
+                        x += step_x;
+                    }
+                    y += step_y;
+                }
+            }
+            position => {
+                let (x, y) = match position {
+                    WatermarkPosition::TopLeft => (margin, margin),
+                    WatermarkPosition::TopRight => (img_w - stamp_w - margin, margin),
+                    WatermarkPosition::BottomLeft => (margin, img_h - stamp_h - margin),
+                    WatermarkPosition::BottomRight => (img_w - stamp_w - margin, img_h - stamp_h - margin),
+                    _ => ((img_w - stamp_w) / 2, (img_h - stamp_h) / 2),
+                };
+                blend(img, &stamp, x, y, self.config.opacity);
+            }
+        }
+    }
+}
+
+/// Parse a "#RRGGBB" or "#RGB" colour string
+pub fn parse_hex_color(color: &str) -> Result<Rgb<u8>, CompressionError> {
+    let hex = color.trim().trim_start_matches('#');
+    let invalid = || CompressionError::invalid_setting("watermark.color", color, "#RRGGBB or #RGB");
+
+    // Checked before slicing by bytes, which would panic inside a multi-byte character
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let expanded: String = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => return Err(invalid()),
+    };
+
+    let channel = |i: usize| u8::from_str_radix(&expanded[i..i + 2], 16).map_err(|_| invalid());
+
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+/// Render a single line of text into a tightly sized RGBA stamp
+fn render_text(text: &str, px: f32, color: Rgb<u8>) -> RgbaImage {
+    let font = FontRef::try_from_slice(FONT_BYTES).expect("bundled font is valid");
+    let scaled = font.as_scaled(PxScale::from(px));
+
+    // Lay out glyphs on a single baseline
+    let mut glyphs: Vec<Glyph> = Vec::new();
+    let mut caret = 0.0f32;
+    let mut previous = None;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let mut glyph = scaled.scaled_glyph(c);
+        if let Some(prev) = previous {
+            caret += scaled.kern(prev, glyph.id);
+        }
+        glyph.position = point(caret, scaled.ascent());
+        caret += scaled.h_advance(glyph.id);
+        previous = Some(glyph.id);
+        glyphs.push(glyph);
+    }
+
+    let width = caret.ceil().max(1.0) as u32;
+    let height = (scaled.ascent() - scaled.descent()).ceil().max(1.0) as u32;
+    let mut stamp = RgbaImage::new(width, height);
+
+    for glyph in glyphs {
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let x = bounds.min.x as i32 + gx as i32;
+                let y = bounds.min.y as i32 + gy as i32;
+                if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                    let pixel = stamp.get_pixel_mut(x as u32, y as u32);
+                    let alpha = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+                    // Overlapping glyph edges keep the stronger coverage
+                    if alpha > pixel[3] {
+                        *pixel = Rgba([color[0], color[1], color[2], alpha]);
+                    }
+                }
+            });
+        }
+    }
+
+    stamp
+}
+
+/// Alpha-blend ("over") a stamp onto the image at (x, y), clipping at the image edges
+fn blend(img: &mut RgbaImage, stamp: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    let (img_w, img_h) = (img.width() as i64, img.height() as i64);
+
+    for (sx, sy, src) in stamp.enumerate_pixels() {
+        let (dx, dy) = (x + sx as i64, y + sy as i64);
+        if dx < 0 || dy < 0 || dx >= img_w || dy >= img_h {
+            continue;
+        }
+
+        let alpha = src[3] as f32 / 255.0 * opacity;
+        if alpha <= 0.0 {
+            continue;
+        }
+
+        let dst = img.get_pixel_mut(dx as u32, dy as u32);
+        let dst_alpha = dst[3] as f32 / 255.0;
+        let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+        for channel in 0..3 {
+            let blended = (src[channel] as f32 * alpha + dst[channel] as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+            dst[channel] = blended.round().clamp(0.0, 255.0) as u8;
+        }
+        dst[3] = (out_alpha * 255.0).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    /// A 10×10 red logo at a tenth of the image width, with a margin of 5 px on a 100×50 image
+    fn logo_watermark(position: WatermarkPosition, opacity: f32) -> Watermark {
+        Watermark {
+            config: WatermarkConfig {
+                source: WatermarkSource::Image { path: String::new(), scale: 0.1 },
+                position,
+                opacity,
+                margin: 0.1,
+            },
+            content: WatermarkContent::Logo { logo: RgbaImage::from_pixel(10, 10, RED), scale: 0.1 },
+        }
+    }
+
+    /// Bounding box (x0, y0, x1, y1) of the pixels that are no longer black
+    fn changed_area(img: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+        img.enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != BLACK)
+            .fold(None, |area, (x, y, _)| match area {
+                None => Some((x, y, x, y)),
+                Some((x0, y0, x1, y1)) => Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y))),
+            })
+    }
+
+    fn stamped(watermark: &Watermark) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(100, 50, BLACK);
+        watermark.apply(&mut img);
+        img
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_hex_color("#1a2B3c").unwrap(), Rgb([0x1a, 0x2b, 0x3c]));
+        assert_eq!(parse_hex_color(" #f80 ").unwrap(), Rgb([0xff, 0x88, 0x00]));
+        assert_eq!(parse_hex_color("ffffff").unwrap(), Rgb([255, 255, 255]));
+        for color in ["", "#", "#12", "#1234", "#GGGGGG", "#é12", "#12é4"] {
+            assert!(parse_hex_color(color).is_err(), "{:?} was accepted", color);
+        }
+    }
+
+    #[test]
+    fn places_the_stamp_inside_the_margin() {
+        let cases = [
+            (WatermarkPosition::TopLeft, (5, 5, 14, 14)),
+            (WatermarkPosition::TopRight, (85, 5, 94, 14)),
+            (WatermarkPosition::BottomLeft, (5, 35, 14, 44)),
+            (WatermarkPosition::BottomRight, (85, 35, 94, 44)),
+            (WatermarkPosition::Center, (45, 20, 54, 29)),
+        ];
+        for (position, area) in cases {
+            let img = stamped(&logo_watermark(position, 1.0));
+            assert_eq!(changed_area(&img), Some(area), "{:?}", position);
+            assert_eq!(img.get_pixel(area.0, area.1), &RED);
+        }
+    }
+
+    #[test]
+    fn tiles_the_whole_image() {
+        let img = stamped(&logo_watermark(WatermarkPosition::Tiled, 1.0));
+        // Stamps every 15 px, starting half a margin in
+        for (x, y) in [(2, 2), (17, 2), (92, 2), (2, 47), (92, 47)] {
+            assert_eq!(img.get_pixel(x, y), &RED, "({}, {})", x, y);
+        }
+        for (x, y) in [(13, 2), (2, 13), (1, 1)] {
+            assert_eq!(img.get_pixel(x, y), &BLACK, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn blends_by_opacity() {
+        assert_eq!(changed_area(&stamped(&logo_watermark(WatermarkPosition::Center, 0.0))), None);
+
+        let img = stamped(&logo_watermark(WatermarkPosition::Center, 0.5));
+        assert_eq!(img.get_pixel(50, 25), &Rgba([128, 0, 0, 255]));
+    }
+
+    #[test]
+    fn blends_over_transparency_and_clips_at_the_edges() {
+        let mut img = RgbaImage::new(4, 4);
+        blend(&mut img, &RgbaImage::from_pixel(3, 3, RED), -1, 2, 0.5);
+
+        // Half-opaque red where the stamp overlaps, untouched elsewhere
+        assert_eq!(img.get_pixel(0, 2), &Rgba([255, 0, 0, 128]));
+        assert_eq!(img.get_pixel(1, 3), &Rgba([255, 0, 0, 128]));
+        assert_eq!(img.get_pixel(2, 2), &Rgba([0, 0, 0, 0]));
+        assert_eq!(img.get_pixel(0, 1), &Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn renders_text_in_its_color() {
+        let config = WatermarkConfig {
+            source: WatermarkSource::Text { text: "Hi".to_string(), size: 0.2, color: "#fff".to_string() },
+            position: WatermarkPosition::BottomRight,
+            opacity: 1.0,
+            margin: 0.0,
+        };
+        let img = stamped(&Watermark::new(&config).unwrap());
+
+        let (x0, y0, x1, y1) = changed_area(&img).unwrap();
+        assert!(x0 > 50 && y0 > 25 && x1 < 100 && y1 < 50, "{:?}", (x0, y0, x1, y1));
+        assert!(img.pixels().any(|pixel| *pixel == Rgba([255, 255, 255, 255])));
+        assert!(img.pixels().all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+    }
+}
@@ -0,0 +1,527 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use image_compressor::Factor;
+use serde::{Deserialize, Serialize};
+
+use super::error::{catch_panic, CompressionError};
+use super::naming::FilenameTemplate;
+use super::pipeline::ImagePipeline;
+use super::processor::compress_single_image;
+use super::responsive::{generate_variants, ResponsiveManifest};
+use super::types::{CompressionConfig, CompressionPhase};
+
+/// Command line argument that starts the binary as a compression worker
+pub const WORKER_ARG: &str = "--compression-worker";
+
+/// Time a worker whose output closed gets to exit on its own before it is killed
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// Message from the main process to a worker, one JSON object per line on stdin
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// First message: the batch settings, answered with `Ready`
+    Init { config: Box<CompressionConfig> },
+    Compress { input: PathBuf, output: PathBuf },
+    Responsive { input: PathBuf, output_dir: PathBuf, counter: usize },
+}
+
+/// Message from a worker to the main process, one JSON object per line on stdout.
+/// Any number of `Phase` messages precede the reply to a request.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Ready,
+    Phase { phase: CompressionPhase },
+    Compressed { size: u64 },
+    Variants { manifest: ResponsiveManifest },
+    Failed { error: CompressionError },
+}
+
+/// Worker processes shared by the threads of one batch.
+///
+/// Each thread takes an idle worker (or starts a new one) for an image and hands it back
+/// afterwards. A worker that crashed, hung past the timeout or broke the protocol is
+/// killed instead, so the next image gets a fresh one.
+pub struct WorkerPool {
+    config: CompressionConfig,
+    timeout: Duration,
+    idle: Mutex<Vec<Worker>>,
+}
+
+impl WorkerPool {
+    pub fn new(config: &CompressionConfig, timeout: Duration) -> Self {
+        Self {
+            config: config.clone(),
+            timeout,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Compress one image in a worker, returning the output size
+    pub fn compress(
+        &self,
+        input: &Path,
+        output: &Path,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<u64, CompressionError> {
+        let request = Request::Compress {
+            input: input.to_path_buf(),
+            output: output.to_path_buf(),
+        };
+
+        match self.run(input, &request, on_phase)? {
+            Response::Compressed { size } => Ok(size),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Generate the responsive variants of one image in a worker
+    pub fn generate_variants(
+        &self,
+        input: &Path,
+        output_dir: &Path,
+        counter: usize,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<ResponsiveManifest, CompressionError> {
+        let request = Request::Responsive {
+            input: input.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            counter,
+        };
+
+        match self.run(input, &request, on_phase)? {
+            Response::Variants { manifest } => Ok(manifest),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    fn run(
+        &self,
+        input: &Path,
+        request: &Request,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<Response, CompressionError> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => Worker::spawn(&self.config, input, self.timeout)?,
+        };
+
+        let response = worker.request(input, request, self.timeout, on_phase);
+
+        // Healthy workers are reused, broken ones are killed when dropped here
+        if !worker.broken {
+            self.idle.lock().unwrap_or_else(|e| e.into_inner()).push(worker);
+        }
+
+        match response? {
+            Response::Failed { error } => Err(error),
+            response => Ok(response),
+        }
+    }
+}
+
+fn unexpected(response: &Response) -> CompressionError {
+    CompressionError::internal(format!("Unexpected worker response: {:?}", response))
+}
+
+/// One worker process and the responses read from its stdout
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<Response>,
+    broken: bool,
+}
+
+impl Worker {
+    /// Start a worker from the current executable and send it the batch settings.
+    /// Startup failures are reported against `input`, the image it was started for.
+    fn spawn(config: &CompressionConfig, input: &Path, timeout: Duration) -> Result<Self, CompressionError> {
+        let exe = env::current_exe()
+            .map_err(|e| CompressionError::internal(format!("Failed to locate the worker executable: {}", e)))?;
+
+        let mut command = Command::new(exe);
+        command.arg(WORKER_ARG);
+        Self::start(command, config, input, timeout)
+    }
+
+    /// Run `command` as a worker and wait for it to accept the batch settings
+    fn start(
+        mut command: Command,
+        config: &CompressionConfig,
+        input: &Path,
+        timeout: Duration,
+    ) -> Result<Self, CompressionError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| CompressionError::internal(format!("Failed to start a worker process: {}", e)))?;
+
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(CompressionError::internal("Worker process has no stdio pipes")),
+        };
+
+        // Read on a separate thread so waiting for a response can time out
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                match serde_json::from_str(&line) {
+                    Ok(response) => {
+                        if sender.send(response).is_err() {
+                            break;
+                        }
+                    }
+                    // Codecs may print to stdout themselves
+                    Err(_) => log::debug!("Ignoring worker output: {}", line),
+                }
+            }
+        });
+
+        let mut worker = Self {
+            child,
+            stdin,
+            responses,
+            broken: false,
+        };
+
+        let init = Request::Init { config: Box::new(config.clone()) };
+        match worker.request(input, &init, timeout, &|_| {})? {
+            Response::Ready => Ok(worker),
+            Response::Failed { error } => Err(error),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Send a request and wait for its reply, forwarding phase updates
+    fn request(
+        &mut self,
+        input: &Path,
+        request: &Request,
+        timeout: Duration,
+        on_phase: &dyn Fn(CompressionPhase),
+    ) -> Result<Response, CompressionError> {
+        let line = serde_json::to_string(request)
+            .map_err(|e| CompressionError::internal(format!("Failed to serialize worker request: {}", e)))?;
+
+        if writeln!(self.stdin, "{}", line).and_then(|()| self.stdin.flush()).is_err() {
+            return Err(self.crashed(input));
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.responses.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Response::Phase { phase }) => on_phase(phase),
+                Ok(response) => return Ok(response),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.broken = true;
+                    return Err(CompressionError::TimedOut {
+                        path: input.display().to_string(),
+                        seconds: timeout.as_secs(),
+                    });
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(self.crashed(input)),
+            }
+        }
+    }
+
+    /// Error for a worker that went away, with its exit status if it has one. A worker
+    /// that closed its pipes but keeps running is killed rather than waited for.
+    fn crashed(&mut self, input: &Path) -> CompressionError {
+        self.broken = true;
+
+        let deadline = Instant::now() + EXIT_GRACE;
+        let cause = loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => break format!("worker process exited ({})", status),
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    break "worker process closed its output and was killed".to_string();
+                }
+                Err(e) => break format!("worker process is unreachable: {}", e),
+            }
+        };
+
+        CompressionError::Crashed {
+            path: input.display().to_string(),
+            cause,
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Batch state of a worker process, set up by `Init`
+struct Session {
+    config: CompressionConfig,
+    pipeline: ImagePipeline,
+    template: FilenameTemplate,
+    factor: Factor,
+}
+
+impl Session {
+    fn new(config: CompressionConfig) -> Result<Self, CompressionError> {
+        let template = match &config.output_template {
+            Some(template) => FilenameTemplate::parse(template)?,
+            None => FilenameTemplate::default(),
+        };
+
+        Ok(Self {
+            pipeline: ImagePipeline::new(&config)?,
+            factor: Factor::new(config.quality, config.size_ratio),
+            template,
+            config,
+        })
+    }
+
+    /// Process one image; panics become the image's error and leave the worker running
+    fn handle(&self, request: Request, on_phase: &dyn Fn(CompressionPhase)) -> Result<Response, CompressionError> {
+        match request {
+            Request::Compress { input, output } => catch_panic(&input, || {
+                compress_single_image(&input, &output, self.factor, &self.pipeline, on_phase)
+                    .map(|size| Response::Compressed { size })
+            }),
+            Request::Responsive { input, output_dir, counter } => catch_panic(&input, || {
+                let responsive = self.config.responsive
+                    .as_ref()
+                    .ok_or_else(|| CompressionError::missing_setting("responsive"))?;
+                generate_variants(&input, &output_dir, &self.pipeline, responsive, &self.template, counter, on_phase)
+                    .map(|manifest| Response::Variants { manifest })
+            }),
+            Request::Init { .. } => Err(CompressionError::internal("Worker is already initialized")),
+        }
+    }
+}
+
+/// Main loop of a worker process: answer requests from stdin until it is closed
+pub fn serve() {
+    let output = Mutex::new(protocol_output());
+    let reply = |response: &Response| send(&output, response);
+    let mut session: Option<Session> = None;
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                reply(&Response::Failed {
+                    error: CompressionError::internal(format!("Invalid worker request: {}", e)),
+                });
+                continue;
+            }
+        };
+
+        let on_phase = |phase| reply(&Response::Phase { phase });
+
+        let response = match request {
+            Request::Init { config } => Session::new(*config).map(|new_session| {
+                session = Some(new_session);
+                Response::Ready
+            }),
+            request => match &session {
+                Some(session) => session.handle(request, &on_phase),
+                None => Err(CompressionError::internal("Worker received a request before Init")),
+            },
+        };
+
+        reply(&response.unwrap_or_else(|error| Response::Failed { error }));
+    }
+}
+
+/// Write one response line, in a single write so lines from different threads can't mix
+fn send(output: &Mutex<Box<dyn Write + Send>>, response: &Response) {
+    let Ok(mut line) = serde_json::to_string(response) else { return };
+    line.push('\n');
+    let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = output.write_all(line.as_bytes());
+    let _ = output.flush();
+}
+
+/// Channel for responses. Codecs may print to stdout themselves and would splice their
+/// output into a response, so on Unix the responses get their own copy of the stdout
+/// pipe and fd 1 is pointed at stderr.
+#[cfg(unix)]
+fn protocol_output() -> Box<dyn Write + Send> {
+    use std::fs::File;
+    use std::os::fd::FromRawFd;
+
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd >= 0 {
+            if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) >= 0 {
+                // The duplicate is a new descriptor that only this File owns
+                return Box::new(File::from_raw_fd(fd));
+            }
+            libc::close(fd);
+        }
+    }
+    Box::new(io::stdout())
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> Box<dyn Write + Send> {
+    Box::new(io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::RgbImage;
+    use serde_json::json;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn config() -> CompressionConfig {
+        serde_json::from_value(json!({
+            "source_paths": ["a.png"],
+            "output_folder": "out",
+            "quality": 80.0,
+            "size_ratio": 1.0,
+            "thread_count": 1,
+            "preserve_structure": false,
+        }))
+        .unwrap()
+    }
+
+    /// A worker running a shell script in place of the compression binary
+    #[cfg(unix)]
+    fn fake_worker(script: &str) -> Worker {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        Worker::start(command, &config(), Path::new("a.png"), TIMEOUT).unwrap()
+    }
+
+    fn compress_request() -> Request {
+        Request::Compress { input: PathBuf::from("a.png"), output: PathBuf::from("out/a.jpg") }
+    }
+
+    #[test]
+    fn uses_one_tagged_json_object_per_message() {
+        assert_eq!(
+            serde_json::to_value(compress_request()).unwrap(),
+            json!({ "type": "compress", "input": "a.png", "output": "out/a.jpg" })
+        );
+        assert_eq!(
+            serde_json::to_value(Response::Phase { phase: CompressionPhase::Encoding }).unwrap(),
+            json!({ "type": "phase", "phase": "encoding" })
+        );
+
+        let failed: Response = serde_json::from_value(json!({ "type": "failed", "error": { "code": "no_paths" } })).unwrap();
+        assert!(matches!(failed, Response::Failed { error: CompressionError::NoPaths }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forwards_phases_and_skips_stray_output() {
+        let mut worker = fake_worker(
+            r#"read init; echo '{"type":"ready"}'
+               read request; echo 'codec noise'; echo '{"type":"phase","phase":"encoding"}'
+               echo '{"type":"compressed","size":42}'; cat > /dev/null"#,
+        );
+
+        let phases = std::cell::RefCell::new(Vec::new());
+        let response = worker.request(Path::new("a.png"), &compress_request(), TIMEOUT, &|phase| phases.borrow_mut().push(phase));
+
+        assert!(matches!(response, Ok(Response::Compressed { size: 42 })));
+        assert_eq!(phases.into_inner(), [CompressionPhase::Encoding]);
+        assert!(!worker.broken);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn times_out_hung_workers() {
+        let mut worker = fake_worker(r#"read init; echo '{"type":"ready"}'; sleep 10"#);
+
+        let start = Instant::now();
+        let response = worker.request(Path::new("a.png"), &compress_request(), Duration::from_millis(200), &|_| {});
+
+        assert!(matches!(response, Err(CompressionError::TimedOut { .. })));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(worker.broken);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_crashed_workers() {
+        let mut worker = fake_worker(r#"read init; echo '{"type":"ready"}'; read request; exit 3"#);
+        match worker.request(Path::new("a.png"), &compress_request(), TIMEOUT, &|_| {}) {
+            Err(CompressionError::Crashed { path, cause }) => {
+                assert_eq!(path, "a.png");
+                assert!(cause.contains("exited") && cause.contains('3'), "{}", cause);
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+        assert!(worker.broken);
+
+        // Closed its output but kept running
+        let mut worker = fake_worker(r#"read init; echo '{"type":"ready"}'; exec >&-; sleep 10"#);
+        match worker.request(Path::new("a.png"), &compress_request(), TIMEOUT, &|_| {}) {
+            Err(CompressionError::Crashed { cause, .. }) => assert!(cause.contains("killed"), "{}", cause),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reuses_healthy_workers_and_drops_broken_ones() {
+        let pool = WorkerPool::new(&config(), Duration::from_millis(200));
+        pool.idle.lock().unwrap().push(fake_worker(
+            r#"read init; echo '{"type":"ready"}'
+               read request; echo '{"type":"failed","error":{"code":"no_images_found"}}'
+               read request; echo '{"type":"compressed","size":7}'
+               read request; sleep 10"#,
+        ));
+        let compress = || pool.compress(Path::new("a.png"), Path::new("out/a.jpg"), &|_| {});
+
+        assert_eq!(compress(), Err(CompressionError::NoImagesFound));
+        assert_eq!(pool.idle.lock().unwrap().len(), 1);
+        assert_eq!(compress(), Ok(7));
+        assert!(matches!(compress(), Err(CompressionError::TimedOut { .. })));
+        assert!(pool.idle.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sessions_answer_requests_in_the_worker() {
+        let dir = std::env::temp_dir().join(format!("worker-session-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        RgbImage::from_pixel(16, 16, image::Rgb([200, 40, 40])).save(dir.join("a.png")).unwrap();
+
+        let session = Session::new(config()).unwrap();
+        let request = Request::Compress { input: dir.join("a.png"), output: dir.join("a.jpg") };
+        let size = match session.handle(request, &|_| {}) {
+            Ok(Response::Compressed { size }) => size,
+            other => panic!("unexpected response: {:?}", other),
+        };
+        assert_eq!(fs::metadata(dir.join("a.jpg")).unwrap().len(), size);
+
+        // Responsive requests need the responsive settings
+        let request = Request::Responsive { input: dir.join("a.png"), output_dir: dir.clone(), counter: 1 };
+        assert!(matches!(session.handle(request, &|_| {}), Err(CompressionError::MissingSetting { .. })));
+        let init = Request::Init { config: Box::new(config()) };
+        assert!(matches!(session.handle(init, &|_| {}), Err(CompressionError::Internal { .. })));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
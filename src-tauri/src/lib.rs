@@ -9,14 +9,16 @@ mod commands;
 use commands::{
     file_ops::{
         select_folder, select_files, open_in_explorer, get_default_output_folder,
-        validate_paths, get_app_directories, ensure_directory_exists, check_path_exists,
+        validate_paths, select_report_path, get_app_directories, ensure_directory_exists, check_path_exists,
     },
     compress::{
-        analyze_images, compress_images, estimate_savings, cancel_compression,
-        get_default_config, get_system_info,
+        AnalysisState, JobState, analyze_images, cancel_analysis, compress_images, export_report,
+        estimate_savings, cancel_compression, clear_thumbnail_cache, get_default_config, get_system_info,
     },
 };
 
+pub use compression::worker::WORKER_ARG;
+
 // Example Tauri command
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -29,9 +31,16 @@ fn get_app_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
 
+/// Serve compression requests on stdin/stdout as a worker process (see `WORKER_ARG`)
+pub fn run_worker() {
+    compression::worker::serve();
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .manage(AnalysisState::default())
+        .manage(JobState::default())
         .setup(|app| {
             // Set up Tauri Stronghold plugin for secure storage
             // This demonstrates how to add secure encrypted storage to your app
@@ -78,14 +87,18 @@ pub fn run() {
             open_in_explorer,
             get_default_output_folder,
             validate_paths,
+            select_report_path,
             get_app_directories,
             ensure_directory_exists,
             check_path_exists,
             // Compression commands
             analyze_images,
+            cancel_analysis,
             compress_images,
+            export_report,
             estimate_savings,
             cancel_compression,
+            clear_thumbnail_cache,
             get_default_config,
             get_system_info,
         ])
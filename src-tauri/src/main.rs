@@ -2,5 +2,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+  // Isolated compression runs in worker processes started from this binary
+  if std::env::args().nth(1).as_deref() == Some(app_lib::WORKER_ARG) {
+    app_lib::run_worker();
+    return;
+  }
+
   app_lib::run();
 }
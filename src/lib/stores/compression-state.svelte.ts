@@ -5,10 +5,12 @@
 
 import type {
 	ImageInfo,
+	ImageError,
 	CompressResult,
 	ProgressUpdate,
 	AnalysisProgress,
-	CompressionConfig
+	CompressionConfig,
+	LargerOutputPolicy
 } from '$lib/types/compression';
 import { estimateCompressedSize } from '$lib/utils/format';
 import { browser } from '$app/environment';
@@ -21,6 +23,9 @@ export const compressionState = $state({
 	// File selection
 	selectedImages: [] as ImageInfo[],
 
+	// Files the last analysis could not read
+	analysisErrors: [] as ImageError[],
+
 	// Processing states
 	isAnalyzing: false,
 	isCompressing: false,
@@ -38,7 +43,16 @@ export const compressionState = $state({
 		size_ratio: 0.8,
 		output_folder: '',
 		thread_count: 4,
-		preserve_structure: false
+		preserve_structure: false,
+		// Fully decode images during analysis instead of reading headers only
+		deep_validation: false,
+		// Pick up files without an extension (e.g. saved from messaging apps)
+		accept_extensionless: false,
+		// Rewrite JPEGs that need no pixel changes without re-encoding
+		lossless_jpeg: false,
+		// Handling of outputs that are not at least min_savings_percent smaller
+		larger_output: 'keep_original' as LargerOutputPolicy,
+		min_savings_percent: 0
 	},
 
 	// UI state
@@ -157,6 +171,7 @@ export function setSortBy(sortBy: typeof compressionState.sortBy) {
  */
 export function resetState() {
 	compressionState.selectedImages = [];
+	compressionState.analysisErrors = [];
 	compressionState.isAnalyzing = false;
 	compressionState.isCompressing = false;
 	compressionState.analysisProgress = null;
@@ -190,6 +205,10 @@ export function buildConfig(): CompressionConfig {
 		quality: compressionState.settings.quality,
 		size_ratio: compressionState.settings.size_ratio,
 		thread_count: compressionState.settings.thread_count,
-		preserve_structure: compressionState.settings.preserve_structure
+		preserve_structure: compressionState.settings.preserve_structure,
+		accept_extensionless: compressionState.settings.accept_extensionless,
+		lossless_jpeg: compressionState.settings.lossless_jpeg ? {} : null,
+		larger_output: compressionState.settings.larger_output,
+		min_savings_percent: compressionState.settings.min_savings_percent
 	};
 }
@@ -21,8 +21,311 @@ export interface CompressionConfig {
 	thread_count: number;
 	/** Whether to preserve the original folder structure in output */
 	preserve_structure: boolean;
+	/**
+	 * Output filename template, e.g. `{date:%Y%m%d}_{stem}_{w}px.{ext}`.
+	 * Tokens: stem, counter, date, w, h, quality, ext, format, parent.
+	 * Defaults to `{stem}.{ext}` when omitted.
+	 */
+	output_template?: string | null;
+	/** Editing operations applied to every image, in order (before cropping) */
+	transforms?: TransformOp[];
+	/** Optional crop to a fixed aspect ratio, applied before resizing */
+	crop?: CropConfig | null;
+	/** Resampling filter used when resizing (default `triangle`) */
+	resize_filter?: ResizeFilter;
+	/** Resize in linear light so dark areas don't shift (default `false`) */
+	linear_light?: boolean;
+	/** Optional unsharp mask applied after resizing */
+	sharpen?: SharpenConfig | null;
+	/** Optional watermark drawn on every image before encoding */
+	watermark?: WatermarkConfig | null;
+	/** Generate srcset variants (one output per width and format) instead of a single JPEG */
+	responsive?: ResponsiveConfig | null;
+	/** Also compress files without an extension, judged by their contents (default: false) */
+	accept_extensionless?: boolean;
+	/** Tuned JPEG encoder settings; omit to use the default encoder */
+	jpeg?: JpegEncoderConfig | null;
+	/**
+	 * Lossless optimization for JPEG sources that need no pixel changes (only rotations,
+	 * flips or grayscale) and no tuned `jpeg` encoder. Omit or `null` to re-encode them.
+	 */
+	lossless_jpeg?: LosslessJpegConfig | null;
+	/** What to do when an output is not `min_savings_percent` smaller (default `keep_original`) */
+	larger_output?: LargerOutputPolicy;
+	/** Required savings in percent (0-99, default 0: only outputs larger than the source are rejected) */
+	min_savings_percent?: number;
+	/** Decoder limits and memory budget against huge images (defaults apply when omitted) */
+	limits?: DecodeLimits;
+	/** Decode and encode in the app process (default) or in isolated worker processes */
+	execution?: ExecutionMode;
+	/** How camera RAW files are turned into pixels (defaults apply when omitted) */
+	raw?: RawConfig;
+	/** Render size of SVG sources, or passthrough (defaults apply when omitted) */
+	svg?: SvgConfig;
 }
 
+/**
+ * Handling of outputs that are not enough smaller than their source.
+ * - `keep_original`: copy the source to the output folder instead
+ * - `skip`: write nothing
+ * - `force`: keep the output anyway
+ */
+export type LargerOutputPolicy = 'keep_original' | 'skip' | 'force';
+
+/**
+ * Where images are decoded and encoded.
+ * - `in_process`: on the app's own threads
+ * - `isolated`: in one worker process per thread; a crash or hang only fails that image
+ *   and the worker is restarted. Images running longer than `timeout_secs` (default 120)
+ *   fail with `timed_out`.
+ */
+export type ExecutionMode = { mode: 'in_process' } | { mode: 'isolated'; timeout_secs?: number };
+
+/**
+ * Limits that protect against huge images and decompression bombs.
+ * Images above `max_pixels` fail with `image_too_large` before they are decoded.
+ */
+export interface DecodeLimits {
+	/** Largest width × height accepted (default 150 000 000) */
+	max_pixels?: number;
+	/** Memory a single decoder may allocate in MiB (default 512) */
+	max_alloc_mb?: number;
+	/** Estimated memory of all images processed at once in MiB (default 2048) */
+	memory_budget_mb?: number;
+}
+
+/**
+ * How camera RAW files (CR2, NEF, ARW, DNG, ...) are turned into pixels.
+ * - `preview`: the largest embedded JPEG preview, fast; files without one are developed
+ * - `develop`: demosaic the sensor data
+ */
+export type RawMode = 'preview' | 'develop';
+
+export interface RawConfig {
+	mode?: RawMode;
+	/** Exposure correction in EV, -5 to 5 (develop mode only, default 0) */
+	exposure?: number;
+	/** Apply the white balance set in the camera (develop mode only, default true) */
+	camera_white_balance?: boolean;
+}
+
+/**
+ * Size at which SVG sources are rendered, before the usual resizing.
+ * - `dpi`: 96 renders at the intrinsic size, 192 at twice that (default 96)
+ * - `width`: a width in pixels, the height follows the aspect ratio
+ */
+export type SvgSize = { mode: 'dpi'; dpi: number } | { mode: 'width'; width: number };
+
+export interface SvgConfig {
+	size?: SvgSize;
+	/** Write minified SVGs instead of rasterizing them (default false) */
+	passthrough?: boolean;
+}
+
+/** Encoded output format */
+export type OutputFormat = 'jpeg' | 'png' | 'webp';
+
+/**
+ * Responsive image set settings. Each source produces `<name>-<width>w.<ext>`
+ * for every width and format, plus a `<name>.json` manifest.
+ */
+export interface ResponsiveConfig {
+	/** Target widths in pixels (1-16383), e.g. [320, 640, 1280, 1920] */
+	widths: number[];
+	/** Output formats, e.g. ['webp', 'jpeg'] */
+	formats: OutputFormat[];
+	/** Allow widths above the source width (otherwise they use the source width) */
+	allow_upscale?: boolean;
+	/** Also write a `<picture>` HTML snippet (`<name>.html`) */
+	emit_html?: boolean;
+}
+
+/**
+ * Manifest written for every source image in responsive mode.
+ */
+export interface ResponsiveManifest {
+	/** Source image path */
+	source: string;
+	/** Source file size in bytes */
+	original_size: number;
+	/** Source width after transforms and crop */
+	width: number;
+	/** Source height after transforms and crop */
+	height: number;
+	/** Every generated file */
+	variants: ResponsiveVariant[];
+}
+
+/**
+ * A single generated file of a responsive image set.
+ */
+export interface ResponsiveVariant {
+	/** Full output path */
+	path: string;
+	/** Output filename */
+	filename: string;
+	/** Encoded format */
+	format: OutputFormat;
+	/** MIME type, e.g. "image/webp" */
+	mime_type: string;
+	/** Width in pixels */
+	width: number;
+	/** Height in pixels */
+	height: number;
+	/** File size in bytes */
+	size: number;
+}
+
+/**
+ * A single editing operation.
+ */
+export type TransformOp =
+	/** Clockwise rotation: 90, 180 or 270 */
+	| { op: 'rotate'; degrees: 90 | 180 | 270 }
+	| { op: 'flip'; direction: 'horizontal' | 'vertical' }
+	| { op: 'grayscale' }
+	/** -255 to 255, added to every channel */
+	| { op: 'brightness'; value: number }
+	/** -100 to 100 percent */
+	| { op: 'contrast'; value: number }
+	/** 0.1 to 10, 1 = unchanged */
+	| { op: 'gamma'; value: number }
+	/** 0 to 3, 0 = grayscale, 1 = unchanged */
+	| { op: 'saturation'; value: number };
+
+/**
+ * Crop settings. The largest window with the target aspect ratio is kept,
+ * positioned by the anchor.
+ */
+export interface CropConfig {
+	/** Aspect ratio width, e.g. 4 for 4:5 */
+	aspect_width: number;
+	/** Aspect ratio height, e.g. 5 for 4:5 */
+	aspect_height: number;
+	/** Which part of the image to keep */
+	anchor: CropAnchor;
+}
+
+/**
+ * Crop anchor. `entropy` keeps the most varied region,
+ * `attention` keeps the region with the most edge detail.
+ */
+export type CropAnchor = 'center' | 'top' | 'bottom' | 'left' | 'right' | 'entropy' | 'attention';
+
+/** Resampling filter, from fastest to sharpest */
+export type ResizeFilter = 'nearest' | 'triangle' | 'catmull_rom' | 'gaussian' | 'lanczos3';
+
+/**
+ * Unsharp mask settings.
+ */
+export interface SharpenConfig {
+	/** Strength (0-5, 0.5 = +50% of the detail) */
+	amount: number;
+	/** Blur radius in pixels (0.1-10) */
+	radius: number;
+	/** Minimum difference (0-255) before a pixel is sharpened */
+	threshold: number;
+}
+
+/**
+ * JPEG encoder backend and tuning options.
+ */
+export interface JpegEncoderConfig {
+	/** Encoder library (default `mozjpeg`) */
+	backend?: JpegBackend;
+	/** Write progressive scans (default `true`) */
+	progressive?: boolean;
+	/** Trellis quantization, mozjpeg only (default `true`) */
+	trellis?: boolean;
+	/** Optimized Huffman tables, always on for progressive files (default `true`) */
+	optimize_huffman?: boolean;
+	/** Chroma subsampling (default `4:2:0`) */
+	subsampling?: ChromaSubsampling;
+	/** Quantization tables; omit to use the backend's defaults */
+	quant_tables?: QuantTables | null;
+}
+
+/** `mozjpeg` gives the smallest files; `rust` supports custom quantization tables */
+export type JpegBackend = 'mozjpeg' | 'rust';
+
+/** Resolution of the color channels relative to brightness */
+export type ChromaSubsampling = '4:4:4' | '4:2:2' | '4:2:0';
+
+/**
+ * Quantization tables. Presets are scaled by the quality setting; custom tables
+ * (64 values 1-255 each, row-major, `rust` backend only) are used as-is.
+ */
+export type QuantTables =
+	| { type: 'preset'; preset: QuantTablePreset }
+	| { type: 'custom'; luma: number[]; chroma: number[] };
+
+/** Quantization table presets available in both backends */
+export type QuantTablePreset =
+	| 'annex_k'
+	| 'flat'
+	| 'ms_ssim'
+	| 'psnr_hvs'
+	| 'klein_silverstein_carney';
+
+/**
+ * Lossless JPEG optimization: keeps the DCT data, optimizes Huffman tables and drops
+ * unneeded segments. Rotations that don't fit the 8/16 px block grid trim the edge.
+ */
+export interface LosslessJpegConfig {
+	/** Write progressive scans (default `true`) */
+	progressive?: boolean;
+	/** Metadata segments to keep (default `essential`) */
+	metadata?: JpegMetadata;
+}
+
+/** `essential` keeps EXIF and the ICC profile; `all` also keeps comments, XMP and IPTC */
+export type JpegMetadata = 'none' | 'essential' | 'all';
+
+/**
+ * Watermark settings. Sizes and margins are relative to the output image
+ * so every image in a batch gets a proportionally identical watermark.
+ */
+export interface WatermarkConfig {
+	/** Text or logo to draw */
+	source: WatermarkSource;
+	/** Placement of the watermark */
+	position: WatermarkPosition;
+	/** Opacity (0-1) */
+	opacity: number;
+	/** Distance from the edges (0-0.5, fraction of the shorter image side) */
+	margin: number;
+}
+
+/**
+ * Watermark content: rendered text (bundled font) or a PNG logo.
+ */
+export type WatermarkSource =
+	| {
+			type: 'text';
+			/** Text to render */
+			text: string;
+			/** Text height (0-1, fraction of the shorter image side) */
+			size: number;
+			/** Text color as "#RRGGBB" */
+			color: string;
+	  }
+	| {
+			type: 'image';
+			/** Path to the PNG logo */
+			path: string;
+			/** Logo width (0-1, fraction of the image width) */
+			scale: number;
+	  };
+
+/** Watermark placement */
+export type WatermarkPosition =
+	| 'top_left'
+	| 'top_right'
+	| 'bottom_left'
+	| 'bottom_right'
+	| 'center'
+	| 'tiled';
+
 /**
  * Information about a single image file.
  * Returned from image analysis before compression.
@@ -42,6 +345,12 @@ export interface ImageInfo {
 	width: number;
 	/** Image height in pixels */
 	height: number;
+	/** True if the extension is missing or names a different format than the file contents */
+	extension_mismatch: boolean;
+	/** Channel layout: "RGB", "RGBA", "Gray" or "GrayAlpha" */
+	color_type: string;
+	/** Bits per channel (e.g. 8 or 16) */
+	bit_depth: number;
 	/** Base64-encoded thumbnail data URL (optional) */
 	thumbnail?: string;
 }
@@ -57,14 +366,65 @@ export interface CompressResult {
 	successful: number;
 	/** Number of images that failed to compress */
 	failed: number;
-	/** Total bytes saved (original size - compressed size) */
+	/** Number of sources copied unchanged because their output was not smaller */
+	kept_original: number;
+	/** Number of images not written because their output was not smaller */
+	skipped: number;
+	/** Total bytes saved (original size - output size); negative when the outputs grew */
 	saved_bytes: number;
 	/** Array of errors that occurred during compression */
 	errors: ImageError[];
+	/** Outcome of every image, sorted by source path */
+	files: FileResult[];
 	/** Time taken to complete compression in milliseconds */
 	duration_ms: number;
 }
 
+/**
+ * File format of an exported batch report.
+ * - `csv`: one row per image
+ * - `json`: totals, breakdowns and every image
+ * - `html`: self-contained page for clients
+ */
+export type ReportFormat = 'csv' | 'json' | 'html';
+
+/**
+ * What happened to one image of a batch.
+ * - `skipped`: nothing was written
+ * - `kept_original`: the source was copied because compressing did not pay off
+ */
+export type FileStatus = 'compressed' | 'skipped' | 'kept_original' | 'failed';
+
+/**
+ * Outcome of one image of a batch.
+ */
+export interface FileResult {
+	/** Path of the source image */
+	source_path: string;
+	/** Path of the written file, null if nothing was written */
+	output_path: string | null;
+	/** Source size in bytes */
+	original_size: number;
+	/** Output size in bytes (0 if nothing was written) */
+	output_size: number;
+	/** Source width in pixels (0 if unknown) */
+	original_width: number;
+	/** Source height in pixels (0 if unknown) */
+	original_height: number;
+	/** Output width in pixels (0 if unknown) */
+	output_width: number;
+	/** Output height in pixels (0 if unknown) */
+	output_height: number;
+	/** Quality the output was encoded at (0-100); null if it wasn't re-encoded (lossless, kept, skipped, SVG) */
+	quality: number | null;
+	/** Time spent on this image in milliseconds */
+	duration_ms: number;
+	/** What happened to the image */
+	status: FileStatus;
+	/** Why the image failed, if it did */
+	error: CompressionError | null;
+}
+
 /**
  * Information about an error that occurred during compression.
  */
@@ -75,21 +435,139 @@ export interface ImageError {
 	filename: string;
 	/** Error message describing what went wrong */
 	error: string;
+	/** Broad category of the failure */
+	reason: FailureReason;
+	/** Typed error with a stable code, used to show a localized message */
+	details: CompressionError;
 }
 
+/**
+ * Category of a per-image failure.
+ * - `too_large`: exceeds the decoder limits
+ * - `io`: other read errors (e.g. a network share dropped)
+ * - `crashed`: a decoder or encoder panicked on this image, or its worker process died
+ * - `timed_out`: its worker process exceeded the per-image timeout
+ */
+export type FailureReason =
+	| 'not_found'
+	| 'permission_denied'
+	| 'unsupported_format'
+	| 'corrupt'
+	| 'too_large'
+	| 'io'
+	| 'crashed'
+	| 'timed_out'
+	| 'other';
+
+/**
+ * Typed error returned by the compression commands and attached to per-image failures.
+ * `code` is stable and selects the localized message; the other fields are its parameters.
+ * `cause` carries the underlying library message and is not localized.
+ */
+export type CompressionError =
+	| { code: 'file_not_found'; path: string }
+	| { code: 'not_a_file'; path: string }
+	| { code: 'permission_denied'; path: string }
+	| { code: 'unsupported_format'; path: string }
+	| { code: 'invalid_file_name'; path: string }
+	| { code: 'decode_failed'; path: string; cause: string }
+	| { code: 'image_too_large'; path: string; cause: string }
+	| { code: 'read_failed'; path: string; cause: string }
+	| { code: 'disk_full'; path: string }
+	| { code: 'write_failed'; path: string; cause: string }
+	| { code: 'encode_failed'; format: string; cause: string }
+	| { code: 'no_paths' }
+	| { code: 'no_images_found' }
+	| { code: 'missing_setting'; setting: string }
+	| { code: 'out_of_range'; setting: string; value: number; min: number; max: number }
+	| { code: 'invalid_setting'; setting: string; value: string; expected: string }
+	| { code: 'invalid_template'; template: string; cause: string }
+	| { code: 'no_finished_job' }
+	| { code: 'crashed'; path: string; cause: string }
+	| { code: 'timed_out'; path: string; seconds: number }
+	| { code: 'internal'; cause: string };
+
 /**
  * Progress update emitted during batch compression.
  * Used to update the UI with real-time progress.
  */
 export interface ProgressUpdate {
-	/** Current image being processed (1-based index) */
+	/** Number of images finished so far */
 	current: number;
 	/** Total number of images to process */
 	total: number;
-	/** Filename of the current image being processed */
+	/** Filename of the image that triggered this update */
 	current_file: string;
-	/** Progress percentage (0-100) */
+	/** Progress percentage (0-100), weighted by file size */
 	percent: number;
+	/** Phase of `current_file` ('scanning' before the batch starts) */
+	phase: CompressionPhase;
+	/** Source bytes of the finished images */
+	bytes_processed: number;
+	/** Source bytes of all images in the batch */
+	total_bytes: number;
+	/** Time since the batch started in milliseconds */
+	elapsed_ms: number;
+	/** Finished images per second */
+	images_per_second: number;
+	/** Source bytes processed per second */
+	bytes_per_second: number;
+	/** Estimated time remaining in milliseconds, null until the first image is finished */
+	eta_ms: number | null;
+	/** Images currently being processed, one per busy worker */
+	active_files: ActiveFile[];
+}
+
+/**
+ * What the batch (or one of its images) is currently doing.
+ * - `scanning`: collecting files before the batch starts
+ * - `decoding`: reading and decoding the source
+ * - `encoding`: editing stages and encoding
+ * - `writing`: writing the output file
+ */
+export type CompressionPhase = 'scanning' | 'decoding' | 'encoding' | 'writing';
+
+/**
+ * Image a worker thread is currently processing.
+ */
+export interface ActiveFile {
+	/** Worker thread index */
+	worker: number;
+	/** Filename of the image */
+	filename: string;
+	/** What the worker is doing with it */
+	phase: CompressionPhase;
+}
+
+/**
+ * Chunk of analyzed images emitted as 'analysis:batch' while an analysis runs.
+ */
+export interface AnalysisBatch {
+	/** Images analyzed since the previous batch (in completion order) */
+	images: ImageInfo[];
+}
+
+/**
+ * Totals returned by analyze_images and emitted as 'analysis:complete'.
+ * The images themselves arrive through 'analysis:batch' events.
+ */
+export interface AnalysisSummary {
+	/** Number of image files found */
+	total: number;
+	/** Number of images analyzed successfully */
+	analyzed: number;
+	/** Combined size of the analyzed images in bytes */
+	total_original_size: number;
+	/** Combined estimated size after compression in bytes */
+	total_estimated_size: number;
+	/** Number of files that could not be analyzed */
+	failed: number;
+	/** Why each failed file was skipped */
+	errors: ImageError[];
+	/** Whether the analysis was cancelled before finishing */
+	cancelled: boolean;
+	/** Time taken in milliseconds */
+	duration_ms: number;
 }
 
 /**
@@ -116,4 +594,6 @@ export interface PathValidation {
 	is_valid: boolean;
 	/** Error message if validation failed */
 	error?: string;
+	/** Typed error if validation failed */
+	details?: CompressionError;
 }
@@ -0,0 +1,87 @@
+/**
+ * Localized messages for errors returned by the compression commands.
+ */
+
+import type { CompressionError } from '$lib/types/compression';
+import * as m from '$lib/paraglide/messages';
+
+/**
+ * Check whether a value is a typed error from the compression engine.
+ *
+ * @param error - Any value thrown by a command call
+ * @returns True if the value carries a `code`
+ */
+export function isCompressionError(error: unknown): error is CompressionError {
+	return typeof error === 'object' && error !== null && typeof (error as { code?: unknown }).code === 'string';
+}
+
+/**
+ * Turn an error into a message in the current language.
+ * Typed errors are translated by their `code`; anything else is shown as is.
+ *
+ * @param error - Typed error, or any value thrown by a command call
+ * @returns Human-readable message
+ *
+ * @example
+ * localizeError({ code: 'file_not_found', path: '/a.jpg' }) // "File does not exist: /a.jpg"
+ * localizeError('Dialog closed') // "Dialog closed"
+ */
+export function localizeError(error: unknown): string {
+	if (!isCompressionError(error)) {
+		return error instanceof Error ? error.message : String(error);
+	}
+
+	switch (error.code) {
+		case 'file_not_found':
+			return m.error_file_not_found({ path: error.path });
+		case 'not_a_file':
+			return m.error_not_a_file({ path: error.path });
+		case 'permission_denied':
+			return m.error_permission_denied({ path: error.path });
+		case 'unsupported_format':
+			return m.error_unsupported_format({ path: error.path });
+		case 'invalid_file_name':
+			return m.error_invalid_file_name({ path: error.path });
+		case 'decode_failed':
+			return m.error_decode_failed({ cause: error.cause });
+		case 'image_too_large':
+			return m.error_image_too_large({ cause: error.cause });
+		case 'read_failed':
+			return m.error_read_failed({ path: error.path, cause: error.cause });
+		case 'disk_full':
+			return m.error_disk_full({ path: error.path });
+		case 'write_failed':
+			return m.error_write_failed({ path: error.path, cause: error.cause });
+		case 'encode_failed':
+			return m.error_encode_failed({ format: error.format, cause: error.cause });
+		case 'no_paths':
+			return m.error_no_paths();
+		case 'no_images_found':
+			return m.error_no_images_found();
+		case 'missing_setting':
+			return m.error_missing_setting({ setting: error.setting });
+		case 'out_of_range':
+			return m.error_out_of_range({
+				setting: error.setting,
+				value: error.value,
+				min: error.min,
+				max: error.max
+			});
+		case 'invalid_setting':
+			return m.error_invalid_setting({
+				setting: error.setting,
+				value: error.value,
+				expected: error.expected
+			});
+		case 'invalid_template':
+			return m.error_invalid_template({ template: error.template, cause: error.cause });
+		case 'no_finished_job':
+			return m.error_no_finished_job();
+		case 'crashed':
+			return m.error_crashed({ path: error.path, cause: error.cause });
+		case 'timed_out':
+			return m.error_timed_out({ path: error.path, seconds: error.seconds });
+		case 'internal':
+			return m.error_internal({ cause: error.cause });
+	}
+}
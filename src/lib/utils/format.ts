@@ -13,9 +13,11 @@
  * formatBytes(1024) // "1 KB"
  * formatBytes(1536000) // "1.46 MB"
  * formatBytes(0) // "0 Bytes"
+ * formatBytes(-2048) // "-2 KB"
  */
 export function formatBytes(bytes: number, decimals = 2): string {
 	if (bytes === 0) return '0 Bytes';
+	if (bytes < 0) return `-${formatBytes(-bytes, decimals)}`;
 
 	const k = 1024;
 	const dm = decimals < 0 ? 0 : decimals;
@@ -5,10 +5,11 @@
 
 import { invoke } from '@tauri-apps/api/core';
 import type {
+	AnalysisSummary,
 	CompressionConfig,
-	ImageInfo,
 	CompressResult,
-	PathValidation
+	PathValidation,
+	ReportFormat
 } from '$lib/types/compression';
 
 /**
@@ -43,21 +44,46 @@ export async function selectFiles(): Promise<string[]> {
 }
 
 /**
- * Analyzes the specified image files and returns metadata for each.
- * This includes size, format, dimensions, and estimated compressed size.
+ * Analyzes the specified image files (or folders).
+ * Results are streamed as 'analysis:batch' events while the analysis runs,
+ * each carrying ImageInfo objects with size, format, dimensions and estimated size.
  *
- * @param paths - Array of file paths to analyze
- * @returns Array of ImageInfo objects with metadata
- * @throws Error if analysis fails
+ * Only image headers are read unless thumbnails are generated or deep validation is requested.
+ *
+ * @param paths - Array of file or folder paths to analyze
+ * @param deepValidation - Fully decode every image to catch corrupted pixel data (slower)
+ * @param acceptExtensionless - Also analyze files without an extension if their contents are an image
+ * @returns Totals of the analysis run
+ * @throws CompressionError if the settings are invalid or no valid images were found
  *
  * @example
- * const images = await analyzeImages(['/path/to/image1.png', '/path/to/image2.jpg']);
- * images.forEach(img => {
- *   console.log(`${img.filename}: ${img.format}, ${img.original_size} bytes`);
+ * const unlisten = await listen<AnalysisBatch>('analysis:batch', (event) => {
+ *   addImages(event.payload.images);
  * });
+ * const summary = await analyzeImages(['/path/to/image1.png', '/path/to/folder']);
+ * console.log(`Analyzed ${summary.analyzed} of ${summary.total} images`);
  */
-export async function analyzeImages(paths: string[]): Promise<ImageInfo[]> {
-	return await invoke<ImageInfo[]>('analyze_images', { paths });
+export async function analyzeImages(
+	paths: string[],
+	deepValidation = false,
+	acceptExtensionless = false
+): Promise<AnalysisSummary> {
+	return await invoke<AnalysisSummary>('analyze_images', {
+		paths,
+		deepValidation,
+		acceptExtensionless
+	});
+}
+
+/**
+ * Cancels the running analysis. Images already streamed are kept,
+ * and analyzeImages resolves with `cancelled: true`.
+ *
+ * @example
+ * await cancelAnalysis();
+ */
+export async function cancelAnalysis(): Promise<void> {
+	await invoke('cancel_analysis');
 }
 
 /**
@@ -66,7 +92,7 @@ export async function analyzeImages(paths: string[]): Promise<ImageInfo[]> {
  *
  * @param config - Compression configuration
  * @returns Result object with statistics and any errors
- * @throws Error if compression fails catastrophically
+ * @throws CompressionError if compression fails catastrophically
  *
  * @example
  * const result = await compressImages({
@@ -83,6 +109,38 @@ export async function compressImages(config: CompressionConfig): Promise<Compres
 	return await invoke<CompressResult>('compress_images', { config });
 }
 
+/**
+ * Writes a report of the last finished compression job.
+ *
+ * @param format - Report format
+ * @param path - Where to write the report; defaults to the job's output folder
+ * @returns Path of the written report
+ * @throws CompressionError if no job has finished yet or the report can't be written
+ *
+ * @example
+ * const reportPath = await exportReport('html');
+ * await openInExplorer(reportPath);
+ */
+export async function exportReport(format: ReportFormat, path?: string): Promise<string> {
+	return await invoke<string>('export_report', { format, path: path ?? null });
+}
+
+/**
+ * Opens a save dialog to choose where a report is written.
+ *
+ * @param format - Report format (sets the file filter and extension)
+ * @param directory - Folder the dialog starts in
+ * @returns Chosen path, or null if the dialog was cancelled
+ * @throws Error if the dialog fails to open
+ *
+ * @example
+ * const path = await selectReportPath('csv', '/path/to/output');
+ * if (path) await exportReport('csv', path);
+ */
+export async function selectReportPath(format: ReportFormat, directory?: string): Promise<string | null> {
+	return await invoke<string | null>('select_report_path', { format, directory: directory ?? null });
+}
+
 /**
  * Opens the specified folder in the system's file explorer.
  * Works cross-platform (Windows Explorer, macOS Finder, Linux file manager).
@@ -114,8 +172,10 @@ export async function getDefaultOutputFolder(): Promise<string> {
 
 /**
  * Validates that the specified paths exist and are valid image files.
+ * Only image headers are read unless deep validation is requested.
  *
  * @param paths - Array of file paths to validate
+ * @param deep - Fully decode every image to catch corrupted pixel data (slower)
  * @returns Array of validation results
  * @throws Error if validation fails
  *
@@ -123,6 +183,6 @@ export async function getDefaultOutputFolder(): Promise<string> {
  * const validations = await validatePaths(['/path/to/file1.png', '/path/to/file2.jpg']);
  * const allValid = validations.every(v => v.is_valid);
  */
-export async function validatePaths(paths: string[]): Promise<PathValidation[]> {
-	return await invoke<PathValidation[]>('validate_paths', { paths });
+export async function validatePaths(paths: string[], deep = false): Promise<PathValidation[]> {
+	return await invoke<PathValidation[]>('validate_paths', { paths, deep });
 }